@@ -0,0 +1,147 @@
+//! Battery Monitoring for Wireless Mice
+//!
+//! Polls the battery level and charging status in a background thread and
+//! hands each reading to a callback. The UI uses the callback to update the
+//! window and the tray, and to warn when the battery runs low.
+
+use crate::device::{DeviceInfo, RazerDevice};
+use anyhow::{Context, Result};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Time between battery readings
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the poll thread checks for a stop request
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Default low-battery level (percent), used until a profile sets its own threshold
+pub const LOW_BATTERY_PERCENT: u8 = 15;
+
+/// A battery reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// Charge level in percent
+    pub level: u8,
+    /// Whether the mouse is charging
+    pub charging: bool,
+}
+
+impl BatteryStatus {
+    /// Whether the battery is at or below `threshold` percent while not charging
+    pub fn is_low(&self, threshold: u8) -> bool {
+        !self.charging && self.level <= threshold
+    }
+
+    /// Whether a warning should be shown, given the previous reading.
+    /// Only warns once when crossing the threshold, not on every poll.
+    pub fn should_warn(&self, previous: Option<BatteryStatus>, threshold: u8) -> bool {
+        self.is_low(threshold) && !previous.is_some_and(|p| p.is_low(threshold))
+    }
+}
+
+/// Battery poller - reads battery status periodically in a background thread
+pub struct BatteryPoller {
+    stop: Arc<AtomicBool>,
+    join: Option<thread::JoinHandle<()>>,
+}
+
+impl BatteryPoller {
+    /// Start polling the given device, calling `on_update` after every reading
+    pub fn start<F>(info: DeviceInfo, on_update: F) -> Result<Self>
+    where
+        F: Fn(BatteryStatus) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        let join = thread::spawn(move || {
+            if let Err(e) = run_battery_loop(info, stop_thread, on_update) {
+                warn!("Battery poller stopped: {e:#}");
+            }
+        });
+
+        Ok(Self {
+            stop,
+            join: Some(join),
+        })
+    }
+
+    /// Stop polling
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(j) = self.join.take() {
+            let _ = j.join();
+        }
+    }
+}
+
+impl Drop for BatteryPoller {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(j) = self.join.take() {
+            let _ = j.join();
+        }
+    }
+}
+
+fn run_battery_loop<F>(info: DeviceInfo, stop: Arc<AtomicBool>, on_update: F) -> Result<()>
+where
+    F: Fn(BatteryStatus),
+{
+    let mut device = RazerDevice::open(&info).context("Failed to open device for battery polling")?;
+    info!("Battery poller started for {}", info.display_name());
+
+    while !stop.load(Ordering::Relaxed) {
+        match read_status(&mut device) {
+            Ok(status) => {
+                debug!("Battery: {}% (charging: {})", status.level, status.charging);
+                on_update(status);
+            }
+            Err(e) => warn!("Failed to read battery status: {e:#}"),
+        }
+
+        // Sleep in small steps so stop requests are handled promptly
+        let next = Instant::now() + POLL_INTERVAL;
+        while Instant::now() < next && !stop.load(Ordering::Relaxed) {
+            thread::sleep(STOP_CHECK_INTERVAL);
+        }
+    }
+
+    info!("Battery poller stopped");
+    Ok(())
+}
+
+fn read_status(device: &mut RazerDevice) -> Result<BatteryStatus> {
+    Ok(BatteryStatus {
+        level: device.get_battery_level()?,
+        charging: device.get_charging_status()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_low_battery() {
+        assert!(BatteryStatus { level: 10, charging: false }.is_low(LOW_BATTERY_PERCENT));
+        assert!(!BatteryStatus { level: 10, charging: true }.is_low(LOW_BATTERY_PERCENT));
+        assert!(!BatteryStatus { level: 50, charging: false }.is_low(LOW_BATTERY_PERCENT));
+        assert!(!BatteryStatus { level: 10, charging: false }.is_low(5));
+    }
+
+    #[test]
+    fn test_warn_once_when_crossing_threshold() {
+        let high = BatteryStatus { level: 20, charging: false };
+        let low = BatteryStatus { level: 15, charging: false };
+        let lower = BatteryStatus { level: 14, charging: false };
+
+        assert!(low.should_warn(Some(high), 15));
+        assert!(low.should_warn(None, 15));
+        assert!(!lower.should_warn(Some(low), 15));
+        assert!(!high.should_warn(Some(low), 15));
+    }
+}
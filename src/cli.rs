@@ -0,0 +1,279 @@
+//! Command-line interface for scripting device settings
+//!
+//! `razerlinux <command>` talks to the mouse through the same `RazerDevice`
+//! and `ProfileManager` code as the window, prints the result on stdout and
+//! exits. Settings that need a running process (remapping, software
+//! lighting) stay with the GUI and the daemon.
+
+use crate::device::{self, DEVICE_MODE_DRIVER, DEVICE_MODE_NORMAL, RazerDevice};
+use crate::error::RazerError;
+use crate::profile::ProfileManager;
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs;
+use std::path::PathBuf;
+
+/// Subcommands, as the first command-line argument
+const SUBCOMMANDS: [&str; 8] = ["devices", "dpi", "polling-rate", "profile", "mode", "help", "--help", "-h"];
+
+pub const USAGE: &str = "\
+Usage: razerlinux <command> [--device <serial|hidraw path>]
+
+Commands:
+  devices                     List connected Razer devices
+  dpi get                     Print the current DPI (\"X\" or \"X Y\")
+  dpi set <dpi> [<dpi-y>]     Set the DPI
+  polling-rate [<hz>]         Print or set the polling rate (125, 500, 1000)
+  profile list                List saved profiles
+  profile load <name>         Apply a profile's DPI, polling rate, power and lighting
+  profile export <name> [<file>]
+                              Write a profile as TOML to a file or stdout
+  mode [driver|normal]        Print or set the device mode
+
+Without --device, the first device found is used.
+Run without a command to start the GUI, or with --daemon to run headless.
+";
+
+/// A device command-line command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Help,
+    Devices,
+    DpiGet,
+    DpiSet(u16, u16),
+    PollingRateGet,
+    PollingRateSet(u16),
+    ProfileList,
+    ProfileLoad(String),
+    ProfileExport { name: String, file: Option<PathBuf> },
+    ModeGet,
+    /// Set Driver mode (true) or Normal mode (false)
+    ModeSet(bool),
+}
+
+/// A parsed command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliArgs {
+    pub command: CliCommand,
+    /// Serial number or hidraw path of the device to use
+    pub device: Option<String>,
+}
+
+/// Whether the arguments (without the program name) start with a subcommand
+pub fn is_subcommand(args: &[String]) -> bool {
+    args.first().is_some_and(|arg| SUBCOMMANDS.contains(&arg.as_str()))
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: &[String]) -> Result<CliArgs> {
+    let mut words = Vec::new();
+    let mut device = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--device" {
+            device = Some(iter.next().context("--device needs a serial number or hidraw path")?.clone());
+        } else {
+            words.push(arg.as_str());
+        }
+    }
+
+    let number = |word: &str, what: &str| -> Result<u16> {
+        word.parse().with_context(|| format!("Invalid {}: {}", what, word))
+    };
+
+    let command = match words.as_slice() {
+        ["help" | "--help" | "-h", ..] => CliCommand::Help,
+        ["devices"] => CliCommand::Devices,
+        ["dpi", "get"] => CliCommand::DpiGet,
+        ["dpi", "set", dpi] => {
+            let dpi = number(dpi, "DPI")?;
+            CliCommand::DpiSet(dpi, dpi)
+        }
+        ["dpi", "set", x, y] => CliCommand::DpiSet(number(x, "DPI")?, number(y, "DPI")?),
+        ["polling-rate"] => CliCommand::PollingRateGet,
+        ["polling-rate", rate] => CliCommand::PollingRateSet(number(rate, "polling rate")?),
+        ["profile", "list"] => CliCommand::ProfileList,
+        ["profile", "load", name] => CliCommand::ProfileLoad(name.to_string()),
+        ["profile", "export", name] => CliCommand::ProfileExport { name: name.to_string(), file: None },
+        ["profile", "export", name, file] => CliCommand::ProfileExport {
+            name: name.to_string(),
+            file: Some(PathBuf::from(file)),
+        },
+        ["mode"] => CliCommand::ModeGet,
+        ["mode", "driver"] => CliCommand::ModeSet(true),
+        ["mode", "normal"] => CliCommand::ModeSet(false),
+        _ => bail!("Invalid command: razerlinux {}\n\n{}", args.join(" "), USAGE),
+    };
+    Ok(CliArgs { command, device })
+}
+
+/// Run a command-line command
+pub fn run(args: &[String]) -> Result<()> {
+    let CliArgs { command, device } = parse(args)?;
+
+    match command {
+        CliCommand::Help => print!("{}", USAGE),
+        CliCommand::Devices => list_devices()?,
+        CliCommand::ProfileList => {
+            for name in ProfileManager::new()?.list_profiles()? {
+                println!("{}", name);
+            }
+        }
+        CliCommand::ProfileExport { name, file } => {
+            let profile = ProfileManager::new()?.load_profile(&name)?;
+            let content = toml::to_string_pretty(&profile).context("Failed to serialize profile")?;
+            match file {
+                Some(path) => fs::write(&path, content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{}", content),
+            }
+        }
+        command => {
+            let mut dev = open_device(device.as_deref())?;
+            run_device_command(&mut dev, command)?;
+        }
+    }
+    Ok(())
+}
+
+/// Commands that talk to a device
+fn run_device_command(dev: &mut RazerDevice, command: CliCommand) -> Result<()> {
+    match command {
+        CliCommand::DpiGet => {
+            let (x, y) = dev.get_dpi().map_err(device_error)?;
+            if x == y {
+                println!("{}", x);
+            } else {
+                println!("{} {}", x, y);
+            }
+        }
+        CliCommand::DpiSet(x, y) => dev.set_dpi(x, y).map_err(device_error)?,
+        CliCommand::PollingRateGet => println!("{}", dev.get_polling_rate().map_err(device_error)?),
+        CliCommand::PollingRateSet(rate) => dev.set_polling_rate(rate).map_err(device_error)?,
+        CliCommand::ProfileLoad(name) => {
+            let profile = ProfileManager::new()?.load_profile(&name)?;
+            let serial = dev.get_serial().ok();
+            if !profile.fits_device(serial.as_deref()) {
+                bail!(
+                    "Profile '{}' is pinned to serial {}",
+                    name,
+                    profile.device_serial.as_deref().unwrap_or_default()
+                );
+            }
+            let failed = profile.apply_to_device(dev);
+            if !failed.is_empty() {
+                bail!("Failed to apply {}", failed.join(", "));
+            }
+            if profile.remap.enabled || profile.lighting.software.is_some() {
+                eprintln!("Note: remapping and software lighting only run in the GUI or with --daemon");
+            }
+        }
+        CliCommand::ModeGet => {
+            let (mode, _) = dev.get_device_mode().map_err(device_error)?;
+            match mode {
+                DEVICE_MODE_NORMAL => println!("normal"),
+                DEVICE_MODE_DRIVER => println!("driver"),
+                other => println!("unknown ({:#04x})", other),
+            }
+        }
+        CliCommand::ModeSet(true) => dev.enable_driver_mode().map_err(device_error)?,
+        CliCommand::ModeSet(false) => dev.disable_driver_mode().map_err(device_error)?,
+        CliCommand::Help
+        | CliCommand::Devices
+        | CliCommand::ProfileList
+        | CliCommand::ProfileExport { .. } => unreachable!("not a device command"),
+    }
+    Ok(())
+}
+
+/// One tab-separated line per device: hidraw path, VID:PID, serial, firmware, mode, name
+fn list_devices() -> Result<()> {
+    for info in device::find_razer_devices().map_err(device_error)? {
+        let identity = match RazerDevice::open(&info) {
+            Ok(mut dev) => dev.identity(),
+            Err(e) => {
+                eprintln!("{}: {}", info.path, e.user_message());
+                continue;
+            }
+        };
+        println!(
+            "{}\t{:04x}:{:04x}\t{}\t{}\t{}\t{}",
+            identity.hid_path,
+            info.vendor_id,
+            identity.product_id,
+            identity.serial.as_deref().unwrap_or("-"),
+            identity.firmware.as_deref().unwrap_or("-"),
+            identity.mode_name().to_lowercase(),
+            identity.name
+        );
+    }
+    Ok(())
+}
+
+/// Open the device with the given serial number or hidraw path, or the
+/// first one. The mode is left alone: a running daemon may be remapping it.
+fn open_device(selector: Option<&str>) -> Result<RazerDevice> {
+    let found = device::find_razer_devices().map_err(device_error)?;
+    let Some(selector) = selector else {
+        let info = found.first().context("No supported Razer device found")?;
+        return device::open_device(info, true).map_err(device_error);
+    };
+
+    if let Some(info) = found.iter().find(|info| info.path == selector) {
+        return device::open_device(info, true).map_err(device_error);
+    }
+    for info in &found {
+        let mut dev = device::open_device(info, true).map_err(device_error)?;
+        if dev.get_serial().is_ok_and(|serial| serial == selector) {
+            return Ok(dev);
+        }
+    }
+    bail!("No Razer device with serial number or path {}", selector)
+}
+
+/// Device errors with the hint on what to do about them
+fn device_error(e: RazerError) -> anyhow::Error {
+    anyhow!(e.user_message())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let parsed = parse(&args("dpi set 1600")).unwrap();
+        assert_eq!(parsed.command, CliCommand::DpiSet(1600, 1600));
+        assert_eq!(parsed.device, None);
+
+        let parsed = parse(&args("dpi set 800 1600 --device PM1234")).unwrap();
+        assert_eq!(parsed.command, CliCommand::DpiSet(800, 1600));
+        assert_eq!(parsed.device.as_deref(), Some("PM1234"));
+
+        assert_eq!(parse(&args("polling-rate 500")).unwrap().command, CliCommand::PollingRateSet(500));
+        assert_eq!(parse(&args("mode driver")).unwrap().command, CliCommand::ModeSet(true));
+        assert_eq!(
+            parse(&args("profile export fps fps.toml")).unwrap().command,
+            CliCommand::ProfileExport { name: "fps".into(), file: Some(PathBuf::from("fps.toml")) }
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_input() {
+        assert!(parse(&args("dpi set fast")).is_err());
+        assert!(parse(&args("dpi")).is_err());
+        assert!(parse(&args("mode turbo")).is_err());
+        assert!(parse(&args("devices --device")).is_err());
+    }
+
+    #[test]
+    fn test_is_subcommand() {
+        assert!(is_subcommand(&args("dpi get")));
+        assert!(!is_subcommand(&args("--minimized")));
+        assert!(!is_subcommand(&args("--daemon")));
+        assert!(!is_subcommand(&[]));
+    }
+}
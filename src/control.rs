@@ -0,0 +1,566 @@
+//! Control socket for scripts and window-manager keybindings
+//!
+//! The running instance (the window or `--daemon`) listens on
+//! `$XDG_RUNTIME_DIR/razerlinux-control.sock`, next to the tray helper
+//! socket. Clients write one JSON request per line and read one JSON
+//! response per line:
+//!
+//! ```text
+//! {"v":1,"id":7,"cmd":"set_dpi","dpi":1600}
+//! {"v":1,"id":7,"ok":true,"result":null}
+//! ```
+//!
+//! Every message carries the protocol version `v`; requests for another
+//! version are refused so clients notice incompatible changes. After
+//! `subscribe`, event lines (`{"v":1,"event":"dpi",...}`) arrive on
+//! the same connection between responses.
+//!
+//! Requests are queued in a [`RequestQueue`] for the thread that owns the
+//! device handles, which answers them through [`PendingRequest::respond`], so
+//! scripts never open a second HID handle. The D-Bus service queues its
+//! method calls the same way.
+
+use crate::macro_engine::{self, MacroManager};
+use crate::profile::Macro;
+use crate::tray_helper;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Version of the message format; bump on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How often the listener checks for new connections and the stop flag
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client waits for the main thread to answer a request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Slow clients are dropped rather than blocking event delivery
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Events queued for a subscriber before it counts as too slow and is dropped
+const EVENT_QUEUE_LEN: usize = 64;
+
+/// Get the control socket path
+pub fn socket_path() -> PathBuf {
+    tray_helper::socket_path().with_file_name("razerlinux-control.sock")
+}
+
+/// Requests, tagged by `cmd`. `device` selects a device by serial number or
+/// hidraw path; without it the selected (or first) device is used.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    /// Connected devices and their state, plus which one is selected
+    Status,
+    ListDevices,
+    SetDpi {
+        dpi: u16,
+        /// Defaults to `dpi`
+        dpi_y: Option<u16>,
+        device: Option<String>,
+    },
+    SwitchProfile {
+        profile: String,
+        device: Option<String>,
+    },
+    /// Turn remapping on or off (flips it without `enabled`)
+    ToggleRemap {
+        enabled: Option<bool>,
+        device: Option<String>,
+    },
+    /// Play one of the device's macros, by id or name
+    RunMacro {
+        #[serde(rename = "macro")]
+        target: MacroRef,
+        device: Option<String>,
+    },
+    /// Receive events on this connection from now on
+    Subscribe,
+}
+
+impl Command {
+    /// The device a request names, if it names one
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            Command::SetDpi { device, .. }
+            | Command::SwitchProfile { device, .. }
+            | Command::ToggleRemap { device, .. }
+            | Command::RunMacro { device, .. } => device.as_deref(),
+            Command::Status | Command::ListDevices | Command::Subscribe => None,
+        }
+    }
+}
+
+/// A macro, by id or by name
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MacroRef {
+    Id(u32),
+    Name(String),
+}
+
+impl MacroRef {
+    pub fn find<'a>(&self, macros: &'a MacroManager) -> Option<&'a Macro> {
+        match self {
+            MacroRef::Id(id) => macros.get_macro(*id),
+            MacroRef::Name(name) => macros.get_all_macros().into_iter().find(|m| &m.name == name),
+        }
+    }
+
+    /// Play the macro on a background thread, returning its name
+    pub fn run(&self, macros: &MacroManager) -> Result<String> {
+        let macro_data = self.find(macros).cloned().context("No such macro")?;
+        let name = macro_data.name.clone();
+        thread::spawn(move || {
+            if let Err(e) = macro_engine::execute_macro(&macro_data) {
+                warn!("Macro execution failed: {}", e);
+            }
+        });
+        Ok(name)
+    }
+}
+
+/// State of one device, as reported by `status`, `list_devices` and used to
+/// detect changes for events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceStatus {
+    /// hidraw node used for control reports
+    pub path: String,
+    pub name: String,
+    pub serial: Option<String>,
+    pub profile: Option<String>,
+    /// (x, y); None until the device's settings have been read
+    pub dpi: Option<(u16, u16)>,
+    pub polling_rate: Option<u16>,
+    /// Percent, for wireless mice whose battery is being polled
+    pub battery: Option<u8>,
+    pub charging: bool,
+    /// "normal", "driver" (while remapping) or "unknown"
+    pub mode: String,
+    pub remapping: bool,
+    /// The remapped device is unplugged and remapping resumes when it is back
+    pub remap_waiting: bool,
+}
+
+/// Reply to `status`
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub version: &'static str,
+    /// "gui" or "daemon"
+    pub mode: &'static str,
+    /// Path of the device shown in the window
+    pub selected: Option<String>,
+    pub devices: Vec<DeviceStatus>,
+}
+
+impl Status {
+    pub fn new(mode: &'static str, selected: Option<String>, devices: Vec<DeviceStatus>) -> Self {
+        Self { version: env!("CARGO_PKG_VERSION"), mode, selected, devices }
+    }
+}
+
+/// Events sent to subscribed clients when a device's state changed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Devices were plugged in or removed (hidraw paths of all devices)
+    Devices { devices: Vec<String> },
+    Dpi { device: String, dpi_x: u16, dpi_y: u16 },
+    Profile { device: String, profile: Option<String> },
+    Remap { device: String, enabled: bool, waiting: bool },
+}
+
+/// Events that turn the `old` device states into the `new` ones
+pub fn changes(old: &[DeviceStatus], new: &[DeviceStatus]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let paths = |states: &[DeviceStatus]| states.iter().map(|s| s.path.clone()).collect::<Vec<_>>();
+    if paths(old) != paths(new) {
+        events.push(Event::Devices { devices: paths(new) });
+    }
+
+    for state in new {
+        let Some(before) = old.iter().find(|s| s.path == state.path) else {
+            continue;
+        };
+        if let Some((dpi_x, dpi_y)) = state.dpi.filter(|_| state.dpi != before.dpi) {
+            events.push(Event::Dpi { device: state.path.clone(), dpi_x, dpi_y });
+        }
+        if state.profile != before.profile {
+            events.push(Event::Profile { device: state.path.clone(), profile: state.profile.clone() });
+        }
+        if (state.remapping, state.remap_waiting) != (before.remapping, before.remap_waiting) {
+            events.push(Event::Remap {
+                device: state.path.clone(),
+                enabled: state.remapping,
+                waiting: state.remap_waiting,
+            });
+        }
+    }
+    events
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    v: u32,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    id: Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn ok(id: Value, result: Value) -> Self {
+        Self { v: PROTOCOL_VERSION, id, ok: true, result: Some(result), error: None }
+    }
+
+    fn error(id: Value, error: impl Into<String>) -> Self {
+        Self { v: PROTOCOL_VERSION, id, ok: false, result: None, error: Some(error.into()) }
+    }
+}
+
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    v: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Parse a request line into its id and command, or the error response
+fn parse_request(line: &str) -> std::result::Result<(Value, Command), Response> {
+    let mut message: Value =
+        serde_json::from_str(line).map_err(|e| Response::error(Value::Null, format!("Invalid JSON: {}", e)))?;
+    let id = message.get_mut("id").map(Value::take).unwrap_or_default();
+    match message.get("v").and_then(Value::as_u64) {
+        Some(v) if v == PROTOCOL_VERSION as u64 => {}
+        Some(v) => {
+            return Err(Response::error(
+                id,
+                format!("Unsupported protocol version {} (this is version {})", v, PROTOCOL_VERSION),
+            ));
+        }
+        None => return Err(Response::error(id, "Missing protocol version \"v\"")),
+    }
+    match serde_json::from_value(message) {
+        Ok(command) => Ok((id, command)),
+        Err(e) => Err(Response::error(id, format!("Invalid request: {}", e))),
+    }
+}
+
+/// A request waiting for the main thread
+pub struct PendingRequest {
+    pub command: Command,
+    reply: Sender<std::result::Result<Value, String>>,
+}
+
+impl PendingRequest {
+    /// Answer the client; errors are sent as their message
+    pub fn respond(self, result: Result<Value>) {
+        // The client may have gone away meanwhile
+        let _ = self.reply.send(result.map_err(|e| format!("{:#}", e)));
+    }
+}
+
+/// Requests from the control socket and D-Bus, answered on the thread that
+/// owns the devices
+pub struct RequestQueue {
+    tx: Sender<PendingRequest>,
+    rx: Receiver<PendingRequest>,
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { tx, rx }
+    }
+
+    /// Handle for queueing requests from another thread
+    pub fn client(&self) -> RequestClient {
+        RequestClient(self.tx.clone())
+    }
+
+    /// Next queued request, if any
+    pub fn try_recv(&self) -> Option<PendingRequest> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Default for RequestQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Queues requests and waits for their answer
+#[derive(Clone)]
+pub struct RequestClient(Sender<PendingRequest>);
+
+impl RequestClient {
+    pub fn request(&self, command: Command) -> std::result::Result<Value, String> {
+        let (reply, replies) = mpsc::channel();
+        self.0
+            .send(PendingRequest { command, reply })
+            .map_err(|_| "RazerLinux is shutting down".to_string())?;
+        replies
+            .recv_timeout(REPLY_TIMEOUT)
+            .map_err(|_| "No reply from RazerLinux".to_string())?
+    }
+}
+
+/// Connections that subscribed to events
+type Subscribers = Arc<Mutex<Vec<Subscriber>>>;
+
+/// A connection that subscribed to events. Its own thread writes them, so
+/// publishing never waits for the client.
+struct Subscriber(SyncSender<String>);
+
+impl Subscriber {
+    fn start(writer: Arc<Mutex<UnixStream>>) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<String>(EVENT_QUEUE_LEN);
+        thread::spawn(move || {
+            for line in rx {
+                if let Err(e) = write_line(&writer, &line) {
+                    debug!("Dropping event subscriber: {}", e);
+                    break;
+                }
+            }
+        });
+        Self(tx)
+    }
+
+    /// Queue an event line; false once the client is gone or too far behind
+    fn send(&self, line: &str) -> bool {
+        self.0.try_send(line.to_string()).is_ok()
+    }
+}
+
+/// Control socket server - accepts clients on a background thread and queues
+/// their requests. Stops and removes the socket when dropped.
+pub struct ControlServer {
+    stop: Arc<AtomicBool>,
+    join: Option<thread::JoinHandle<()>>,
+    subscribers: Subscribers,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Listen on the control socket. Fails if another instance is listening.
+    pub fn start(requests: RequestClient) -> Result<Self> {
+        let path = socket_path();
+        if UnixStream::connect(&path).is_ok() {
+            bail!("Another RazerLinux instance is listening on {}", path.display());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).with_context(|| format!("Failed to bind {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        // When started through sudo/pkexec, the socket belongs to the user
+        let user = ["SUDO_UID", "PKEXEC_UID"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok()?.parse::<u32>().ok());
+        if let Some(Err(e)) = user.map(|uid| std::os::unix::fs::chown(&path, Some(uid), None)) {
+            warn!("Failed to hand the control socket to the user: {}", e);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+        let stop_thread = stop.clone();
+        let subscribers_thread = subscribers.clone();
+        let join = thread::spawn(move || accept_loop(listener, stop_thread, requests, subscribers_thread));
+
+        info!("Control socket listening on {}", path.display());
+        Ok(Self {
+            stop,
+            join: Some(join),
+            subscribers,
+            path,
+        })
+    }
+
+    /// Queue an event for every subscribed client, dropping those that went
+    /// away or fell behind. Never blocks.
+    pub fn publish(&self, event: &Event) {
+        let line = match serde_json::to_string(&EventMessage { v: PROTOCOL_VERSION, event }) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize control event: {}", e);
+                return;
+            }
+        };
+        if let Ok(mut subs) = self.subscribers.lock() {
+            subs.retain(|subscriber| subscriber.send(&line));
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.join.take() {
+            let _ = handle.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn accept_loop(listener: UnixListener, stop: Arc<AtomicBool>, requests: RequestClient, subscribers: Subscribers) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let requests = requests.clone();
+                let subscribers = subscribers.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve_client(stream, requests, subscribers) {
+                        debug!("Control client disconnected: {}", e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(e) => {
+                warn!("Control socket accept failed: {}", e);
+                thread::sleep(ACCEPT_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Answer one client's requests until it disconnects
+fn serve_client(stream: UnixStream, requests: RequestClient, subscribers: Subscribers) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    // Responses and events share the connection, so whole lines are written under a lock
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse_request(&line) {
+            Err(response) => response,
+            Ok((id, Command::Subscribe)) => {
+                if let Ok(mut subs) = subscribers.lock() {
+                    subs.push(Subscriber::start(writer.clone()));
+                }
+                Response::ok(id, Value::Null)
+            }
+            Ok((id, command)) => {
+                debug!("Control request: {:?}", command);
+                match requests.request(command) {
+                    Ok(result) => Response::ok(id, result),
+                    Err(e) => Response::error(id, e),
+                }
+            }
+        };
+        let line = serde_json::to_string(&response).map_err(std::io::Error::other)?;
+        write_line(&writer, &line)?;
+    }
+    Ok(())
+}
+
+fn write_line(writer: &Mutex<UnixStream>, line: &str) -> std::io::Result<()> {
+    let mut stream = writer.lock().map_err(|_| std::io::Error::other("poisoned lock"))?;
+    stream.write_all(line.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn state(path: &str, dpi: u16) -> DeviceStatus {
+        DeviceStatus {
+            path: path.to_string(),
+            name: "Razer Naga Trinity".to_string(),
+            serial: None,
+            profile: Some("Default".to_string()),
+            dpi: Some((dpi, dpi)),
+            polling_rate: Some(1000),
+            battery: None,
+            charging: false,
+            mode: "normal".to_string(),
+            remapping: false,
+            remap_waiting: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let (id, command) = parse_request(r#"{"v":1,"id":"a","cmd":"set_dpi","dpi":800}"#).unwrap();
+        assert_eq!(id, Value::from("a"));
+        assert_eq!(command, Command::SetDpi { dpi: 800, dpi_y: None, device: None });
+
+        let (_, command) = parse_request(r#"{"v":1,"cmd":"run_macro","macro":"Combo"}"#).unwrap();
+        assert_eq!(command, Command::RunMacro { target: MacroRef::Name("Combo".into()), device: None });
+
+        // Errors keep the id so clients can match them up
+        let response = parse_request(r#"{"v":2,"id":3,"cmd":"status"}"#).unwrap_err();
+        assert_eq!(response.id, Value::from(3));
+        assert!(!response.ok);
+        assert!(parse_request(r#"{"cmd":"status"}"#).is_err());
+        assert!(parse_request(r#"{"v":1,"cmd":"reboot"}"#).is_err());
+        assert!(parse_request("status").is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = vec![state("/dev/hidraw1", 800)];
+        assert!(changes(&old, &old).is_empty());
+
+        let mut new = vec![state("/dev/hidraw1", 1600), state("/dev/hidraw4", 800)];
+        new[0].remapping = true;
+        assert_eq!(
+            changes(&old, &new),
+            vec![
+                Event::Devices { devices: vec!["/dev/hidraw1".into(), "/dev/hidraw4".into()] },
+                Event::Dpi { device: "/dev/hidraw1".into(), dpi_x: 1600, dpi_y: 1600 },
+                Event::Remap { device: "/dev/hidraw1".into(), enabled: true, waiting: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subscriber() {
+        let (stream, client) = UnixStream::pair().unwrap();
+        let subscriber = Subscriber::start(Arc::new(Mutex::new(stream)));
+        assert!(subscriber.send("{}"));
+        let mut line = String::new();
+        BufReader::new(&client).read_line(&mut line).unwrap();
+        assert_eq!(line, "{}\n");
+
+        // Once the client is gone its writer stops and sends fail
+        drop(client);
+        let gone = (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            !subscriber.send("{}")
+        });
+        assert!(gone);
+    }
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::Profile { device: "/dev/hidraw1".into(), profile: Some("FPS".into()) };
+        let line = serde_json::to_string(&EventMessage { v: PROTOCOL_VERSION, event: &event }).unwrap();
+        assert_eq!(line, r#"{"v":1,"event":"profile","device":"/dev/hidraw1","profile":"FPS"}"#);
+    }
+}
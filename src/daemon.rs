@@ -0,0 +1,376 @@
+//! Headless daemon mode (`--daemon`)
+//!
+//! Keeps what the window would keep running - remappers, DPI button pollers,
+//! autoscroll overlays, software lighting and battery polling - without
+//! creating any window, so remaps are active from login on (see
+//! assets/razerlinux.service). Every connected device gets its startup
+//! profile from the settings, and devices plugged in later get theirs too.
+//!
+//! The daemon is controlled over IPC only: it answers the control socket and
+//! D-Bus, takes commands from the tray helper socket when a tray helper is
+//! running, and stops on SIGTERM/SIGINT, restoring Normal mode on every device.
+
+use crate::battery::BatteryStatus;
+use crate::control::{self, Command, ControlServer, DeviceStatus, RequestQueue, Status};
+use crate::dbus_service::DbusService;
+use crate::device;
+use crate::devices::{DeviceSession, DeviceSet, WorkerEvents, show_dpi_notification};
+use crate::focus::{AppProfileSwitcher, FocusWatcher, FocusedWindow};
+use crate::hidpoll::DpiStep;
+use crate::hotplug::HotplugMonitor;
+use crate::lighting::SoftwareEffect;
+use crate::protocol::{LedEffect, Rgb};
+use crate::remap::RemapState;
+use crate::settings::AppSettings;
+use crate::tray_helper::{IpcCommand, TrayClient};
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long the daemon waits for worker events before checking IPC and signals
+const LOOP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Base color for software lighting presets when the profile's hardware
+/// effect has no color (Razer green)
+const DEFAULT_PRESET_COLOR: Rgb = Rgb::new(0, 255, 0);
+
+/// Set by SIGTERM/SIGINT
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Events from worker threads, handled on the daemon thread, which owns the
+/// device handles
+enum DaemonEvent {
+    /// A DPI button was pressed on the device with this hidraw path
+    DpiStep(String, DpiStep),
+    /// The remapper of the device with this hidraw path lost its source
+    /// devices or grabbed them again
+    RemapSource(String, RemapState),
+    /// Razer devices were plugged in or removed (removed device nodes)
+    DevicesChanged(Vec<PathBuf>),
+    /// Another application got the focus
+    FocusChanged(FocusedWindow),
+    /// A battery reading of the device with this hidraw path
+    Battery(String, BatteryStatus),
+}
+
+/// Run without a window until SIGTERM/SIGINT or a Quit command
+pub fn run_daemon() -> Result<()> {
+    unsafe {
+        let handler = request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut devices = DeviceSet::new();
+    refresh_devices(&mut devices, &[], &tx);
+    if devices.is_empty() {
+        info!("No supported Razer device connected yet, waiting for one");
+    }
+
+    // Keep the monitor alive for the lifetime of the daemon
+    let hotplug_tx = tx.clone();
+    let _hotplug = HotplugMonitor::start(move |removed| {
+        let _ = hotplug_tx.send(DaemonEvent::DevicesChanged(removed));
+    })
+    .map_err(|e| warn!("Hotplug monitoring unavailable: {}", e))
+    .ok();
+
+    // Per-application profiles, when the settings have rules for them
+    let settings = AppSettings::load().unwrap_or_default();
+    let focus_tx = tx.clone();
+    let focus = (!settings.app_profiles.is_empty()).then(|| {
+        FocusWatcher::start(move |window| {
+            let _ = focus_tx.send(DaemonEvent::FocusChanged(window));
+        })
+    });
+    let mut apps = focus.as_ref().map(|_| AppProfileSwitcher::new(settings));
+
+    let mut tray = TrayClient::connect();
+    if tray.is_connected() {
+        info!("Connected to tray helper");
+    } else {
+        info!("Tray helper not available; stop the daemon with SIGTERM");
+    }
+
+    let requests = RequestQueue::new();
+    let control = ControlServer::start(requests.client())
+        .map_err(|e| warn!("Control socket unavailable: {:#}", e))
+        .ok();
+    let dbus = DbusService::start(requests.client())
+        .map_err(|e| warn!("D-Bus service unavailable: {:#}", e))
+        .ok();
+    let mut last_states = device_states(&devices);
+    if let Some(dbus) = dbus.as_ref() {
+        dbus.update(last_states.clone());
+    }
+
+    info!("RazerLinux daemon running");
+    while !STOP.load(Ordering::Relaxed) {
+        match rx.recv_timeout(LOOP_INTERVAL) {
+            Ok(event) => handle_event(&mut devices, event, apps.as_mut(), &tx),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        while let Some(request) = requests.try_recv() {
+            let result = handle_request(&mut devices, request.command.clone(), &tx);
+            request.respond(result);
+        }
+        let states = device_states(&devices);
+        if states != last_states {
+            if let Some(control) = control.as_ref() {
+                for event in control::changes(&last_states, &states) {
+                    control.publish(&event);
+                }
+            }
+            if let Some(dbus) = dbus.as_ref() {
+                dbus.update(states.clone());
+            }
+            last_states = states;
+        }
+
+        while let Some(cmd) = tray.try_recv() {
+            match cmd {
+                IpcCommand::Quit => STOP.store(true, Ordering::Relaxed),
+                IpcCommand::Lighting(preset) => set_software_lighting(&mut devices, &preset),
+                IpcCommand::ShowWindow => {
+                    info!("The daemon has no window; run razerlinux without --daemon to configure devices")
+                }
+                _ => {}
+            }
+        }
+    }
+
+    info!("RazerLinux daemon stopping, restoring Normal mode");
+    for session in devices.sessions_mut() {
+        session.stop_remapping();
+        session.lighting.take();
+    }
+    if tray.is_connected() {
+        tray.quit();
+    }
+    Ok(())
+}
+
+fn handle_event(
+    devices: &mut DeviceSet,
+    event: DaemonEvent,
+    apps: Option<&mut AppProfileSwitcher>,
+    tx: &Sender<DaemonEvent>,
+) {
+    match event {
+        DaemonEvent::DpiStep(path, step) => {
+            let Some(session) = devices.find_by_path(&path) else { return };
+            // Read on every step, so turning notifications off in the window applies right away
+            if let Some(text) = session.cycle_dpi_stage(step)
+                && AppSettings::load().is_ok_and(|settings| settings.show_dpi_notifications)
+            {
+                show_dpi_notification(&text);
+            }
+        }
+        DaemonEvent::RemapSource(path, state) => {
+            let Some(session) = devices.find_by_path(&path) else { return };
+            match state {
+                RemapState::Waiting => {
+                    info!("{} disconnected, remapping resumes when it is back", session.identity.name)
+                }
+                RemapState::Active => info!("{} is back, remapping resumed", session.identity.name),
+            }
+        }
+        DaemonEvent::DevicesChanged(removed) => refresh_devices(devices, &removed, tx),
+        DaemonEvent::Battery(path, status) => {
+            let Some(session) = devices.find_by_path(&path) else { return };
+            if session.set_battery_status(status) {
+                warn!("{}: battery low ({}%)", session.identity.name, status.level);
+            }
+        }
+        DaemonEvent::FocusChanged(window) => {
+            let Some(apps) = apps else { return };
+            // The rules and startup profiles may have been edited since
+            match AppSettings::load() {
+                Ok(settings) => apps.set_settings(settings),
+                Err(e) => warn!("Failed to reload settings, keeping the previous ones: {}", e),
+            }
+            for session in devices.sessions_mut() {
+                let Some(profile) = apps.switch_to(&window, &session.identity.key()) else {
+                    continue;
+                };
+                if session.active_profile.as_deref() == Some(profile.as_str()) {
+                    continue;
+                }
+                info!("{} has the focus, switching {} to '{}'", window.class, session.identity.name, profile);
+                if let Err(e) = load_profile(session, &profile, tx) {
+                    warn!("Failed to load profile '{}' on {}: {:#}", profile, session.identity.name, e);
+                }
+            }
+        }
+    }
+}
+
+fn device_states(devices: &DeviceSet) -> Vec<DeviceStatus> {
+    devices.sessions().map(DeviceSession::status).collect()
+}
+
+/// Answer a control socket request
+fn handle_request(devices: &mut DeviceSet, command: Command, tx: &Sender<DaemonEvent>) -> Result<Value> {
+    match command {
+        Command::Status => return Ok(serde_json::to_value(Status::new("daemon", None, device_states(devices)))?),
+        Command::ListDevices => return Ok(serde_json::to_value(device_states(devices))?),
+        // Answered by the server
+        Command::Subscribe => return Ok(Value::Null),
+        _ => {}
+    }
+
+    let index = devices.resolve(command.device()).context("No such device")?;
+    let session = devices.get_mut(index).context("No such device")?;
+    match command {
+        Command::SetDpi { dpi, dpi_y, .. } => {
+            let dpi_y = dpi_y.unwrap_or(dpi);
+            let dev = session.device.as_mut().context("Device is not open")?;
+            dev.set_dpi(dpi, dpi_y).map_err(|e| anyhow!(e.user_message()))?;
+            if let Some(ui) = session.ui.as_mut() {
+                ui.dpi = (dpi, dpi_y);
+            }
+            if let Some(engine) = session.lighting.as_ref() {
+                engine.set_dpi(dpi);
+            }
+            Ok(Value::Null)
+        }
+        Command::SwitchProfile { profile, .. } => {
+            load_profile(session, &profile, tx)?;
+            Ok(Value::Null)
+        }
+        Command::ToggleRemap { enabled, .. } => {
+            let enabled = enabled.unwrap_or(session.remapper.is_none());
+            if !enabled {
+                session.stop_remapping();
+            } else {
+                let cycle_stages = session.ui.as_ref().is_none_or(|ui| ui.dpi_buttons_cycle);
+                let events = worker_events(tx, &session.info.path);
+                session
+                    .start_remapping(cycle_stages, events)
+                    .with_context(|| format!("Failed to start remapping on {}", session.identity.name))?;
+            }
+            Ok(json!({ "enabled": enabled }))
+        }
+        Command::RunMacro { target, .. } => Ok(json!({ "name": target.run(&session.macros)? })),
+        Command::Status | Command::ListDevices | Command::Subscribe => Ok(Value::Null),
+    }
+}
+
+/// Open newly connected devices with their startup profile, and forget
+/// unplugged ones (dropping a session stops its workers). A replugged device
+/// gets the profile back it had before.
+fn refresh_devices(devices: &mut DeviceSet, gone: &[PathBuf], tx: &Sender<DaemonEvent>) {
+    let found = match device::find_razer_devices() {
+        Ok(found) => found,
+        Err(e) => {
+            error!("Error scanning for devices: {}", e);
+            return;
+        }
+    };
+    let (new, _) = devices.retain_found(&found, gone);
+    if new.is_empty() {
+        return;
+    }
+
+    let settings = AppSettings::load().unwrap_or_else(|e| {
+        warn!("Failed to load settings, using defaults: {}", e);
+        AppSettings::default()
+    });
+    for info in new {
+        let mut dev = match device::open_device(&info, false) {
+            Ok(dev) => dev,
+            Err(e) => {
+                error!("Failed to open {}: {}", info.display_name(), e);
+                continue;
+            }
+        };
+        let identity = dev.identity();
+        let key = identity.key();
+        let mut session = match devices.take_unplugged(&key) {
+            Some(mut session) => {
+                let events = worker_events(tx, &info.path);
+                session.reattach(info, identity, dev, events);
+                session
+            }
+            None => {
+                let mut session = DeviceSession::new(info, identity);
+                session.device = Some(dev);
+                session
+            }
+        };
+        let battery_tx = tx.clone();
+        let battery_path = session.info.path.clone();
+        session.start_battery_poller(move |status| {
+            let _ = battery_tx.send(DaemonEvent::Battery(battery_path.clone(), status));
+        });
+
+        let profile_name = devices
+            .take_last_profile(&key)
+            .unwrap_or_else(|| settings.profile_for_device(&key).to_string());
+        if profile_name.is_empty() {
+            devices.push(session);
+            continue;
+        }
+        if let Err(e) = load_profile(&mut session, &profile_name, tx) {
+            warn!("Failed to load profile '{}' on {}: {:#}", profile_name, session.identity.name, e);
+        }
+        devices.push(session);
+    }
+}
+
+/// Load a profile on a device, logging the settings that failed to apply
+fn load_profile(session: &mut DeviceSession, name: &str, tx: &Sender<DaemonEvent>) -> Result<()> {
+    let failed = session.load_profile(name, worker_events(tx, &session.info.path))?;
+    if !failed.is_empty() {
+        warn!("{}: failed to apply {}", session.identity.name, failed.join(", "));
+    }
+    Ok(())
+}
+
+/// Worker events of a device, sent to the daemon thread, which owns the
+/// device handles
+fn worker_events(tx: &Sender<DaemonEvent>, path: &str) -> WorkerEvents {
+    let step_tx = tx.clone();
+    let step_path = path.to_string();
+    let state_tx = tx.clone();
+    let state_path = path.to_string();
+    WorkerEvents {
+        on_step: Box::new(move |step| {
+            let _ = step_tx.send(DaemonEvent::DpiStep(step_path.clone(), step));
+        }),
+        on_state: Box::new(move |state| {
+            let _ = state_tx.send(DaemonEvent::RemapSource(state_path.clone(), state));
+        }),
+    }
+}
+
+/// Switch every device to a software lighting preset from the tray ("off" stops it)
+fn set_software_lighting(devices: &mut DeviceSet, preset: &str) {
+    for session in devices.sessions_mut() {
+        let color = session
+            .ui
+            .as_ref()
+            .and_then(|ui| match ui.lighting.effect {
+                Some(LedEffect::Static { color })
+                | Some(LedEffect::Breathing { color, .. })
+                | Some(LedEffect::Reactive { color, .. }) => Some(color),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_PRESET_COLOR);
+        let dpi = session.ui.as_ref().map_or(0, |ui| ui.dpi.0);
+        if let Err(e) = session.set_software_lighting(SoftwareEffect::preset(preset, color), dpi) {
+            warn!("Failed to start software lighting on {}: {}", session.identity.name, e);
+        }
+    }
+}
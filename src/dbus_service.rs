@@ -0,0 +1,384 @@
+//! D-Bus service on the session bus
+//!
+//! Desktop widgets (KDE/GNOME extensions, waybar modules) read the running
+//! instance over D-Bus. It owns `io.github.aleksandarmilacic.RazerLinux`:
+//!
+//! - `/io/github/aleksandarmilacic/RazerLinux` implements the `Manager`
+//!   interface (profile list, profile switching) and ObjectManager
+//! - every connected device gets an object below `.../devices/` with the
+//!   `Device` interface: properties for DPI, polling rate, battery and mode,
+//!   `SetDpi`/`SwitchProfile` methods and `DpiChanged`/`ProfileChanged`
+//!   signals, plus PropertiesChanged for every property
+//!
+//! The bus is served on a background thread. Properties come from the device
+//! states the main thread pushes with [`DbusService::update`]; method calls
+//! go through the control request queue like control socket requests.
+
+use crate::control::{Command, DeviceStatus, RequestClient};
+use crate::profile::ProfileManager;
+use anyhow::{Context, Result, bail};
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::SyncConnection;
+use dbus::blocking::stdintf::org_freedesktop_dbus::{PropertiesPropertiesChanged, RequestNameReply};
+use dbus::channel::{Channel, Sender as _};
+use dbus::message::{MessageType, SignalArgs};
+use dbus::{Message, MethodErr, Path};
+use dbus_crossroads::{Crossroads, IfaceToken};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const BUS_NAME: &str = "io.github.aleksandarmilacic.RazerLinux";
+pub const ROOT_PATH: &str = "/io/github/aleksandarmilacic/RazerLinux";
+pub const MANAGER_INTERFACE: &str = "io.github.aleksandarmilacic.RazerLinux.Manager";
+pub const DEVICE_INTERFACE: &str = "io.github.aleksandarmilacic.RazerLinux.Device";
+
+/// How long each read of the bus waits, bounding the stop and update latency
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Object path of a device, from its serial number (or hidraw path)
+pub fn device_object_path(status: &DeviceStatus) -> String {
+    let key = status.serial.as_deref().unwrap_or(&status.path);
+    let element: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}/devices/{}", ROOT_PATH, element)
+}
+
+/// D-Bus service - serves the session bus on a background thread. Releases
+/// the bus name when dropped.
+pub struct DbusService {
+    stop: Arc<AtomicBool>,
+    join: Option<thread::JoinHandle<()>>,
+    updates: Sender<Vec<DeviceStatus>>,
+}
+
+impl DbusService {
+    /// Own the bus name on the session bus. Fails if another instance owns it.
+    pub fn start(requests: RequestClient) -> Result<Self> {
+        let channel = Channel::get_private(dbus::channel::BusType::Session)
+            .context("Failed to connect to the session bus")?;
+        Self::start_on(channel, requests)
+    }
+
+    fn start_on(channel: Channel, requests: RequestClient) -> Result<Self> {
+        let conn = Arc::new(SyncConnection::from(channel));
+        match conn.request_name(BUS_NAME, false, false, true)? {
+            RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => {}
+            _ => bail!("{} is already owned by another instance", BUS_NAME),
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (updates, states) = mpsc::channel();
+        let stop_thread = stop.clone();
+        let join = thread::spawn(move || {
+            if let Err(e) = serve(conn, requests, states, stop_thread) {
+                warn!("D-Bus service stopped: {e:#}");
+            }
+        });
+
+        info!("D-Bus service available as {}", BUS_NAME);
+        Ok(Self {
+            stop,
+            join: Some(join),
+            updates,
+        })
+    }
+
+    /// Publish the current device states; changes are signalled to clients
+    pub fn update(&self, states: Vec<DeviceStatus>) {
+        let _ = self.updates.send(states);
+    }
+}
+
+impl Drop for DbusService {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.join.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn serve(
+    conn: Arc<SyncConnection>,
+    requests: RequestClient,
+    states: Receiver<Vec<DeviceStatus>>,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    let mut cr = Crossroads::new();
+    // Devices coming and going are announced with InterfacesAdded/Removed
+    cr.set_object_manager_support(Some(conn.clone()));
+    let manager = register_manager(&mut cr, requests.clone());
+    let device = register_device(&mut cr, requests);
+    let object_manager = cr.object_manager();
+    cr.insert(ROOT_PATH, &[manager, object_manager], ());
+
+    let mut published: BTreeMap<String, DeviceStatus> = BTreeMap::new();
+    while !stop.load(Ordering::Relaxed) {
+        if conn.channel().read_write(Some(POLL_INTERVAL)).is_err() {
+            bail!("Lost the session bus connection");
+        }
+        while let Some(message) = conn.channel().pop_message() {
+            if message.msg_type() == MessageType::MethodCall {
+                let _ = cr.handle_message(message, &*conn);
+            }
+        }
+        // Only the latest states matter
+        if let Some(latest) = states.try_iter().last() {
+            publish_states(&mut cr, &conn, device, &mut published, latest);
+        }
+        conn.channel().flush();
+    }
+
+    let _ = conn.release_name(BUS_NAME);
+    Ok(())
+}
+
+fn register_manager(cr: &mut Crossroads, requests: RequestClient) -> IfaceToken<()> {
+    cr.register(MANAGER_INTERFACE, move |b| {
+        b.method("ListProfiles", (), ("profiles",), |_, _, ()| {
+            let profiles = ProfileManager::new()
+                .and_then(|manager| manager.list_profiles())
+                .map_err(|e| MethodErr::failed(&e))?;
+            Ok((profiles,))
+        });
+        // On the device selected in the window (the first device in daemon mode)
+        b.method("SwitchProfile", ("name",), (), move |_, _, (name,): (String,)| {
+            requests
+                .request(Command::SwitchProfile { profile: name, device: None })
+                .map(|_| ())
+                .map_err(|e| MethodErr::failed(&e))
+        });
+    })
+}
+
+fn register_device(cr: &mut Crossroads, requests: RequestClient) -> IfaceToken<DeviceStatus> {
+    cr.register(DEVICE_INTERFACE, move |b| {
+        b.property("Name").emits_changed_const().get(|_, s: &mut DeviceStatus| Ok(s.name.clone()));
+        b.property("Serial")
+            .emits_changed_const()
+            .get(|_, s: &mut DeviceStatus| Ok(s.serial.clone().unwrap_or_default()));
+        b.property("Path").emits_changed_const().get(|_, s: &mut DeviceStatus| Ok(s.path.clone()));
+        b.property("Dpi").get(|_, s: &mut DeviceStatus| Ok(dpi(s)));
+        b.property("PollingRate").get(|_, s: &mut DeviceStatus| Ok(polling_rate(s)));
+        b.property("Battery").get(|_, s: &mut DeviceStatus| Ok(battery(s)));
+        b.property("Charging").get(|_, s: &mut DeviceStatus| Ok(s.charging));
+        b.property("Mode").get(|_, s: &mut DeviceStatus| Ok(s.mode.clone()));
+        b.property("Profile").get(|_, s: &mut DeviceStatus| Ok(profile(s)));
+        b.property("Remapping").get(|_, s: &mut DeviceStatus| Ok(s.remapping));
+
+        let dpi_requests = requests.clone();
+        b.method("SetDpi", ("x", "y"), (), move |_, s: &mut DeviceStatus, (x, y): (u16, u16)| {
+            let command = Command::SetDpi { dpi: x, dpi_y: Some(y), device: Some(s.path.clone()) };
+            dpi_requests.request(command).map(|_| ()).map_err(|e| MethodErr::failed(&e))
+        });
+        b.method("SwitchProfile", ("name",), (), move |_, s: &mut DeviceStatus, (name,): (String,)| {
+            let command = Command::SwitchProfile { profile: name, device: Some(s.path.clone()) };
+            requests.request(command).map(|_| ()).map_err(|e| MethodErr::failed(&e))
+        });
+
+        b.signal::<(u16, u16), _>("DpiChanged", ("x", "y"));
+        b.signal::<(String,), _>("ProfileChanged", ("name",));
+    })
+}
+
+// Property values; D-Bus has no optional values, so unknown ones are 0, -1 or ""
+
+fn dpi(s: &DeviceStatus) -> (u16, u16) {
+    s.dpi.unwrap_or_default()
+}
+
+fn polling_rate(s: &DeviceStatus) -> u16 {
+    s.polling_rate.unwrap_or_default()
+}
+
+fn battery(s: &DeviceStatus) -> i32 {
+    s.battery.map_or(-1, i32::from)
+}
+
+fn profile(s: &DeviceStatus) -> String {
+    s.profile.clone().unwrap_or_default()
+}
+
+/// Add and remove device objects and signal what changed on the others
+fn publish_states(
+    cr: &mut Crossroads,
+    conn: &SyncConnection,
+    device: IfaceToken<DeviceStatus>,
+    published: &mut BTreeMap<String, DeviceStatus>,
+    states: Vec<DeviceStatus>,
+) {
+    let mut current = BTreeMap::new();
+    for state in states {
+        let path = device_object_path(&state);
+        match published.remove(&path) {
+            Some(old) => {
+                for message in change_signals(&path, &old, &state) {
+                    let _ = conn.send(message);
+                }
+                if let Some(data) = cr.data_mut::<DeviceStatus>(&Path::from(path.clone())) {
+                    *data = state.clone();
+                }
+            }
+            None => cr.insert(path.clone(), &[device], state.clone()),
+        }
+        current.insert(path, state);
+    }
+
+    for path in published.keys() {
+        cr.remove::<DeviceStatus>(&Path::from(path.clone()));
+    }
+    *published = current;
+}
+
+/// PropertiesChanged and the DpiChanged/ProfileChanged signals for a device
+fn change_signals(path: &str, old: &DeviceStatus, new: &DeviceStatus) -> Vec<Message> {
+    let mut changed = PropMap::new();
+    let mut add = |name: &str, value: Box<dyn RefArg>| {
+        changed.insert(name.to_string(), Variant(value));
+    };
+    if dpi(old) != dpi(new) {
+        add("Dpi", Box::new(dpi(new)));
+    }
+    if old.polling_rate != new.polling_rate {
+        add("PollingRate", Box::new(polling_rate(new)));
+    }
+    if old.battery != new.battery {
+        add("Battery", Box::new(battery(new)));
+    }
+    if old.charging != new.charging {
+        add("Charging", Box::new(new.charging));
+    }
+    if old.mode != new.mode {
+        add("Mode", Box::new(new.mode.clone()));
+    }
+    if old.profile != new.profile {
+        add("Profile", Box::new(profile(new)));
+    }
+    if old.remapping != new.remapping {
+        add("Remapping", Box::new(new.remapping));
+    }
+
+    let mut messages = Vec::new();
+    let path = Path::from(path.to_string());
+    if !changed.is_empty() {
+        let signal = PropertiesPropertiesChanged {
+            interface_name: DEVICE_INTERFACE.to_string(),
+            changed_properties: changed,
+            invalidated_properties: Vec::new(),
+        };
+        messages.push(signal.to_emit_message(&path));
+    }
+    if dpi(old) != dpi(new) {
+        let (x, y) = dpi(new);
+        messages.push(Message::signal(&path, &DEVICE_INTERFACE.into(), &"DpiChanged".into()).append2(x, y));
+    }
+    if old.profile != new.profile {
+        messages.push(Message::signal(&path, &DEVICE_INTERFACE.into(), &"ProfileChanged".into()).append1(profile(new)));
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::RequestQueue;
+    use dbus::blocking::Connection;
+    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command as Process, Stdio};
+
+    fn state(dpi: u16) -> DeviceStatus {
+        DeviceStatus {
+            serial: Some("PM1234-56".to_string()),
+            ..crate::control::tests::state("/dev/hidraw3", dpi)
+        }
+    }
+
+    /// A private bus: the dbus-daemon process and its address
+    fn private_bus() -> Option<(Child, String)> {
+        let mut daemon = Process::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+        Some((daemon, address.trim().to_string()))
+    }
+
+    fn connect(address: &str) -> Channel {
+        let mut channel = Channel::open_private(address).unwrap();
+        channel.register().unwrap();
+        channel
+    }
+
+    #[test]
+    fn test_device_object_path() {
+        assert_eq!(device_object_path(&state(800)), format!("{}/devices/PM1234_56", ROOT_PATH));
+    }
+
+    #[test]
+    fn test_change_signals() {
+        assert!(change_signals("/a", &state(800), &state(800)).is_empty());
+        let signals = change_signals("/a", &state(800), &state(1600));
+        let members: Vec<_> = signals.iter().map(|m| m.member().unwrap().to_string()).collect();
+        assert_eq!(members, ["PropertiesChanged", "DpiChanged"]);
+    }
+
+    #[test]
+    fn test_service_on_private_bus() {
+        let Some((mut daemon, address)) = private_bus() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let queue = RequestQueue::new();
+        let service = DbusService::start_on(connect(&address), queue.client()).unwrap();
+        // A second instance can't take the name
+        assert!(DbusService::start_on(connect(&address), queue.client()).is_err());
+        service.update(vec![state(800)]);
+
+        let path = device_object_path(&state(800));
+        let client_address = address.clone();
+        let client = thread::spawn(move || {
+            let conn = Connection::from(connect(&client_address));
+            let proxy = conn.with_proxy(BUS_NAME, path, Duration::from_secs(5));
+            // The object appears once the service handled the update
+            let dpi = (0..50).find_map(|_| {
+                thread::sleep(Duration::from_millis(20));
+                proxy.get::<(u16, u16)>(DEVICE_INTERFACE, "Dpi").ok()
+            });
+            let mode: String = proxy.get(DEVICE_INTERFACE, "Mode").unwrap();
+            let switched: Result<(), dbus::Error> = proxy.method_call(DEVICE_INTERFACE, "SwitchProfile", ("FPS",));
+            (dpi, mode, switched.is_ok())
+        });
+
+        // Answer the method call as the main thread would
+        let request = loop {
+            if let Some(request) = queue.try_recv() {
+                break request;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(
+            request.command,
+            Command::SwitchProfile { profile: "FPS".into(), device: Some("/dev/hidraw3".into()) }
+        );
+        request.respond(Ok(serde_json::Value::Null));
+
+        let (dpi, mode, switched) = client.join().unwrap();
+        assert_eq!(dpi, Some((800, 800)));
+        assert_eq!(mode, "normal");
+        assert!(switched);
+
+        drop(service);
+        let _ = daemon.kill();
+    }
+}
@@ -1,15 +1,21 @@
 //! Device detection and communication module
 
-use crate::protocol::{Command, NOSTORE, RazerReport, VARSTORE};
-use anyhow::{Context, Result};
+use crate::models::{self, DeviceModel, Feature, LedZone};
+use crate::protocol::{
+    self, Command, LED_EFFECT_STATIC, LedEffect, NOSTORE, RazerReport, Rgb, STATUS_BUSY,
+    STATUS_SUCCESS, TRANSACTION_IDS, VARSTORE,
+};
+use crate::error::{RazerError, Result};
 use hidapi::HidApi;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Razer USB Vendor ID
 pub const RAZER_VENDOR_ID: u16 = 0x1532;
 
-/// Razer Naga Trinity Product ID
-pub const NAGA_TRINITY_PID: u16 = 0x0067;
-
 /// Device mode constants
 /// In Normal mode (0x00), side buttons don't send any input
 /// In Driver mode (0x03), side buttons send keyboard keys (1-9, 0, -, =, etc.)
@@ -25,94 +31,467 @@ pub struct DeviceInfo {
     pub manufacturer: String,
     pub product: String,
     pub interface_number: i32,
+    /// Matching entry in the device model table (None for unsupported devices)
+    pub model: Option<&'static DeviceModel>,
 }
 
-/// Find a Razer Naga Trinity device
-pub fn find_naga_trinity() -> Result<Option<DeviceInfo>> {
-    let api = HidApi::new().context("Failed to initialize HID API")?;
+impl DeviceInfo {
+    fn from_hid(device: &hidapi::DeviceInfo) -> Self {
+        Self {
+            path: device.path().to_string_lossy().to_string(),
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            manufacturer: device.manufacturer_string().unwrap_or_default().to_string(),
+            product: device.product_string().unwrap_or_default().to_string(),
+            interface_number: device.interface_number(),
+            model: models::find_model(device.product_id()),
+        }
+    }
 
-    // Debug: list all Naga Trinity interfaces
-    for device in api.device_list() {
-        if device.vendor_id() == RAZER_VENDOR_ID && device.product_id() == NAGA_TRINITY_PID {
-            tracing::debug!(
-                "Found Naga Trinity interface {}: {:?} (usage_page: {:#06x}, usage: {:#06x})",
-                device.interface_number(),
-                device.path().to_string_lossy(),
-                device.usage_page(),
-                device.usage()
-            );
+    /// Display name, preferring the model table over the USB product string
+    pub fn display_name(&self) -> String {
+        match self.model {
+            Some(model) => model.name.to_string(),
+            None => self.product.clone(),
+        }
+    }
+}
+
+/// Everything that identifies one physical device, shown in the About view.
+/// The serial number tells apart several mice of the same model.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceIdentity {
+    pub name: String,
+    /// None if the device did not report one (some wireless receivers)
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    pub product_id: u16,
+    /// hidraw node used for control reports
+    pub hid_path: String,
+    /// hidraw and evdev nodes of the same USB device
+    pub nodes: DeviceNodes,
+    /// Device mode as reported by `GetDeviceMode`
+    pub mode: Option<u8>,
+}
+
+impl DeviceIdentity {
+    /// Key telling devices apart across reconnects: the serial number, or the
+    /// hidraw path if the device has none
+    pub fn key(&self) -> String {
+        self.serial.clone().unwrap_or_else(|| self.hid_path.clone())
+    }
+
+    /// Human-readable device mode
+    pub fn mode_name(&self) -> &'static str {
+        match self.mode {
+            Some(DEVICE_MODE_NORMAL) => "Normal",
+            Some(DEVICE_MODE_DRIVER) => "Driver",
+            Some(_) => "Unknown",
+            None => "-",
+        }
+    }
+}
+
+/// USB device directory in sysfs that a hidraw or evdev node belongs to.
+/// Interfaces of one mouse share it; different mice never do.
+fn usb_device_sysfs_dir(class: &str, node: &Path) -> Option<PathBuf> {
+    let name = node.file_name()?;
+    let device = std::fs::canonicalize(Path::new("/sys/class").join(class).join(name).join("device")).ok()?;
+    // The closest ancestor with a busnum attribute is the USB device itself
+    device
+        .ancestors()
+        .find(|dir| dir.join("busnum").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Nodes named `prefix*` in `dir` that belong to the USB device `usb_dir`
+fn nodes_of_usb_device(usb_dir: &Path, class: &str, dir: &str, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut nodes: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(prefix))
+        })
+        .filter(|path| usb_device_sysfs_dir(class, path).as_deref() == Some(usb_dir))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+/// Kernel nodes of one physical device, so its remapper and DPI poller leave
+/// other Razer devices alone. Empty lists mean "not scoped": every Razer
+/// interface is used, as with a single device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceNodes {
+    /// /dev/hidraw* nodes (DPI button reports)
+    pub hidraw: Vec<PathBuf>,
+    /// /dev/input/event* nodes (grabbed for remapping)
+    pub input: Vec<PathBuf>,
+}
+
+impl DeviceNodes {
+    /// Nodes of the USB device a hidraw control node belongs to
+    pub fn for_hid_path(hid_path: &str) -> Self {
+        let Some(usb_dir) = usb_device_sysfs_dir("hidraw", Path::new(hid_path)) else {
+            return Self::default();
+        };
+        Self {
+            hidraw: nodes_of_usb_device(&usb_dir, "hidraw", "/dev", "hidraw"),
+            input: nodes_of_usb_device(&usb_dir, "input", "/dev/input", "event"),
+        }
+    }
+
+    /// Short name for logs and virtual device names, e.g. "hidraw3"
+    pub fn tag(&self) -> Option<String> {
+        let first = self.hidraw.first()?;
+        Some(first.file_name()?.to_string_lossy().into_owned())
+    }
+}
+
+/// Interface numbers to use for control reports, best first.
+/// Interface 0 is typically the control interface for older Razer mice like
+/// Naga Trinity; newer mice may use interface 2 or 3.
+const CONTROL_INTERFACES: [i32; 3] = [0, 2, 1];
+
+/// Pick one control interface per physical device from a list of interfaces.
+/// `group` names the physical device an interface belongs to; devices keep
+/// the order in which they were first seen.
+fn pick_control_interfaces<F>(interfaces: Vec<DeviceInfo>, group: F) -> Vec<DeviceInfo>
+where
+    F: Fn(&DeviceInfo) -> String,
+{
+    let mut groups: Vec<(String, Vec<DeviceInfo>)> = Vec::new();
+    for info in interfaces {
+        let key = group(&info);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, members)) => members.push(info),
+            None => groups.push((key, vec![info])),
         }
     }
 
-    // Try interfaces in order of preference for control messages
-    // Interface 0 is typically the control interface for older Razer mice like Naga Trinity
-    // Newer mice may use interface 2 or 3
-    for preferred_interface in [0, 2, 1] {
-        for device in api.device_list() {
-            if device.vendor_id() == RAZER_VENDOR_ID && device.product_id() == NAGA_TRINITY_PID {
-                if device.interface_number() == preferred_interface {
-                    return Ok(Some(DeviceInfo {
-                        path: device.path().to_string_lossy().to_string(),
-                        vendor_id: device.vendor_id(),
-                        product_id: device.product_id(),
-                        manufacturer: device.manufacturer_string().unwrap_or_default().to_string(),
-                        product: device.product_string().unwrap_or_default().to_string(),
-                        interface_number: device.interface_number(),
-                    }));
+    groups
+        .into_iter()
+        .filter_map(|(_, members)| {
+            CONTROL_INTERFACES
+                .iter()
+                .find_map(|&n| members.iter().find(|i| i.interface_number == n).cloned())
+        })
+        .collect()
+}
+
+/// Open a detected device, making sure it starts in Normal mode unless
+/// `keep_mode` is set (reopening a device that is being remapped)
+pub fn open_device(info: &DeviceInfo, keep_mode: bool) -> Result<RazerDevice> {
+    tracing::info!("Found {} at {}", info.display_name(), info.path);
+    let mut dev = RazerDevice::open(info)?;
+    tracing::info!(
+        "Device opened successfully! (transaction ID {:#04x})",
+        dev.transaction_id()
+    );
+
+    if keep_mode {
+        return Ok(dev);
+    }
+
+    // Check and log device mode
+    match dev.get_device_mode() {
+        Ok((mode, param)) => {
+            tracing::info!("Device mode: {:#04x}, param: {:#04x}", mode, param);
+            // Mode 0x00 = Normal (hardware handles buttons)
+            // Mode 0x03 = Driver mode (software handles buttons - side buttons send keyboard keys)
+            if mode == 0x00 {
+                tracing::info!("Device is in Normal mode");
+            } else if mode == 0x03 {
+                tracing::info!("Device is in Driver mode - restoring Normal mode on startup");
+                // Ensure we're in Normal mode on startup for clean state
+                if let Err(e) = dev.disable_driver_mode() {
+                    tracing::warn!("Failed to restore Normal mode: {}", e);
                 }
             }
         }
+        Err(e) => {
+            tracing::warn!("Failed to get device mode: {} (this may be normal)", e);
+        }
     }
 
-    Ok(None)
+    Ok(dev)
+}
+
+/// Find every connected Razer device listed in the device model table,
+/// one control interface per physical device
+pub fn find_razer_devices() -> Result<Vec<DeviceInfo>> {
+    let api = HidApi::new()?;
+
+    let is_supported = |device: &hidapi::DeviceInfo| {
+        device.vendor_id() == RAZER_VENDOR_ID && models::is_supported(device.product_id())
+    };
+
+    // Debug: list all supported device interfaces
+    for device in api.device_list().filter(|d| is_supported(d)) {
+        tracing::debug!(
+            "Found {:#06x} interface {}: {:?} (usage_page: {:#06x}, usage: {:#06x})",
+            device.product_id(),
+            device.interface_number(),
+            device.path().to_string_lossy(),
+            device.usage_page(),
+            device.usage()
+        );
+    }
+
+    let interfaces = api
+        .device_list()
+        .filter(|d| is_supported(d))
+        .map(DeviceInfo::from_hid)
+        .collect();
+
+    // Interfaces of one device share a USB device in sysfs; without sysfs,
+    // fall back to one device per product ID
+    Ok(pick_control_interfaces(interfaces, |info| {
+        match usb_device_sysfs_dir("hidraw", Path::new(&info.path)) {
+            Some(dir) => dir.to_string_lossy().into_owned(),
+            None => format!("{:#06x}", info.product_id),
+        }
+    }))
 }
 
 /// List all connected Razer devices
 pub fn list_razer_devices() -> Result<Vec<DeviceInfo>> {
-    let api = HidApi::new().context("Failed to initialize HID API")?;
+    let api = HidApi::new()?;
     let mut devices = Vec::new();
 
     for device in api.device_list() {
         if device.vendor_id() == RAZER_VENDOR_ID {
-            devices.push(DeviceInfo {
-                path: device.path().to_string_lossy().to_string(),
-                vendor_id: device.vendor_id(),
-                product_id: device.product_id(),
-                manufacturer: device.manufacturer_string().unwrap_or_default().to_string(),
-                product: device.product_string().unwrap_or_default().to_string(),
-                interface_number: device.interface_number(),
-            });
+            devices.push(DeviceInfo::from_hid(device));
         }
     }
 
     Ok(devices)
 }
 
+/// Turn a hidapi failure into a more specific error by checking the hidraw node:
+/// gone means the device was unplugged, unreadable means missing permissions.
+fn classify_hid_error(path: &str, error: hidapi::HidError) -> RazerError {
+    if !path.starts_with("/dev/") {
+        return RazerError::Hid(error);
+    }
+    if !Path::new(path).exists() {
+        return RazerError::Disconnected;
+    }
+    match std::fs::OpenOptions::new().read(true).write(true).open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => RazerError::PermissionDenied {
+            path: path.to_string(),
+        },
+        _ => RazerError::Hid(error),
+    }
+}
+
+/// Transaction IDs confirmed by probing, keyed by product ID.
+/// The device is reopened on every reconnect, so only probe once per process.
+static TRANSACTION_ID_CACHE: OnceLock<Mutex<HashMap<u16, u8>>> = OnceLock::new();
+
+fn transaction_id_cache() -> &'static Mutex<HashMap<u16, u8>> {
+    TRANSACTION_ID_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Serializes command/response exchanges across all device handles in the
+/// process (e.g. the UI handle and the lighting engine's handle), so one
+/// handle never reads the response to another handle's command.
+static HID_LOCK: Mutex<()> = Mutex::new(());
+
+/// Time the device needs to process a command before the first read
+const RESPONSE_DELAY: Duration = Duration::from_millis(80);
+
+/// How `send_command` waits for a busy device or skips stale replies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Delay before the first re-read; doubled after every attempt
+    pub initial_backoff: Duration,
+    /// Upper bound for the back-off delay
+    pub max_backoff: Duration,
+    /// Give up once this much time has passed since the command was sent
+    pub timeout: Duration,
+    /// How often to re-read a response whose CRC does not match (0 = fail at once)
+    pub crc_retries: u32,
+}
+
+impl RetryPolicy {
+    /// Used while probing transaction IDs, where a wrong ID never gets an answer
+    const PROBE: Self = Self {
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(50),
+        timeout: Duration::from_millis(200),
+        crc_retries: 0,
+    };
+
+    /// Back-off before re-read number `attempt` (starting at 0)
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(200),
+            timeout: Duration::from_secs(1),
+            crc_retries: 2,
+        }
+    }
+}
+
 /// Handle to an open Razer device for communication
 pub struct RazerDevice {
     handle: hidapi::HidDevice,
-    #[allow(dead_code)]
-    product_id: u16,
+    info: DeviceInfo,
+    model: &'static DeviceModel,
+    transaction_id: u8,
+    retry: RetryPolicy,
 }
 
 impl RazerDevice {
-    /// Open a Razer device by path
-    pub fn open(path: &str) -> Result<Self> {
-        let api = HidApi::new().context("Failed to initialize HID API")?;
+    /// Open a detected Razer device
+    pub fn open(info: &DeviceInfo) -> Result<Self> {
+        let model = info.model.ok_or(RazerError::UnknownDevice(info.product_id))?;
+
+        let api = HidApi::new()?;
+        let path = std::ffi::CString::new(info.path.as_str())
+            .map_err(|_| RazerError::InvalidArgument(format!("Invalid device path {:?}", info.path)))?;
         let handle = api
-            .open_path(std::ffi::CString::new(path)?.as_c_str())
-            .context("Failed to open HID device")?;
+            .open_path(path.as_c_str())
+            .map_err(|e| classify_hid_error(&info.path, e))?;
 
-        Ok(Self {
+        let mut device = Self {
             handle,
-            product_id: NAGA_TRINITY_PID,
-        })
+            info: info.clone(),
+            model,
+            transaction_id: model.transaction_id,
+            retry: RetryPolicy::default(),
+        };
+
+        let cached = transaction_id_cache()
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&model.product_id).copied());
+        match cached {
+            Some(transaction_id) => device.transaction_id = transaction_id,
+            None => {
+                if let Some(transaction_id) = device.probe_transaction_id() {
+                    if let Ok(mut cache) = transaction_id_cache().lock() {
+                        cache.insert(model.product_id, transaction_id);
+                    }
+                }
+            }
+        }
+
+        Ok(device)
+    }
+
+    /// Model table entry for this device
+    pub fn model(&self) -> &'static DeviceModel {
+        self.model
+    }
+
+    /// Detection info this device was opened from
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Transaction ID used for reports to this device
+    pub fn transaction_id(&self) -> u8 {
+        self.transaction_id
+    }
+
+    /// Change how commands wait for a busy device
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Find the transaction ID the device answers to.
+    ///
+    /// Sends a harmless GetFirmwareVersion with the model's transaction ID first,
+    /// then the other known IDs. Devices silently ignore reports with the wrong ID,
+    /// so only a response with status 0x02 (success) counts.
+    fn probe_transaction_id(&mut self) -> Option<u8> {
+        let preferred = self.model.transaction_id;
+        let candidates = std::iter::once(preferred)
+            .chain(TRANSACTION_IDS.into_iter().filter(|&id| id != preferred));
+
+        for transaction_id in candidates {
+            let report =
+                RazerReport::new_with_transaction_id(Command::GetFirmwareVersion, transaction_id);
+            match self.exchange(&report, RetryPolicy::PROBE) {
+                Ok(response) if response.status == STATUS_SUCCESS => {
+                    if transaction_id != preferred {
+                        tracing::info!(
+                            "{} answers to transaction ID {:#04x} (model table says {:#04x})",
+                            self.model.name,
+                            transaction_id,
+                            preferred
+                        );
+                    }
+                    self.transaction_id = transaction_id;
+                    return Some(transaction_id);
+                }
+                Ok(response) => tracing::debug!(
+                    "No answer to transaction ID {:#04x} (status {:#04x})",
+                    transaction_id,
+                    response.status
+                ),
+                Err(e) => tracing::debug!("Transaction ID {:#04x} failed: {}", transaction_id, e),
+            }
+        }
+
+        tracing::warn!(
+            "{} did not answer any transaction ID, using {:#04x} from the model table",
+            self.model.name,
+            preferred
+        );
+        None
+    }
+
+    /// Build a report addressed with this device's transaction ID
+    fn report(&self, command: Command) -> RazerReport {
+        RazerReport::new_with_transaction_id(command, self.transaction_id)
+    }
+
+    /// Send a command with a variable-length argument list, chained over
+    /// several reports if it does not fit in one. Returns the last response.
+    fn send_args(&mut self, command: Command, args: &[u8]) -> Result<RazerReport> {
+        let reports = RazerReport::chain(command, self.transaction_id, args)?;
+        let (last, rest) = reports.split_last().expect("a chain has at least one report");
+        for report in rest {
+            self.send_command(report)?;
+        }
+        self.send_command(last)
+    }
+
+    /// Whether the device takes extended matrix (class 0x0F) lighting commands
+    fn uses_extended_matrix(&self) -> bool {
+        self.model.matrix.is_some()
     }
 
     /// Send a command and receive a response
     fn send_command(&mut self, report: &RazerReport) -> Result<RazerReport> {
-        let mut send_data = [0u8; 90];
-        send_data.copy_from_slice(&report.to_bytes());
+        self.exchange(report, self.retry)
+    }
+
+    /// Send a command, then read responses until the device answers it.
+    ///
+    /// Busy responses and replies to other commands (stale responses left over
+    /// from an earlier exchange) are re-read with back-off until `retry.timeout`.
+    fn exchange(&mut self, report: &RazerReport, retry: RetryPolicy) -> Result<RazerReport> {
+        let send_data = report.to_bytes();
+
+        let _guard = HID_LOCK.lock().unwrap_or_else(|e| e.into_inner());
 
         // Debug: print what we're sending
         tracing::debug!("Sending (90 bytes): {:02x?}", &send_data[0..12]);
@@ -125,38 +504,74 @@ impl RazerDevice {
 
         self.handle
             .send_feature_report(&with_report_id)
-            .context("Failed to send feature report")?;
+            .map_err(|e| classify_hid_error(&self.info.path, e))?;
+        let sent = Instant::now();
 
         // Wait for device to process - Razer devices need time
-        std::thread::sleep(std::time::Duration::from_millis(80));
+        thread::sleep(RESPONSE_DELAY);
+
+        let mut attempt = 0;
+        let mut crc_retries = 0;
+        loop {
+            let resp_data = self.read_response()?;
+            let response = RazerReport::parse(&resp_data);
 
-        // Read the response as feature report
+            let busy = response.status == STATUS_BUSY;
+            if !busy && response.is_reply_to(report) {
+                match RazerReport::from_bytes(&resp_data) {
+                    Err(e @ RazerError::CrcMismatch { .. }) if crc_retries < retry.crc_retries => {
+                        tracing::warn!("{}, re-reading", e);
+                        crc_retries += 1;
+                        thread::sleep(retry.initial_backoff);
+                        continue;
+                    }
+                    result => return result,
+                }
+            }
+
+            if sent.elapsed() >= retry.timeout {
+                tracing::debug!("Giving up after {} retries (busy: {})", attempt, busy);
+                return Err(if busy {
+                    RazerError::DeviceBusy
+                } else {
+                    RazerError::Timeout {
+                        class: report.command_class,
+                        id: report.command_id,
+                    }
+                });
+            }
+
+            let reason = if busy { "device busy" } else { "stale response" };
+            let delay = retry.backoff(attempt);
+            tracing::debug!("{}, re-reading in {:?}", reason, delay);
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Read one 90-byte response feature report
+    fn read_response(&self) -> Result<[u8; 90]> {
         let mut response = [0u8; 91];
         response[0] = 0x00; // Report ID we want to read
 
         let len = self
             .handle
             .get_feature_report(&mut response)
-            .context("Failed to get feature report")?;
+            .map_err(|e| classify_hid_error(&self.info.path, e))?;
 
         tracing::debug!("Read {} bytes, response: {:02x?}", len, &response[0..12]);
 
-        // Parse response (skip report ID byte)
+        // Skip report ID byte
         let mut resp_data = [0u8; 90];
         resp_data.copy_from_slice(&response[1..91]);
-
-        // Check if we got actual data back
-        if resp_data[0] == 0x00 && resp_data[1] == 0x00 && resp_data[2] == 0x00 {
-            // Response looks empty - might need to retry or the device didn't respond
-            tracing::warn!("Device returned empty response - command may not be supported");
-        }
-
-        RazerReport::from_bytes(&resp_data)
+        Ok(resp_data)
     }
 
     /// Get the firmware version
     pub fn get_firmware_version(&mut self) -> Result<String> {
-        let report = RazerReport::new(Command::GetFirmwareVersion);
+        self.require(Feature::Firmware, "a firmware version")?;
+
+        let report = self.report(Command::GetFirmwareVersion);
         let response = self.send_command(&report)?;
 
         // Debug: print raw response
@@ -173,9 +588,38 @@ impl RazerDevice {
         Ok(format!("v{}.{}", major, minor))
     }
 
+    /// Get the serial number printed on the device
+    pub fn get_serial(&mut self) -> Result<String> {
+        self.require(Feature::Serial, "a serial number")?;
+
+        let report = self.report(Command::GetSerialNumber);
+        let response = self.send_command(&report)?;
+        protocol::parse_serial(response.args())
+    }
+
+    /// Read serial, firmware and mode, and find the device's evdev nodes.
+    /// Fields the device fails to report are left empty.
+    pub fn identity(&mut self) -> DeviceIdentity {
+        let serial = self
+            .get_serial()
+            .inspect_err(|e| tracing::warn!("Failed to read serial number: {}", e))
+            .ok();
+        DeviceIdentity {
+            name: self.info.display_name(),
+            serial,
+            firmware: self.get_firmware_version().ok(),
+            product_id: self.info.product_id,
+            hid_path: self.info.path.clone(),
+            nodes: DeviceNodes::for_hid_path(&self.info.path),
+            mode: self.get_device_mode().ok().map(|(mode, _)| mode),
+        }
+    }
+
     /// Get the current DPI setting
     pub fn get_dpi(&mut self) -> Result<(u16, u16)> {
-        let mut report = RazerReport::new(Command::GetDpi);
+        self.require(Feature::Dpi, "DPI")?;
+
+        let mut report = self.report(Command::GetDpi);
 
         // Set NOSTORE in the first argument byte (like OpenRazer does for Naga Trinity)
         report.data[0] = NOSTORE;
@@ -198,7 +642,20 @@ impl RazerDevice {
 
     /// Set the DPI
     pub fn set_dpi(&mut self, dpi_x: u16, dpi_y: u16) -> Result<()> {
-        let mut report = RazerReport::new(Command::SetDpi);
+        self.require(Feature::Dpi, "DPI")?;
+
+        if !self.model.dpi_in_range(dpi_x) || !self.model.dpi_in_range(dpi_y) {
+            return Err(RazerError::InvalidArgument(format!(
+                "DPI {}x{} out of range for {} ({}-{})",
+                dpi_x,
+                dpi_y,
+                self.model.name,
+                self.model.dpi_min,
+                self.model.dpi_max
+            )));
+        }
+
+        let mut report = self.report(Command::SetDpi);
 
         // Variable storage: VARSTORE saves to device, NOSTORE is temporary
         report.data[0] = VARSTORE;
@@ -214,9 +671,46 @@ impl RazerDevice {
         Ok(())
     }
 
+    /// Get the DPI stage list stored on the device and the zero-based active stage
+    pub fn get_dpi_stages(&mut self) -> Result<(Vec<(u16, u16)>, usize)> {
+        self.require(Feature::DpiStages, "DPI stages")?;
+
+        let mut report = self.report(Command::GetDpiStages);
+        report.data[0] = VARSTORE;
+
+        let response = self.send_command(&report)?;
+        protocol::parse_dpi_stages(response.args())
+    }
+
+    /// Store a DPI stage list on the device. The DPI buttons cycle through
+    /// these stages while the mouse is in normal (non-driver) mode.
+    pub fn set_dpi_stages(&mut self, stages: &[(u16, u16)], active: usize) -> Result<()> {
+        self.require(Feature::DpiStages, "DPI stages")?;
+
+        if let Some(&(x, y)) = stages
+            .iter()
+            .find(|&&(x, y)| !self.model.dpi_in_range(x) || !self.model.dpi_in_range(y))
+        {
+            return Err(RazerError::InvalidArgument(format!(
+                "DPI stage {}x{} out of range for {} ({}-{})",
+                x,
+                y,
+                self.model.name,
+                self.model.dpi_min,
+                self.model.dpi_max
+            )));
+        }
+
+        let args = protocol::dpi_stages_args(VARSTORE, stages, active)?;
+        self.send_args(Command::SetDpiStages, &args)?;
+        Ok(())
+    }
+
     /// Get the polling rate
     pub fn get_polling_rate(&mut self) -> Result<u16> {
-        let report = RazerReport::new(Command::GetPollingRate);
+        self.require(Feature::PollingRate, "a polling rate")?;
+
+        let report = self.report(Command::GetPollingRate);
         let response = self.send_command(&report)?;
 
         // Polling rate is returned as interval in ms, convert to Hz
@@ -228,18 +722,20 @@ impl RazerDevice {
 
     /// Set the polling rate (125, 500, or 1000 Hz)
     pub fn set_polling_rate(&mut self, rate: u16) -> Result<()> {
+        self.require(Feature::PollingRate, "a polling rate")?;
+
         let interval = match rate {
             125 => 8,  // 8ms interval
             500 => 2,  // 2ms interval
             1000 => 1, // 1ms interval
             _ => {
-                return Err(anyhow::anyhow!(
-                    "Invalid polling rate. Use 125, 500, or 1000"
+                return Err(RazerError::InvalidArgument(
+                    "Invalid polling rate. Use 125, 500, or 1000".to_string(),
                 ));
             }
         };
 
-        let mut report = RazerReport::new(Command::SetPollingRate);
+        let mut report = self.report(Command::SetPollingRate);
         report.data[0] = interval;
         report.data_size = 1;
 
@@ -247,12 +743,262 @@ impl RazerDevice {
         Ok(())
     }
 
+    /// Check that the model has an LED zone
+    fn check_led_zone(&self, zone: LedZone) -> Result<()> {
+        if self.model.led_zones.contains(&zone) {
+            Ok(())
+        } else {
+            Err(self.unsupported(format!("a {} LED", zone.name())))
+        }
+    }
+
+    /// Set the brightness (0-255) of a single LED zone
+    pub fn set_led_brightness(&mut self, zone: LedZone, brightness: u8) -> Result<()> {
+        self.check_led_zone(zone)?;
+
+        let command = if self.uses_extended_matrix() {
+            Command::SetExtendedBrightness
+        } else {
+            Command::SetLedBrightness
+        };
+        let mut report = self.report(command);
+        report.data[0] = VARSTORE;
+        report.data[1] = zone.led_id();
+        report.data[2] = brightness;
+
+        self.send_command(&report)?;
+        Ok(())
+    }
+
+    /// Get the brightness (0-255) of a single LED zone
+    pub fn get_led_brightness(&mut self, zone: LedZone) -> Result<u8> {
+        self.check_led_zone(zone)?;
+
+        let command = if self.uses_extended_matrix() {
+            Command::GetExtendedBrightness
+        } else {
+            Command::GetLedBrightness
+        };
+        let mut report = self.report(command);
+        report.data[0] = VARSTORE;
+        report.data[1] = zone.led_id();
+
+        let response = self.send_command(&report)?;
+        Ok(response.data[2])
+    }
+
+    /// Set the LED brightness (0-255) on every LED zone of the device
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<()> {
+        if self.model.led_zones.is_empty() {
+            return Err(self.unsupported("LEDs"));
+        }
+
+        for &zone in self.model.led_zones {
+            self.set_led_brightness(zone, brightness)?;
+        }
+        Ok(())
+    }
+
+    /// Turn a single LED zone on or off
+    pub fn set_led_state(&mut self, zone: LedZone, on: bool) -> Result<()> {
+        self.check_led_zone(zone)?;
+
+        let mut report = self.report(Command::SetLedState);
+        report.data[0] = VARSTORE;
+        report.data[1] = zone.led_id();
+        report.data[2] = on as u8;
+
+        self.send_command(&report)?;
+        Ok(())
+    }
+
+    /// Set a single LED zone (logo, scroll wheel, ...) to a static color
+    pub fn set_zone_color(&mut self, zone: LedZone, color: Rgb) -> Result<()> {
+        self.check_led_zone(zone)?;
+
+        if self.uses_extended_matrix() {
+            // Extended matrix devices ignore the LED class color; a static
+            // effect addressed to the one LED sets it instead
+            let args = LedEffect::Static { color }.extended_args(VARSTORE, zone.led_id());
+            self.send_args(Command::SetExtendedEffect, &args)?;
+            return Ok(());
+        }
+
+        let mut report = self.report(Command::SetLedRgb);
+        report.data[0] = VARSTORE;
+        report.data[1] = zone.led_id();
+        report.data[2] = color.r;
+        report.data[3] = color.g;
+        report.data[4] = color.b;
+        self.send_command(&report)?;
+
+        let mut report = self.report(Command::SetLedEffect);
+        report.data[0] = VARSTORE;
+        report.data[1] = zone.led_id();
+        report.data[2] = LED_EFFECT_STATIC;
+        self.send_command(&report)?;
+
+        self.set_led_state(zone, true)
+    }
+
+    /// Apply a lighting effect to the whole device
+    pub fn set_effect(&mut self, effect: &LedEffect) -> Result<()> {
+        if self.model.led_zones.is_empty() {
+            return Err(self.unsupported("LEDs"));
+        }
+
+        if self.uses_extended_matrix() {
+            // Extended matrix devices take the effect per LED zone
+            for &zone in self.model.led_zones {
+                let args = effect.extended_args(VARSTORE, zone.led_id());
+                self.send_args(Command::SetExtendedEffect, &args)?;
+            }
+        } else {
+            let args = effect.matrix_args();
+            self.send_args(Command::SetMatrixEffect, &args)?;
+        }
+
+        tracing::info!("Lighting effect set to {}", effect.name());
+        Ok(())
+    }
+
+    /// Upload one row of per-LED colors to the device's custom frame buffer.
+    ///
+    /// The frame is shown once `display_custom_frame` is called; for animations,
+    /// upload every row of a frame and then display it.
+    pub fn set_custom_frame(&mut self, row: u8, colors: &[Rgb]) -> Result<()> {
+        let (rows, cols) = self
+            .model
+            .matrix
+            .ok_or_else(|| self.unsupported("per-LED custom frames"))?;
+        if row >= rows {
+            return Err(RazerError::InvalidArgument(format!(
+                "Row {} out of range for {} ({} rows)",
+                row,
+                self.model.name,
+                rows
+            )));
+        }
+        if colors.len() > cols as usize {
+            return Err(RazerError::InvalidArgument(format!(
+                "{} colors given but {} has {} LEDs per row",
+                colors.len(),
+                self.model.name,
+                cols
+            )));
+        }
+
+        let args = protocol::custom_frame_args(row, 0, colors)?;
+        self.send_args(Command::SetCustomFrame, &args)?;
+        Ok(())
+    }
+
+    /// Switch the device to showing the uploaded custom frame
+    pub fn display_custom_frame(&mut self) -> Result<()> {
+        if !self.uses_extended_matrix() {
+            return Err(self.unsupported("per-LED custom frames"));
+        }
+
+        self.send_args(Command::SetExtendedEffect, &protocol::custom_frame_effect_args())?;
+        Ok(())
+    }
+
+    /// Fail with a clear message if the model lacks a feature
+    fn require(&self, feature: Feature, what: &str) -> Result<()> {
+        if self.model.supports(feature) {
+            Ok(())
+        } else {
+            Err(self.unsupported(what))
+        }
+    }
+
+    /// Error for a feature this model does not have
+    fn unsupported(&self, what: impl Into<String>) -> RazerError {
+        RazerError::FeatureNotSupported {
+            model: self.model.name,
+            what: what.into(),
+        }
+    }
+
+    /// Get the battery level in percent (wireless devices)
+    pub fn get_battery_level(&mut self) -> Result<u8> {
+        self.require(Feature::Battery, "a battery level")?;
+
+        let report = self.report(Command::GetBatteryLevel);
+        let response = self.send_command(&report)?;
+
+        // data[1] = level scaled to 0-255
+        let raw = response.data[1] as u16;
+        Ok(((raw * 100 + 127) / 255) as u8)
+    }
+
+    /// Check whether the device is charging (wireless devices)
+    pub fn get_charging_status(&mut self) -> Result<bool> {
+        self.require(Feature::Battery, "a charging status")?;
+
+        let report = self.report(Command::GetChargingStatus);
+        let response = self.send_command(&report)?;
+
+        Ok(response.data[1] == 0x01)
+    }
+
+    /// Get the idle timeout in seconds before the mouse goes to sleep (wireless devices)
+    pub fn get_idle_time(&mut self) -> Result<u16> {
+        self.require(Feature::Battery, "an idle timeout")?;
+
+        let report = self.report(Command::GetIdleTime);
+        let response = self.send_command(&report)?;
+
+        Ok(u16::from_be_bytes([response.data[0], response.data[1]]))
+    }
+
+    /// Set the idle timeout in seconds (60-900) before the mouse goes to sleep
+    pub fn set_idle_time(&mut self, seconds: u16) -> Result<()> {
+        self.require(Feature::Battery, "an idle timeout")?;
+        if !(protocol::IDLE_TIME_MIN..=protocol::IDLE_TIME_MAX).contains(&seconds) {
+            return Err(RazerError::InvalidArgument(format!(
+                "Invalid idle timeout. Use {}-{} seconds",
+                protocol::IDLE_TIME_MIN,
+                protocol::IDLE_TIME_MAX
+            )));
+        }
+
+        let mut report = self.report(Command::SetIdleTime);
+        report.data[0..2].copy_from_slice(&seconds.to_be_bytes());
+
+        self.send_command(&report)?;
+        Ok(())
+    }
+
+    /// Get the battery level (percent) at which the mouse signals low battery
+    pub fn get_low_battery_threshold(&mut self) -> Result<u8> {
+        self.require(Feature::Battery, "a low-battery threshold")?;
+
+        let report = self.report(Command::GetLowBatteryThreshold);
+        let response = self.send_command(&report)?;
+
+        Ok(protocol::low_battery_threshold_from_raw(response.data[0]))
+    }
+
+    /// Set the battery level (percent, 5-25) at which the mouse signals low battery
+    pub fn set_low_battery_threshold(&mut self, percent: u8) -> Result<()> {
+        self.require(Feature::Battery, "a low-battery threshold")?;
+
+        let mut report = self.report(Command::SetLowBatteryThreshold);
+        report.data[0] = protocol::low_battery_threshold_to_raw(percent);
+
+        self.send_command(&report)?;
+        Ok(())
+    }
+
     /// Get the current device mode
     /// Returns (mode, param) where:
     /// - mode 0x00 = Normal mode (side buttons send keypresses)
     /// - mode 0x03 = Driver mode (side buttons sent via special reports)
     pub fn get_device_mode(&mut self) -> Result<(u8, u8)> {
-        let report = RazerReport::new(Command::GetDeviceMode);
+        self.require(Feature::DeviceMode, "device modes")?;
+
+        let report = self.report(Command::GetDeviceMode);
         let response = self.send_command(&report)?;
 
         tracing::debug!("Device mode response: {:02x?}", &response.data[0..4]);
@@ -264,7 +1010,9 @@ impl RazerDevice {
     /// - mode 0x00, param 0x00 = Normal mode (hardware handles buttons)
     /// - mode 0x03, param 0x00 = Driver mode (buttons sent via HID reports)
     pub fn set_device_mode(&mut self, mode: u8, param: u8) -> Result<()> {
-        let mut report = RazerReport::new(Command::SetDeviceMode);
+        self.require(Feature::DeviceMode, "device modes")?;
+
+        let mut report = self.report(Command::SetDeviceMode);
         report.data[0] = mode;
         report.data[1] = param;
         report.data_size = 2;
@@ -292,3 +1040,44 @@ impl RazerDevice {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(path: &str, product_id: u16, interface_number: i32) -> DeviceInfo {
+        DeviceInfo {
+            path: path.to_string(),
+            vendor_id: RAZER_VENDOR_ID,
+            product_id,
+            manufacturer: "Razer".into(),
+            product: String::new(),
+            interface_number,
+            model: models::find_model(product_id),
+        }
+    }
+
+    #[test]
+    fn test_one_control_interface_per_device() {
+        // Two identical mice (usb 1-2 and 1-3) and a device without interface 0
+        let interfaces = vec![
+            interface("/dev/hidraw2", 0x0067, 1),
+            interface("/dev/hidraw1", 0x0067, 0),
+            interface("/dev/hidraw4", 0x0067, 0),
+            interface("/dev/hidraw5", 0x0067, 2),
+            interface("/dev/hidraw8", 0x005C, 1),
+            interface("/dev/hidraw9", 0x005C, 2),
+        ];
+        let usb = |info: &DeviceInfo| match info.path.as_str() {
+            "/dev/hidraw1" | "/dev/hidraw2" => "1-2".to_string(),
+            "/dev/hidraw4" | "/dev/hidraw5" => "1-3".to_string(),
+            _ => "1-4".to_string(),
+        };
+
+        let picked: Vec<String> = pick_control_interfaces(interfaces, usb)
+            .into_iter()
+            .map(|info| info.path)
+            .collect();
+        assert_eq!(picked, vec!["/dev/hidraw1", "/dev/hidraw4", "/dev/hidraw9"]);
+    }
+}
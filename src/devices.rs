@@ -0,0 +1,791 @@
+//! Several connected Razer devices
+//!
+//! The window configures one device at a time. The selected device's handle
+//! and workers live in the shared slots main.rs passes to its callbacks; every
+//! other device is parked in its `DeviceSession` together with its remapper,
+//! DPI poller and lighting engine, so a mouse and a keypad both stay remapped
+//! while either one is being edited. The headless daemon keeps every device's
+//! state in its session.
+//!
+//! Loading profiles, remapping and DPI stage cycling are session methods that
+//! the window and the daemon share; the window moves the selected device's
+//! state back into its session while calling them.
+//!
+//! A remapped device that is unplugged keeps its session, with the stale
+//! handle dropped, so its remapper and virtual device survive; the session is
+//! reattached to the device with the same identity key when it is back.
+
+use crate::battery::{BatteryPoller, BatteryStatus, LOW_BATTERY_PERCENT};
+use crate::control::DeviceStatus;
+use crate::device::{DEVICE_MODE_DRIVER, DEVICE_MODE_NORMAL, DeviceIdentity, DeviceInfo, DeviceNodes, RazerDevice};
+use crate::hidpoll::{DpiButtonPoller, DpiStep};
+use crate::lighting::{LightingEngine, SoftwareEffect};
+use crate::macro_engine::MacroManager;
+use crate::models::Feature;
+use crate::overlay::{AutoscrollOverlay, OverlayCommand};
+use crate::profile::{LightingSettings, Macro, PowerSettings, Profile, ProfileManager, RemapLayer};
+use crate::remap::{self, MappingTarget, RemapConfig, RemapState, RemapUpdate, Remapper};
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Window settings of a parked device that are not read back from the device
+#[derive(Debug, Clone, Default)]
+pub struct ParkedUi {
+    pub dpi: (u16, u16),
+    pub dpi_stages: Vec<(u16, u16)>,
+    pub active_stage: usize,
+    pub dpi_buttons_cycle: bool,
+    pub polling_rate: u16,
+    pub brightness: u8,
+    pub lighting: LightingSettings,
+    pub power: PowerSettings,
+}
+
+impl ParkedUi {
+    /// Settings a profile sets, with the DPI stages to cycle through
+    pub fn from_profile(profile: &Profile, dpi_stages: Vec<(u16, u16)>, active_stage: usize) -> Self {
+        Self {
+            dpi: (profile.dpi.x, profile.dpi.y),
+            dpi_stages,
+            active_stage,
+            dpi_buttons_cycle: !profile.remap.dpi_button_keys,
+            polling_rate: profile.polling_rate,
+            brightness: profile.brightness,
+            lighting: profile.lighting.clone(),
+            power: profile.power.clone(),
+        }
+    }
+}
+
+/// Where a session's workers report, called on their threads: DPI button
+/// steps while the buttons cycle stages, and the remapper losing its source
+/// devices or grabbing them again
+pub struct WorkerEvents {
+    pub on_step: Box<dyn Fn(DpiStep) + Send>,
+    pub on_state: Box<dyn Fn(RemapState) + Send>,
+}
+
+/// Read a profile, refusing it if it is pinned to a device with another serial
+pub fn read_profile(name: &str, serial: Option<&str>) -> Result<Profile> {
+    let profile = ProfileManager::new()?.load_profile(name)?;
+    if !profile.fits_device(serial) {
+        anyhow::bail!(
+            "Profile '{}' is pinned to another mouse (serial {})",
+            name,
+            profile.device_serial.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(profile)
+}
+
+/// Short description of a DPI stage, e.g. "DPI 1600 (stage 3/5)"
+pub fn dpi_stage_text(dpi_x: u16, dpi_y: u16, index: usize, count: usize) -> String {
+    if dpi_x == dpi_y {
+        format!("DPI {} (stage {}/{})", dpi_x, index + 1, count)
+    } else {
+        format!("DPI {}x{} (stage {}/{})", dpi_x, dpi_y, index + 1, count)
+    }
+}
+
+/// Show a desktop notification for a DPI change via notify-send.
+/// Replaces the previous DPI notification instead of stacking them.
+pub fn show_dpi_notification(text: &str) {
+    let text = text.to_string();
+    // Wait on a helper thread so the caller never blocks on (or leaks) the child
+    thread::spawn(move || {
+        let result = std::process::Command::new("notify-send")
+            .args([
+                "--app-name=RazerLinux",
+                "--expire-time=1500",
+                "--hint=string:x-canonical-private-synchronous:razerlinux-dpi",
+                "--icon=input-mouse",
+                "RazerLinux",
+                &text,
+            ])
+            .status();
+        if let Err(e) = result {
+            warn!("Failed to show DPI notification: {}", e);
+        }
+    });
+}
+
+/// One connected device. While it is selected, the runtime fields are empty
+/// because main.rs holds them.
+pub struct DeviceSession {
+    pub info: DeviceInfo,
+    pub identity: DeviceIdentity,
+    /// Profile last loaded on this device
+    pub active_profile: Option<String>,
+
+    pub device: Option<RazerDevice>,
+    pub remapper: Option<Remapper>,
+    pub dpi_poller: Option<DpiButtonPoller>,
+    pub overlay: Option<AutoscrollOverlay>,
+    pub lighting: Option<LightingEngine>,
+    pub mappings: BTreeMap<u16, MappingTarget>,
+    pub layers: Vec<RemapLayer>,
+    pub macros: MacroManager,
+    pub autoscroll: bool,
+    /// None until the device has been selected and parked once
+    pub ui: Option<ParkedUi>,
+    /// Polls wireless devices whether they are selected or not
+    pub battery: Option<BatteryPoller>,
+    /// Last battery reading
+    pub battery_status: Option<BatteryStatus>,
+}
+
+impl DeviceSession {
+    pub fn new(info: DeviceInfo, identity: DeviceIdentity) -> Self {
+        Self {
+            info,
+            identity,
+            active_profile: None,
+            device: None,
+            remapper: None,
+            dpi_poller: None,
+            overlay: None,
+            lighting: None,
+            mappings: BTreeMap::new(),
+            layers: Vec::new(),
+            macros: MacroManager::new(),
+            autoscroll: false,
+            ui: None,
+            battery: None,
+            battery_status: None,
+        }
+    }
+
+    /// Name for the device picker, with the serial to tell identical models apart
+    pub fn label(&self) -> String {
+        let mut label = self.identity.name.clone();
+        if let Some(serial) = &self.identity.serial {
+            label.push_str(&format!(" ({})", serial));
+        }
+        if let Some(profile) = &self.active_profile {
+            label.push_str(&format!(" - {}", profile));
+        }
+        label
+    }
+
+    /// State reported over the control socket, from the parked settings
+    pub fn status(&self) -> DeviceStatus {
+        DeviceStatus {
+            path: self.info.path.clone(),
+            name: self.identity.name.clone(),
+            serial: self.identity.serial.clone(),
+            profile: self.active_profile.clone(),
+            dpi: self.ui.as_ref().map(|ui| ui.dpi),
+            polling_rate: self.ui.as_ref().map(|ui| ui.polling_rate),
+            battery: self.battery_status.map(|status| status.level),
+            charging: self.battery_status.is_some_and(|status| status.charging),
+            mode: self.mode().to_string(),
+            remapping: self.remapper.is_some(),
+            remap_waiting: self.remapper.as_ref().is_some_and(|r| r.is_waiting()),
+        }
+    }
+
+    /// Device mode; remapping keeps the device in Driver mode
+    pub fn mode(&self) -> &'static str {
+        if self.remapper.is_some() {
+            return "driver";
+        }
+        match self.identity.mode {
+            Some(DEVICE_MODE_NORMAL) => "normal",
+            Some(DEVICE_MODE_DRIVER) => "driver",
+            _ => "unknown",
+        }
+    }
+
+    /// Read a profile and load it, see `apply_profile`
+    pub fn load_profile(&mut self, name: &str, events: WorkerEvents) -> Result<Vec<&'static str>> {
+        let profile = read_profile(name, self.identity.serial.as_deref())?;
+        Ok(self.apply_profile(name, &profile, events))
+    }
+
+    /// Load a profile: push it to the device, take over its mappings,
+    /// layers, macros and autoscroll setting, and start, update or stop
+    /// remapping to match; a running remapper takes the new mappings without
+    /// a restart. The settings the window shows land in `ui`. Returns the
+    /// names of settings that failed to apply.
+    pub fn apply_profile(&mut self, name: &str, profile: &Profile, events: WorkerEvents) -> Vec<&'static str> {
+        let mut failed = match self.device.as_mut() {
+            Some(dev) => profile.apply_to_device(dev),
+            None => Vec::new(),
+        };
+        if let Err(e) = self.set_software_lighting(profile.lighting.software.clone(), profile.dpi.x) {
+            error!("Failed to start software lighting on {}: {}", self.identity.name, e);
+            failed.push("software lighting");
+        }
+
+        // Stages to cycle with the DPI buttons; profiles without any keep the
+        // ones shown before, or the device's own list
+        let (dpi_stages, active_stage) = if !profile.dpi.dpi_stages.is_empty() {
+            (profile.dpi.dpi_stages.clone(), profile.dpi.active_stage)
+        } else if let Some(ui) = self.ui.as_ref() {
+            (ui.dpi_stages.clone(), ui.active_stage)
+        } else {
+            self.device
+                .as_mut()
+                .filter(|dev| dev.model().supports(Feature::DpiStages))
+                .and_then(|dev| dev.get_dpi_stages().map_err(|e| warn!("Failed to read DPI stages: {}", e)).ok())
+                .unwrap_or_default()
+        };
+        self.ui = Some(ParkedUi::from_profile(profile, dpi_stages, active_stage));
+
+        self.mappings = remap::mappings_from_profile(&profile.remap.mappings);
+        self.layers = profile.remap.layers.clone();
+        self.macros.load_from_profile(profile.macros.clone());
+        self.autoscroll = profile.remap.autoscroll;
+        let cycle_stages = !profile.remap.dpi_button_keys;
+        if let Some(poller) = self.dpi_poller.as_ref() {
+            poller.set_cycle_stages(cycle_stages);
+        }
+        if !profile.remap.enabled {
+            self.stop_remapping();
+        } else if self.remapper.is_some() {
+            if !self.update_remapping().is_empty() {
+                failed.push("mappings to new keys (turn remapping off and on)");
+            }
+        } else if let Err(e) = self.start_remapping(cycle_stages, events) {
+            error!("Failed to start remapping on {}: {:#}", self.identity.name, e);
+            failed.push("remapping");
+        }
+
+        self.active_profile = Some(name.to_string());
+        info!("Loaded profile '{}' on {}", name, self.identity.name);
+        failed
+    }
+
+    /// Switch the device to Driver Mode, which makes its side buttons send
+    /// keyboard keys, and start its DPI button poller, autoscroll overlay and
+    /// remapper, scoped to the device's own nodes. Does nothing while
+    /// remapping is on.
+    pub fn start_remapping(&mut self, cycle_stages: bool, events: WorkerEvents) -> Result<()> {
+        if self.remapper.is_some() {
+            return Ok(());
+        }
+        match self.device.as_mut().map(|dev| dev.enable_driver_mode()) {
+            Some(Ok(())) => info!("Driver mode enabled for side button remapping"),
+            Some(Err(e)) => warn!("Failed to enable driver mode: {} - side buttons may not work", e),
+            None => warn!("{} is not open - cannot enable driver mode", self.identity.name),
+        }
+        let nodes = DeviceNodes::for_hid_path(&self.info.path);
+
+        // Start the DPI button poller first so its virtual device exists
+        // when the remapper enumerates devices
+        if self.dpi_poller.is_none() {
+            match DpiButtonPoller::start(nodes.clone(), cycle_stages, events.on_step) {
+                Ok(poller) => {
+                    info!("DPI button poller started");
+                    self.dpi_poller = Some(poller);
+                    // Brief delay to let the uinput device be created
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => warn!("Failed to start DPI poller: {} - DPI buttons won't be remappable", e),
+            }
+        }
+
+        let overlay_sender = self.autoscroll_overlay();
+        let config = RemapConfig {
+            source_device: None,
+            nodes,
+            mappings: self.mappings.clone(),
+            layers: remap::layers_from_profile(&self.layers),
+            autoscroll_enabled: self.autoscroll,
+        };
+        match Remapper::start(config, overlay_sender, self.remapper_macros(), events.on_state) {
+            Ok(remapper) => {
+                self.remapper = Some(remapper);
+                info!("Remapping enabled on {}", self.identity.name);
+                Ok(())
+            }
+            Err(e) => {
+                self.stop_workers();
+                self.disable_driver_mode();
+                Err(e.context("Remap start failed"))
+            }
+        }
+    }
+
+    /// Hand the mappings, macros and autoscroll setting to the running
+    /// remapper, which swaps them without regrabbing the device or
+    /// recreating the virtual device. The autoscroll overlay is started or
+    /// stopped to match. Does nothing while remapping is off. Returns the
+    /// source codes of mappings the remapper leaves out because its virtual
+    /// device can't emit their target keys (see `Remapper::update`).
+    pub fn update_remapping(&mut self) -> Vec<u16> {
+        if self.remapper.is_none() {
+            return Vec::new();
+        }
+        let overlay_sender = self.autoscroll_overlay();
+        let update = RemapUpdate {
+            mappings: self.mappings.clone(),
+            layers: remap::layers_from_profile(&self.layers),
+            macros: self.remapper_macros(),
+            autoscroll_enabled: self.autoscroll,
+            overlay_sender,
+        };
+        let dropped = self.remapper.as_ref().map(|remapper| remapper.update(update)).unwrap_or_default();
+        if let Some(overlay) = self.overlay.take_if(|_| !self.autoscroll) {
+            overlay.shutdown();
+            info!("Autoscroll overlay stopped");
+        }
+        dropped
+    }
+
+    /// Drop the handle and the workers that talk to the device once it is
+    /// unplugged, keeping the remapper, which waits to be reattached
+    fn detach(&mut self) {
+        self.device = None;
+        if let Some(poller) = self.dpi_poller.take() {
+            poller.stop();
+        }
+        self.lighting = None;
+        if let Some(poller) = self.battery.take() {
+            poller.stop();
+        }
+        self.battery_status = None;
+    }
+
+    /// Take over a replugged device with the same identity key: switch it to
+    /// Driver Mode (it starts in Normal mode), restart the DPI button poller
+    /// for its new nodes and hand those to the waiting remapper
+    pub fn reattach(&mut self, info: DeviceInfo, identity: DeviceIdentity, device: RazerDevice, events: WorkerEvents) {
+        self.info = info;
+        self.identity = identity;
+        self.device = Some(device);
+        if let Some(Err(e)) = self.device.as_mut().map(|dev| dev.enable_driver_mode()) {
+            warn!("Failed to re-enable driver mode: {} - side buttons may not work", e);
+        }
+        let nodes = DeviceNodes::for_hid_path(&self.info.path);
+        let cycle_stages = self.ui.as_ref().is_none_or(|ui| ui.dpi_buttons_cycle);
+        match DpiButtonPoller::start(nodes.clone(), cycle_stages, events.on_step) {
+            Ok(poller) => {
+                self.dpi_poller = Some(poller);
+                // The remapper grabs the poller's virtual device too
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => warn!("Failed to start DPI poller: {} - DPI buttons won't be remappable", e),
+        }
+        if let Some(remapper) = self.remapper.as_ref() {
+            remapper.reattach(nodes, events.on_state);
+        }
+        info!("{} is back, remapping resumes", self.identity.name);
+    }
+
+    /// Stop the remapping workers and restore Normal mode
+    pub fn stop_remapping(&mut self) {
+        if self.stop_workers() {
+            self.disable_driver_mode();
+        }
+    }
+
+    /// Stop the remapper, DPI button poller and overlay; false if none ran
+    fn stop_workers(&mut self) -> bool {
+        let was_remapping = self.remapper.is_some() || self.dpi_poller.is_some();
+        if let Some(remapper) = self.remapper.take() {
+            remapper.stop();
+        }
+        if let Some(poller) = self.dpi_poller.take() {
+            poller.stop();
+            info!("DPI button poller stopped");
+        }
+        if let Some(overlay) = self.overlay.take() {
+            overlay.shutdown();
+            info!("Autoscroll overlay stopped");
+        }
+        was_remapping
+    }
+
+    fn disable_driver_mode(&mut self) {
+        match self.device.as_mut().map(|dev| dev.disable_driver_mode()) {
+            Some(Ok(())) => info!("Driver mode disabled - restored normal mode"),
+            Some(Err(e)) => warn!("Failed to disable driver mode: {}", e),
+            None => {}
+        }
+    }
+
+    /// Sender of the autoscroll overlay, started if autoscroll is on
+    fn autoscroll_overlay(&mut self) -> Option<Sender<OverlayCommand>> {
+        if !self.autoscroll {
+            return None;
+        }
+        if let Some(overlay) = self.overlay.as_ref() {
+            return Some(overlay.sender());
+        }
+        match AutoscrollOverlay::start() {
+            Ok(overlay) => {
+                let sender = overlay.sender();
+                self.overlay = Some(overlay);
+                info!("Autoscroll overlay created");
+                Some(sender)
+            }
+            Err(e) => {
+                warn!("Failed to create autoscroll overlay: {} - will work without visual indicator", e);
+                None
+            }
+        }
+    }
+
+    /// Macros by ID, as the remapper thread looks them up
+    fn remapper_macros(&self) -> HashMap<u32, Macro> {
+        self.macros
+            .export_for_profile()
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect()
+    }
+
+    /// Step the device through its DPI stages. Returns a description of the
+    /// new stage for the status line and the DPI notification.
+    pub fn cycle_dpi_stage(&mut self, step: DpiStep) -> Option<String> {
+        let (Some(ui), Some(dev)) = (self.ui.as_mut(), self.device.as_mut()) else {
+            return None;
+        };
+        if ui.dpi_stages.is_empty() {
+            return None;
+        }
+
+        let index = step.next_stage(ui.active_stage, ui.dpi_stages.len());
+        let (dpi_x, dpi_y) = ui.dpi_stages[index];
+        if let Err(e) = dev.set_dpi(dpi_x, dpi_y) {
+            warn!("Failed to set DPI stage on {}: {}", self.identity.name, e);
+            return None;
+        }
+        ui.active_stage = index;
+        ui.dpi = (dpi_x, dpi_y);
+        if let Some(engine) = self.lighting.as_ref() {
+            engine.set_dpi(dpi_x);
+        }
+        let text = format!(
+            "{}: {}",
+            self.identity.name,
+            dpi_stage_text(dpi_x, dpi_y, index, ui.dpi_stages.len())
+        );
+        info!("{}", text);
+        Some(text)
+    }
+
+    /// Start, retarget or stop (None) the software lighting engine; `dpi` is
+    /// the device's current DPI for the DPI stage effect
+    pub fn set_software_lighting(&mut self, effect: Option<SoftwareEffect>, dpi: u16) -> Result<()> {
+        let Some(effect) = effect else {
+            // Dropping the engine stops its thread
+            self.lighting.take();
+            return Ok(());
+        };
+        if let Some(engine) = self.lighting.as_ref() {
+            engine.set_effect(effect);
+            return Ok(());
+        }
+        if self.device.is_none() {
+            return Ok(());
+        }
+        self.lighting = Some(LightingEngine::start(self.info.clone(), effect, dpi)?);
+        Ok(())
+    }
+
+    /// Start polling the battery of a wireless device, unless it is polled already
+    pub fn start_battery_poller<F>(&mut self, on_update: F)
+    where
+        F: Fn(BatteryStatus) + Send + 'static,
+    {
+        let wireless = self.info.model.is_some_and(|model| model.supports(Feature::Battery));
+        if !wireless || self.battery.is_some() {
+            return;
+        }
+        match BatteryPoller::start(self.info.clone(), on_update) {
+            Ok(poller) => self.battery = Some(poller),
+            Err(e) => warn!("Failed to start battery poller on {}: {}", self.identity.name, e),
+        }
+    }
+
+    /// Keep a battery reading. Returns true if the battery just ran low,
+    /// going by the threshold of the loaded profile.
+    pub fn set_battery_status(&mut self, status: BatteryStatus) -> bool {
+        let threshold = self
+            .ui
+            .as_ref()
+            .map_or(LOW_BATTERY_PERCENT, |ui| ui.power.low_battery_threshold);
+        let low = status.should_warn(self.battery_status, threshold);
+        self.battery_status = Some(status);
+        low
+    }
+
+    /// Whether the device owns one of the given hidraw or evdev nodes
+    fn uses_any_node(&self, nodes: &[PathBuf]) -> bool {
+        let path = PathBuf::from(&self.info.path);
+        nodes.iter().any(|node| {
+            *node == path
+                || self.identity.nodes.hidraw.contains(node)
+                || self.identity.nodes.input.contains(node)
+        })
+    }
+}
+
+/// All connected devices and which one the window shows
+#[derive(Default)]
+pub struct DeviceSet {
+    sessions: Vec<DeviceSession>,
+    selected: Option<usize>,
+    /// Profile each unplugged device had loaded, by identity key, so it comes
+    /// back when the device is plugged in again
+    last_profiles: BTreeMap<String, String>,
+    /// Sessions of unplugged devices that were being remapped, by identity
+    /// key; not listed until `take_unplugged` reattaches them
+    unplugged: BTreeMap<String, DeviceSession>,
+}
+
+impl DeviceSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&DeviceSession> {
+        self.selected.map(|i| &self.sessions[i])
+    }
+
+    pub fn selected_mut(&mut self) -> Option<&mut DeviceSession> {
+        self.selected.map(|i| &mut self.sessions[i])
+    }
+
+    /// All sessions, in selection order
+    pub fn sessions(&self) -> impl Iterator<Item = &DeviceSession> {
+        self.sessions.iter()
+    }
+
+    /// All sessions, in selection order
+    pub fn sessions_mut(&mut self) -> impl Iterator<Item = &mut DeviceSession> {
+        self.sessions.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut DeviceSession> {
+        self.sessions.get_mut(index)
+    }
+
+    /// Session whose control interface is the given hidraw path
+    pub fn find_by_path(&mut self, path: &str) -> Option<&mut DeviceSession> {
+        self.sessions.iter_mut().find(|s| s.info.path == path)
+    }
+
+    /// Index of the session whose control interface is the given hidraw path
+    pub fn index_of_path(&self, path: &str) -> Option<usize> {
+        self.sessions.iter().position(|s| s.info.path == path)
+    }
+
+    /// Index of the session of a device, by its identity key
+    pub fn index_of_key(&self, key: &str) -> Option<usize> {
+        self.sessions.iter().position(|s| s.identity.key() == key)
+    }
+
+    /// Index of the device a request names by hidraw path or serial number,
+    /// or of the selected (or else the first) device if it names none
+    pub fn resolve(&self, selector: Option<&str>) -> Option<usize> {
+        match selector {
+            Some(selector) => self.index_of_path(selector).or_else(|| {
+                self.sessions
+                    .iter()
+                    .position(|s| s.identity.serial.as_deref() == Some(selector))
+            }),
+            None => self.selected.or((!self.sessions.is_empty()).then_some(0)),
+        }
+    }
+
+    /// Mark a device as selected. The caller moves its runtime state into
+    /// the shared slots.
+    pub fn select(&mut self, index: usize) {
+        if index < self.sessions.len() {
+            self.selected = Some(index);
+        }
+    }
+
+    pub fn push(&mut self, session: DeviceSession) {
+        self.sessions.push(session);
+    }
+
+    /// Labels for the device picker, in selection order
+    pub fn labels(&self) -> Vec<String> {
+        self.sessions.iter().map(DeviceSession::label).collect()
+    }
+
+    /// Forget devices that are no longer connected (their parked workers stop
+    /// when dropped), and devices one of whose nodes in `gone` was removed,
+    /// since a replugged device may get its old nodes back. Devices being
+    /// remapped are kept aside instead, see `take_unplugged`. Returns the
+    /// devices in `found` that are not known yet, and whether the selected
+    /// device went away.
+    pub fn retain_found(&mut self, found: &[DeviceInfo], gone: &[PathBuf]) -> (Vec<DeviceInfo>, bool) {
+        let selected_path = self.selected().map(|s| s.info.path.clone());
+
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.sessions)
+            .into_iter()
+            .partition(|s| {
+                found.iter().any(|info| info.path == s.info.path) && !s.uses_any_node(gone)
+            });
+        self.sessions = kept;
+        for mut session in removed {
+            let key = session.identity.key();
+            if let Some(profile) = session.active_profile.clone() {
+                self.last_profiles.insert(key.clone(), profile);
+            }
+            if session.remapper.is_some() {
+                info!("{} was unplugged, keeping its remapper until it is back", session.identity.name);
+                session.detach();
+                self.unplugged.insert(key, session);
+            }
+        }
+
+        self.selected = selected_path
+            .as_ref()
+            .and_then(|path| self.index_of_path(path));
+        let selected_removed = selected_path.is_some() && self.selected.is_none();
+
+        let new = found
+            .iter()
+            .filter(|info| self.index_of_path(&info.path).is_none())
+            .cloned()
+            .collect();
+        (new, selected_removed)
+    }
+
+    /// Profile an unplugged device had loaded, if it is plugged in again
+    pub fn take_last_profile(&mut self, key: &str) -> Option<String> {
+        self.last_profiles.remove(key)
+    }
+
+    /// Session of an unplugged device that was being remapped, for the device
+    /// with this identity key that was just opened; the caller reattaches it
+    /// (`DeviceSession::reattach`) and pushes it back
+    pub fn take_unplugged(&mut self, key: &str) -> Option<DeviceSession> {
+        self.unplugged.remove(key)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn info(path: &str) -> DeviceInfo {
+        DeviceInfo {
+            path: path.to_string(),
+            vendor_id: 0x1532,
+            product_id: 0x0067,
+            manufacturer: "Razer".into(),
+            product: "Razer Naga Trinity".into(),
+            interface_number: 0,
+            model: None,
+        }
+    }
+
+    /// A Naga Trinity session with no device handle
+    pub(crate) fn session(path: &str, serial: &str) -> DeviceSession {
+        let identity = DeviceIdentity {
+            name: "Razer Naga Trinity".into(),
+            serial: Some(serial.into()),
+            hid_path: path.into(),
+            ..Default::default()
+        };
+        DeviceSession::new(info(path), identity)
+    }
+
+    #[test]
+    fn test_retain_found_keeps_selection() {
+        let mut set = DeviceSet::new();
+        set.push(session("/dev/hidraw1", "A"));
+        set.push(session("/dev/hidraw4", "B"));
+        set.select(1);
+
+        // First device unplugged, a new one plugged in
+        let (new, selected_removed) = set.retain_found(&[info("/dev/hidraw4"), info("/dev/hidraw7")], &[]);
+        assert!(!selected_removed);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.selected().unwrap().identity.key(), "B");
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].path, "/dev/hidraw7");
+
+        let (_, selected_removed) = set.retain_found(&[], &[]);
+        assert!(selected_removed);
+        assert!(set.is_empty());
+        assert!(set.selected().is_none());
+    }
+
+    #[test]
+    fn test_replugged_device_gets_its_profile_back() {
+        let mut set = DeviceSet::new();
+        set.push(session("/dev/hidraw1", "A"));
+        set.select(0);
+        set.selected_mut().unwrap().active_profile = Some("fps".into());
+
+        // Unplugged and plugged in again within one burst of hotplug events:
+        // the node name is reused, but the old session is stale
+        let gone = [PathBuf::from("/dev/hidraw1")];
+        let (new, selected_removed) = set.retain_found(&[info("/dev/hidraw1")], &gone);
+        assert!(selected_removed);
+        assert!(set.is_empty());
+        assert_eq!(new.len(), 1);
+
+        assert_eq!(set.take_last_profile("A").as_deref(), Some("fps"));
+        assert_eq!(set.take_last_profile("A"), None);
+        // Only devices being remapped wait to be reattached
+        assert!(set.take_unplugged("A").is_none());
+    }
+
+    #[test]
+    fn test_battery_status() {
+        let mut session = session("/dev/hidraw1", "A");
+        assert_eq!(session.status().battery, None);
+
+        assert!(!session.set_battery_status(BatteryStatus { level: 80, charging: false }));
+        // Warns once when crossing the default threshold
+        assert!(session.set_battery_status(BatteryStatus { level: 10, charging: false }));
+        assert!(!session.set_battery_status(BatteryStatus { level: 9, charging: false }));
+        let status = session.status();
+        assert_eq!(status.battery, Some(9));
+        assert!(!status.charging);
+    }
+
+    #[test]
+    fn test_resolve_by_path_or_serial() {
+        let mut set = DeviceSet::new();
+        assert_eq!(set.resolve(None), None);
+        set.push(session("/dev/hidraw1", "PM1111"));
+        set.push(session("/dev/hidraw4", "PM2222"));
+
+        assert_eq!(set.resolve(None), Some(0));
+        assert_eq!(set.resolve(Some("/dev/hidraw4")), Some(1));
+        assert_eq!(set.resolve(Some("PM2222")), Some(1));
+        assert_eq!(set.resolve(Some("PM3333")), None);
+        set.select(1);
+        assert_eq!(set.resolve(None), Some(1));
+    }
+
+    #[test]
+    fn test_labels_tell_identical_models_apart() {
+        let mut set = DeviceSet::new();
+        set.push(session("/dev/hidraw1", "PM1111"));
+        set.push(session("/dev/hidraw4", "PM2222"));
+        set.find_by_path("/dev/hidraw4").unwrap().active_profile = Some("mmo".into());
+
+        assert_eq!(
+            set.labels(),
+            vec!["Razer Naga Trinity (PM1111)", "Razer Naga Trinity (PM2222) - mmo"]
+        );
+    }
+}
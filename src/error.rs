@@ -0,0 +1,102 @@
+//! Errors from the HID protocol and device layer
+//!
+//! `protocol.rs` and `device.rs` return `RazerError` so callers can tell a busy
+//! device from a missing feature or a permission problem, and the UI can
+//! suggest a fix instead of showing a bare message.
+
+use thiserror::Error;
+
+/// Result type of the protocol and device layer
+pub type Result<T> = std::result::Result<T, RazerError>;
+
+/// Errors talking to a Razer device
+#[derive(Debug, Error)]
+pub enum RazerError {
+    /// The device still answered "busy" when the retry timeout ran out
+    #[error("device busy")]
+    DeviceBusy,
+
+    /// The device rejected the command (status 0x03)
+    #[error("command {class:#04x}/{id:#04x} failed")]
+    CommandFailed { class: u8, id: u8 },
+
+    /// The device does not implement the command (status 0x05)
+    #[error("command {class:#04x}/{id:#04x} not supported by the device")]
+    NotSupported { class: u8, id: u8 },
+
+    /// The model table says the device lacks a feature, so nothing was sent
+    #[error("{model} does not support {what}")]
+    FeatureNotSupported { model: &'static str, what: String },
+
+    /// The response checksum (byte 88) does not match its contents
+    #[error("response CRC mismatch (expected {expected:#04x}, got {actual:#04x})")]
+    CrcMismatch { expected: u8, actual: u8 },
+
+    /// The device reported a timeout (status 0x04) or never answered in time
+    #[error("command {class:#04x}/{id:#04x} timed out")]
+    Timeout { class: u8, id: u8 },
+
+    /// The hidraw node exists but this user may not open it
+    #[error("permission denied on {path}")]
+    PermissionDenied { path: String },
+
+    /// The device went away (unplugged, or the receiver lost the mouse)
+    #[error("device disconnected")]
+    Disconnected,
+
+    /// The USB product ID is not in the model table
+    #[error("unsupported Razer device (PID {0:#06x})")]
+    UnknownDevice(u16),
+
+    /// A value the device or the protocol cannot take
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    /// A response that could not be decoded
+    #[error("malformed response: {0}")]
+    InvalidResponse(String),
+
+    /// Any other hidapi failure
+    #[error("HID error: {0}")]
+    Hid(#[from] hidapi::HidError),
+}
+
+impl RazerError {
+    /// What the user can do about this error, if anything
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            RazerError::PermissionDenied { .. } => Some(
+                "install the udev rule (sudo cp config/99-razermouse.rules /etc/udev/rules.d/), replug the mouse, or run with sudo",
+            ),
+            RazerError::Disconnected => Some("reconnect the mouse and click Refresh"),
+            RazerError::DeviceBusy | RazerError::Timeout { .. } | RazerError::CrcMismatch { .. } => {
+                Some("try again in a moment")
+            }
+            _ => None,
+        }
+    }
+
+    /// Error message followed by the hint, for status bars and dialogs
+    pub fn user_message(&self) -> String {
+        match self.hint() {
+            Some(hint) => format!("{} - {}", self, hint),
+            None => self.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_hint_mentions_udev() {
+        let e = RazerError::PermissionDenied { path: "/dev/hidraw3".into() };
+        let message = e.user_message();
+        assert!(message.starts_with("permission denied on /dev/hidraw3"));
+        assert!(message.contains("udev"));
+
+        let e = RazerError::NotSupported { class: 0x04, id: 0x86 };
+        assert_eq!(e.user_message(), "command 0x04/0x86 not supported by the device");
+    }
+}
@@ -0,0 +1,245 @@
+//! Focused window tracking for per-application profiles
+//!
+//! Watches `_NET_ACTIVE_WINDOW` on the X11 root window and reports the
+//! WM_CLASS and process name of each newly focused window. Our own window is
+//! ignored, so opening RazerLinux to tweak a profile does not switch away
+//! from it. Wayland sessions only expose XWayland windows this way.
+
+use crate::settings::AppSettings;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::*;
+
+/// How long the watcher sleeps when no X11 events are pending, bounding the stop latency
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The application owning the focused window
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FocusedWindow {
+    /// WM_CLASS instance name (e.g. "navigator")
+    pub instance: String,
+    /// WM_CLASS class name (e.g. "firefox")
+    pub class: String,
+    /// Name of the process from `_NET_WM_PID`, if the window sets it
+    pub process: Option<String>,
+}
+
+/// Split a WM_CLASS property value ("instance\0class\0") into instance and class
+pub fn parse_wm_class(value: &[u8]) -> (String, String) {
+    let mut parts = value.split(|&b| b == 0).map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or_default();
+    (instance, class)
+}
+
+/// Focus watcher - calls back when another application gets the focus.
+/// Stops when dropped.
+pub struct FocusWatcher {
+    stop: Arc<AtomicBool>,
+    join: Option<thread::JoinHandle<()>>,
+}
+
+impl FocusWatcher {
+    /// Start watching the X11 display, calling `on_change` for each newly
+    /// focused application
+    pub fn start<F>(on_change: F) -> Self
+    where
+        F: Fn(FocusedWindow) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        let join = thread::spawn(move || {
+            if let Err(e) = run_watch_loop(stop_thread, on_change) {
+                warn!("Focus watcher stopped: {e:#}");
+            }
+        });
+
+        Self {
+            stop,
+            join: Some(join),
+        }
+    }
+}
+
+impl Drop for FocusWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.join.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_watch_loop<F>(stop: Arc<AtomicBool>, on_change: F) -> Result<()>
+where
+    F: Fn(FocusedWindow),
+{
+    let (conn, screen_num) = x11rb::connect(None).context("Failed to connect to X11 display")?;
+    let root = conn.setup().roots[screen_num].root;
+    let active_atom = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+    let pid_atom = conn.intern_atom(false, b"_NET_WM_PID")?.reply()?.atom;
+
+    conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE))?;
+    conn.flush()?;
+    info!("Focus watcher: watching the active window");
+
+    let own_pid = std::process::id();
+    let mut last: Option<FocusedWindow> = None;
+    let mut check = true;
+    while !stop.load(Ordering::Relaxed) {
+        if check {
+            check = false;
+            let focused = read_focused_window(&conn, root, active_atom, pid_atom, own_pid);
+            if let Some(window) = focused.filter(|window| last.as_ref() != Some(window)) {
+                debug!("Focus: {} ({}) {:?}", window.class, window.instance, window.process);
+                last = Some(window.clone());
+                on_change(window);
+            }
+        }
+
+        match conn.poll_for_event()? {
+            Some(Event::PropertyNotify(event)) if event.atom == active_atom => check = true,
+            Some(_) => {}
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+
+    info!("Focus watcher: shutting down");
+    Ok(())
+}
+
+/// The application owning the active window; None when nothing has the
+/// focus, the window is gone already or it is one of ours
+fn read_focused_window<C: Connection>(
+    conn: &C,
+    root: Window,
+    active_atom: Atom,
+    pid_atom: Atom,
+    own_pid: u32,
+) -> Option<FocusedWindow> {
+    let window = conn
+        .get_property(false, root, active_atom, AtomEnum::WINDOW, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()
+        .filter(|&window| window != x11rb::NONE)?;
+
+    let wm_class = conn
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+        .ok()?
+        .reply()
+        .ok()?;
+    let (instance, class) = parse_wm_class(&wm_class.value);
+
+    let pid = conn
+        .get_property(false, window, pid_atom, AtomEnum::CARDINAL, 0, 1)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| reply.value32().and_then(|mut values| values.next()));
+    if pid == Some(own_pid) {
+        return None;
+    }
+    let process = pid.and_then(|pid| fs::read_to_string(format!("/proc/{}/comm", pid)).ok());
+
+    Some(FocusedWindow {
+        instance,
+        class,
+        process: process.map(|name| name.trim_end().to_string()),
+    })
+}
+
+/// Picks the profile each device switches to as the focus moves, from the
+/// `app_profiles` rules. A device only switches when the profile its rules
+/// pick changes, so a profile loaded by hand stays until the focus moves to
+/// an application with a different one.
+pub struct AppProfileSwitcher {
+    settings: AppSettings,
+    /// Last picked profile per device key
+    targets: HashMap<String, String>,
+}
+
+impl AppProfileSwitcher {
+    pub fn new(settings: AppSettings) -> Self {
+        Self {
+            settings,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Use settings saved since, so edited rules and startup profiles apply
+    pub fn set_settings(&mut self, settings: AppSettings) {
+        self.settings = settings;
+    }
+
+    /// Profile the device with the given key switches to now that `window`
+    /// has the focus, if the rules pick a different one than before
+    pub fn switch_to(&mut self, window: &FocusedWindow, key: &str) -> Option<String> {
+        let target = self.settings.profile_for_window(window, key);
+        let previous = self
+            .targets
+            .get(key)
+            .map(String::as_str)
+            .unwrap_or_else(|| self.settings.profile_for_device(key));
+        if target == previous {
+            return None;
+        }
+        let target = target.to_string();
+        self.targets.insert(key.to_string(), target.clone());
+        (!target.is_empty()).then_some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::AppProfileRule;
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(
+            parse_wm_class(b"navigator\0firefox\0"),
+            ("navigator".to_string(), "firefox".to_string())
+        );
+        assert_eq!(parse_wm_class(b"code"), ("code".to_string(), String::new()));
+        assert_eq!(parse_wm_class(b""), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn test_switcher_only_switches_on_new_target() {
+        let mut settings = AppSettings::default();
+        settings.app_profiles.push(AppProfileRule {
+            class: Some("code".into()),
+            process: None,
+            device: None,
+            profile: "ide".into(),
+        });
+        let mut switcher = AppProfileSwitcher::new(settings);
+        let window = |class: &str| FocusedWindow {
+            instance: class.into(),
+            class: class.into(),
+            process: None,
+        };
+
+        // Still the startup profile: nothing to do
+        assert_eq!(switcher.switch_to(&window("firefox"), "PM1234"), None);
+        assert_eq!(switcher.switch_to(&window("code"), "PM1234").as_deref(), Some("ide"));
+        assert_eq!(switcher.switch_to(&window("code"), "PM1234"), None);
+        assert_eq!(switcher.switch_to(&window("firefox"), "PM1234").as_deref(), Some("Default"));
+
+        // A new startup profile for the device applies from the next switch
+        let mut settings = AppSettings::default();
+        settings.device_profiles.insert("PM1234".into(), "work".into());
+        switcher.set_settings(settings);
+        assert_eq!(switcher.switch_to(&window("firefox"), "PM1234").as_deref(), Some("work"));
+    }
+}
@@ -1,18 +1,21 @@
 //! HID Raw Device Polling for DPI Buttons
 //!
-//! The Razer Naga Trinity's DPI buttons (under the scroll wheel) don't generate
+//! The DPI buttons (under the scroll wheel) on mice like the Razer Naga Trinity don't generate
 //! standard Linux input events. Instead, they send special HID reports on the
 //! keyboard interface that are only visible via hidraw.
 //!
 //! This module polls the hidraw device for these special reports (Report ID 0x04)
 //! and converts DPI button codes to virtual F13/F14 key events that can be
-//! remapped like any other button.
+//! remapped like any other button. With stage cycling enabled, presses are
+//! reported to a callback as `DpiStep`s instead, so the application can step
+//! through the profile's DPI stages.
 //!
 //! Based on reverse-engineering from OpenRazer kernel driver:
 //! - Report format: 0x04 [modifiers] [key codes...]
 //! - DPI Up:   code 0x20 -> F13 (keycode 183)
 //! - DPI Down: code 0x21 -> F14 (keycode 184)
 
+use crate::device::DeviceNodes;
 use anyhow::{Context, Result};
 use evdev::{EventType, InputEvent, uinput::VirtualDeviceBuilder, AttributeSet, Key};
 use std::fs::File;
@@ -26,8 +29,6 @@ use tracing::{info, warn, debug};
 
 /// Razer USB VID
 const RAZER_VID: u16 = 0x1532;
-/// Naga Trinity PID
-const NAGA_TRINITY_PID: u16 = 0x0067;
 
 /// HID report codes for DPI buttons (from OpenRazer)
 const HID_CODE_DPI_UP: u8 = 0x20;   // M1 in OpenRazer terminology
@@ -37,8 +38,39 @@ const HID_CODE_DPI_DOWN: u8 = 0x21; // M2 in OpenRazer terminology
 const KEY_F13: u16 = 183;
 const KEY_F14: u16 = 184;
 
-/// Find all hidraw devices for the Razer Naga Trinity keyboard interface
-pub fn find_naga_trinity_hidraw_devices() -> Vec<PathBuf> {
+/// Direction of a DPI button press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DpiStep {
+    Up,
+    Down,
+}
+
+impl DpiStep {
+    /// Index of the stage to switch to from `current` in a list of `count`
+    /// stages, wrapping around at either end
+    pub fn next_stage(self, current: usize, count: usize) -> usize {
+        if count == 0 {
+            return 0;
+        }
+        let current = current.min(count - 1);
+        match self {
+            DpiStep::Up => (current + 1) % count,
+            DpiStep::Down => (current + count - 1) % count,
+        }
+    }
+}
+
+/// Name of the uinput device the poller injects F13/F14 through. Scoped
+/// pollers carry their device's tag so each remapper grabs only its own.
+pub fn virtual_device_name(nodes: &DeviceNodes) -> String {
+    match nodes.tag() {
+        Some(tag) => format!("RazerLinux DPI Buttons ({})", tag),
+        None => "RazerLinux DPI Buttons".to_string(),
+    }
+}
+
+/// Find all hidraw devices of supported Razer mice whose DPI buttons report via hidraw
+pub fn find_razer_hidraw_devices() -> Vec<PathBuf> {
     let mut devices = Vec::new();
     
     // Scan /sys/class/hidraw/ for Razer devices
@@ -61,7 +93,7 @@ pub fn find_naga_trinity_hidraw_devices() -> Vec<PathBuf> {
             if let Ok(uevent) = std::fs::read_to_string(&uevent_path) {
                 // Parse MODALIAS or HID_ID to find our device
                 // Format: HID_ID=0003:00001532:00000067
-                let is_naga_trinity = uevent.lines().any(|line| {
+                let is_supported = uevent.lines().any(|line| {
                     if let Some(hid_id) = line.strip_prefix("HID_ID=") {
                         // Parse format: BUS:VID:PID
                         let parts: Vec<&str> = hid_id.split(':').collect();
@@ -70,16 +102,18 @@ pub fn find_naga_trinity_hidraw_devices() -> Vec<PathBuf> {
                                 u16::from_str_radix(parts[1], 16),
                                 u16::from_str_radix(parts[2], 16)
                             ) {
-                                return vid == RAZER_VID && pid == NAGA_TRINITY_PID;
+                                return vid == RAZER_VID
+                                    && crate::models::find_model(pid)
+                                        .is_some_and(|m| m.buttons.dpi_buttons);
                             }
                         }
                     }
                     false
                 });
                 
-                if is_naga_trinity {
+                if is_supported {
                     let dev_path = PathBuf::from("/dev").join(&hidraw_name_str.as_ref());
-                    info!("Found Naga Trinity hidraw device: {:?}", dev_path);
+                    info!("Found Razer hidraw device: {:?}", dev_path);
                     devices.push(dev_path);
                 }
             }
@@ -92,6 +126,7 @@ pub fn find_naga_trinity_hidraw_devices() -> Vec<PathBuf> {
 /// DPI Button Poller - polls hidraw for DPI button HID reports
 pub struct DpiButtonPoller {
     stop: Arc<AtomicBool>,
+    cycle_stages: Arc<AtomicBool>,
     join: Option<thread::JoinHandle<()>>,
 }
 
@@ -99,25 +134,39 @@ impl DpiButtonPoller {
     /// Start polling for DPI button events
     /// 
     /// This creates a background thread that:
-    /// 1. Opens all Naga Trinity hidraw devices
+    /// 1. Opens all supported Razer hidraw devices
     /// 2. Polls for Report ID 0x04 (keyboard report with special keys)
     /// 3. Converts DPI button codes (0x20/0x21) to F13/F14 key events
     /// 4. Injects those events via uinput virtual device
-    pub fn start() -> Result<Self> {
+    ///
+    /// Only the hidraw nodes in `nodes` are polled unless it is empty.
+    /// While `cycle_stages` is set, presses call `on_step` instead of injecting keys.
+    pub fn start<F>(nodes: DeviceNodes, cycle_stages: bool, on_step: F) -> Result<Self>
+    where
+        F: Fn(DpiStep) + Send + 'static,
+    {
         let stop = Arc::new(AtomicBool::new(false));
         let stop_thread = stop.clone();
+        let cycle_stages = Arc::new(AtomicBool::new(cycle_stages));
+        let cycle_thread = cycle_stages.clone();
         
         let join = thread::spawn(move || {
-            if let Err(e) = run_dpi_poller_loop(stop_thread) {
+            if let Err(e) = run_dpi_poller_loop(stop_thread, nodes, cycle_thread, on_step) {
                 warn!("DPI button poller stopped: {e:#}");
             }
         });
         
         Ok(Self {
             stop,
+            cycle_stages,
             join: Some(join),
         })
     }
+
+    /// Switch between DPI stage cycling and F13/F14 key injection
+    pub fn set_cycle_stages(&self, enabled: bool) {
+        self.cycle_stages.store(enabled, Ordering::Relaxed);
+    }
     
     /// Stop the poller
     pub fn stop(mut self) {
@@ -137,11 +186,22 @@ impl Drop for DpiButtonPoller {
     }
 }
 
-fn run_dpi_poller_loop(stop: Arc<AtomicBool>) -> Result<()> {
-    let hidraw_devices = find_naga_trinity_hidraw_devices();
+fn run_dpi_poller_loop<F>(
+    stop: Arc<AtomicBool>,
+    nodes: DeviceNodes,
+    cycle_stages: Arc<AtomicBool>,
+    on_step: F,
+) -> Result<()>
+where
+    F: Fn(DpiStep),
+{
+    let mut hidraw_devices = find_razer_hidraw_devices();
+    if !nodes.hidraw.is_empty() {
+        hidraw_devices.retain(|path| nodes.hidraw.contains(path));
+    }
     
     if hidraw_devices.is_empty() {
-        warn!("No Naga Trinity hidraw devices found - DPI buttons won't be available");
+        warn!("No Razer hidraw devices found - DPI buttons won't be available");
         // Keep thread alive but just sleep until stopped
         while !stop.load(Ordering::Relaxed) {
             thread::sleep(Duration::from_millis(500));
@@ -184,9 +244,10 @@ fn run_dpi_poller_loop(stop: Arc<AtomicBool>) -> Result<()> {
     keys.insert(Key::new(KEY_F13));
     keys.insert(Key::new(KEY_F14));
     
+    let name = virtual_device_name(&nodes);
     let vbuilder = VirtualDeviceBuilder::new()
         .context("Failed to create uinput builder for DPI buttons")?
-        .name("RazerLinux DPI Buttons")
+        .name(&name)
         .with_keys(&keys)
         .context("Failed to set F13/F14 key capabilities")?;
     
@@ -198,6 +259,9 @@ fn run_dpi_poller_loop(stop: Arc<AtomicBool>) -> Result<()> {
     // Track button states to detect press/release
     let mut dpi_up_pressed = false;
     let mut dpi_down_pressed = false;
+    // Whether the current press was injected as a key (and needs a release)
+    let mut dpi_up_injected = false;
+    let mut dpi_down_injected = false;
     
     while !stop.load(Ordering::Relaxed) {
         let mut had_data = false;
@@ -245,13 +309,21 @@ fn run_dpi_poller_loop(stop: Arc<AtomicBool>) -> Result<()> {
                     
                     // DPI Up press/release
                     if found_dpi_up && !dpi_up_pressed {
-                        info!("DPI UP pressed -> injecting F13");
                         dpi_up_pressed = true;
-                        let press = InputEvent::new(EventType::KEY, KEY_F13, 1);
-                        let sync = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
-                        if let Err(e) = vdev.emit(&[press, sync]) {
-                            warn!("Failed to emit F13 press: {}", e);
+                        dpi_up_injected = !cycle_stages.load(Ordering::Relaxed);
+                        if dpi_up_injected {
+                            info!("DPI UP pressed -> injecting F13");
+                            let press = InputEvent::new(EventType::KEY, KEY_F13, 1);
+                            let sync = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+                            if let Err(e) = vdev.emit(&[press, sync]) {
+                                warn!("Failed to emit F13 press: {}", e);
+                            }
+                        } else {
+                            info!("DPI UP pressed -> next DPI stage");
+                            on_step(DpiStep::Up);
                         }
+                    } else if !found_dpi_up && dpi_up_pressed && !dpi_up_injected {
+                        dpi_up_pressed = false;
                     } else if !found_dpi_up && dpi_up_pressed {
                         info!("DPI UP released -> injecting F13 release");
                         dpi_up_pressed = false;
@@ -264,13 +336,21 @@ fn run_dpi_poller_loop(stop: Arc<AtomicBool>) -> Result<()> {
                     
                     // DPI Down press/release
                     if found_dpi_down && !dpi_down_pressed {
-                        info!("DPI DOWN pressed -> injecting F14");
                         dpi_down_pressed = true;
-                        let press = InputEvent::new(EventType::KEY, KEY_F14, 1);
-                        let sync = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
-                        if let Err(e) = vdev.emit(&[press, sync]) {
-                            warn!("Failed to emit F14 press: {}", e);
+                        dpi_down_injected = !cycle_stages.load(Ordering::Relaxed);
+                        if dpi_down_injected {
+                            info!("DPI DOWN pressed -> injecting F14");
+                            let press = InputEvent::new(EventType::KEY, KEY_F14, 1);
+                            let sync = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+                            if let Err(e) = vdev.emit(&[press, sync]) {
+                                warn!("Failed to emit F14 press: {}", e);
+                            }
+                        } else {
+                            info!("DPI DOWN pressed -> previous DPI stage");
+                            on_step(DpiStep::Down);
                         }
+                    } else if !found_dpi_down && dpi_down_pressed && !dpi_down_injected {
+                        dpi_down_pressed = false;
                     } else if !found_dpi_down && dpi_down_pressed {
                         info!("DPI DOWN released -> injecting F14 release");
                         dpi_down_pressed = false;
@@ -309,10 +389,32 @@ mod tests {
     #[test]
     fn test_find_hidraw() {
         // This test just runs the discovery - won't find devices unless run on actual hardware
-        let devices = find_naga_trinity_hidraw_devices();
+        let devices = find_razer_hidraw_devices();
         println!("Found {} hidraw devices", devices.len());
         for d in &devices {
             println!("  {:?}", d);
         }
     }
+
+    #[test]
+    fn test_virtual_device_name() {
+        assert_eq!(virtual_device_name(&DeviceNodes::default()), "RazerLinux DPI Buttons");
+
+        let nodes = DeviceNodes {
+            hidraw: vec![PathBuf::from("/dev/hidraw3"), PathBuf::from("/dev/hidraw4")],
+            input: Vec::new(),
+        };
+        assert_eq!(virtual_device_name(&nodes), "RazerLinux DPI Buttons (hidraw3)");
+    }
+
+    #[test]
+    fn test_dpi_step_wraps() {
+        assert_eq!(DpiStep::Up.next_stage(0, 3), 1);
+        assert_eq!(DpiStep::Up.next_stage(2, 3), 0);
+        assert_eq!(DpiStep::Down.next_stage(0, 3), 2);
+        assert_eq!(DpiStep::Down.next_stage(1, 3), 0);
+        // Stale index after the list shrank
+        assert_eq!(DpiStep::Up.next_stage(7, 3), 0);
+        assert_eq!(DpiStep::Up.next_stage(0, 0), 0);
+    }
 }
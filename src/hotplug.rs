@@ -0,0 +1,171 @@
+//! Hotplug monitoring via udev
+//!
+//! Watches hidraw and input add/remove events of Razer devices (VID 0x1532)
+//! on a background thread. Plugging in a mouse fires a burst of events, one
+//! per interface, so the callback runs once the burst has settled. It gets
+//! the device nodes removed during the burst: a replugged device often gets
+//! its old /dev/hidrawN back, and these nodes tell main.rs that the handle
+//! it holds is stale anyway.
+
+use anyhow::{Context, Result};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Razer USB VID
+const RAZER_VID: u16 = 0x1532;
+
+/// Quiet time after the last event before the callback runs
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// How long each poll of the udev socket waits, bounding the stop latency
+const POLL_INTERVAL_MS: i32 = 200;
+
+/// Product ID of the Razer HID device a sysfs path belongs to.
+///
+/// hidraw and evdev nodes of a USB HID device sit below a directory named
+/// `BUS:VID:PID.INSTANCE` (e.g. `0003:1532:0067.0005`). The path is part of
+/// remove events too, when sysfs attributes can no longer be read. Virtual
+/// devices such as our own uinput devices live under /devices/virtual and
+/// never match.
+pub fn razer_product_from_devpath(devpath: &str) -> Option<u16> {
+    devpath.split('/').find_map(|segment| {
+        let mut parts = segment.split(':');
+        let (_bus, vid, rest) = (parts.next()?, parts.next()?, parts.next()?);
+        let (pid, _instance) = rest.split_once('.')?;
+        if parts.next().is_some() || vid.len() != 4 || pid.len() != 4 {
+            return None;
+        }
+        let vid = u16::from_str_radix(vid, 16).ok()?;
+        let pid = u16::from_str_radix(pid, 16).ok()?;
+        (vid == RAZER_VID).then_some(pid)
+    })
+}
+
+/// Hotplug monitor - calls back when Razer devices are plugged in or removed.
+/// Stops when dropped.
+pub struct HotplugMonitor {
+    stop: Arc<AtomicBool>,
+    join: Option<thread::JoinHandle<()>>,
+}
+
+impl HotplugMonitor {
+    /// Start watching udev, calling `on_change` with the removed device nodes
+    /// after each settled burst of add/remove events
+    pub fn start<F>(on_change: F) -> Result<Self>
+    where
+        F: Fn(Vec<PathBuf>) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        let join = thread::spawn(move || {
+            if let Err(e) = run_monitor_loop(stop_thread, on_change) {
+                warn!("Hotplug monitor stopped: {e:#}");
+            }
+        });
+
+        Ok(Self {
+            stop,
+            join: Some(join),
+        })
+    }
+}
+
+impl Drop for HotplugMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.join.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_monitor_loop<F>(stop: Arc<AtomicBool>, on_change: F) -> Result<()>
+where
+    F: Fn(Vec<PathBuf>),
+{
+    // The socket is not Send, so it is created on the monitor thread
+    let socket = udev::MonitorBuilder::new()
+        .context("Failed to create udev monitor")?
+        .match_subsystem("hidraw")
+        .context("Failed to filter udev monitor")?
+        .match_subsystem("input")
+        .context("Failed to filter udev monitor")?
+        .listen()
+        .context("Failed to listen for udev events")?;
+
+    info!("Hotplug monitor: watching hidraw/input events");
+
+    let mut last_event: Option<Instant> = None;
+    let mut removed: Vec<PathBuf> = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        let mut fds = libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut fds, 1, POLL_INTERVAL_MS) };
+
+        if ready > 0 {
+            for event in socket.iter() {
+                let devpath = event.devpath().to_string_lossy().into_owned();
+                let Some(product_id) = razer_product_from_devpath(&devpath) else {
+                    continue;
+                };
+                match event.event_type() {
+                    // A dongle reconnect rebinds the interfaces
+                    udev::EventType::Add | udev::EventType::Remove | udev::EventType::Bind => {
+                        debug!(
+                            "Hotplug: {:?} {:#06x} {}",
+                            event.event_type(),
+                            product_id,
+                            event.sysname().to_string_lossy()
+                        );
+                        if event.event_type() == udev::EventType::Remove {
+                            removed.extend(event.devnode().map(PathBuf::from));
+                        }
+                        last_event = Some(Instant::now());
+                    }
+                    _ => {}
+                }
+            }
+        } else if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err).context("Failed to poll udev monitor");
+            }
+        }
+
+        if last_event.is_some_and(|t| t.elapsed() >= SETTLE_TIME) {
+            last_event = None;
+            info!("Hotplug: Razer devices changed");
+            on_change(std::mem::take(&mut removed));
+        }
+    }
+
+    info!("Hotplug monitor: shutting down");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_razer_product_from_devpath() {
+        let hidraw = "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:1532:0067.0005/hidraw/hidraw3";
+        assert_eq!(razer_product_from_devpath(hidraw), Some(0x0067));
+
+        let event = "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.2/0003:1532:0067.0007/input/input21/event9";
+        assert_eq!(razer_product_from_devpath(event), Some(0x0067));
+
+        // Other vendors and our own uinput devices
+        let logitech = "/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/0003:046D:C52B.0001/hidraw/hidraw0";
+        assert_eq!(razer_product_from_devpath(logitech), None);
+        assert_eq!(razer_product_from_devpath("/devices/virtual/input/input30/event20"), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! Software Lighting Engine
+//!
+//! Hardware effects are limited to what the firmware offers. This module renders
+//! effects on the host instead and streams them to the mouse as extended matrix
+//! custom frames (see `RazerDevice::set_custom_frame`), so the mouse color can
+//! follow application state: the current DPI, an active macro recording, etc.
+//!
+//! The engine runs in its own thread with its own device handle and is
+//! controlled through an mpsc channel, like the autoscroll overlay.
+
+use crate::device::{DeviceInfo, RazerDevice, RetryPolicy};
+use crate::protocol::Rgb;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Time between rendered frames (10 fps).
+/// Every frame costs two HID round-trips, so faster rates only add latency.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Blink period while a macro is being recorded
+const MACRO_BLINK_PERIOD: Duration = Duration::from_millis(500);
+
+/// Blink color while a macro is being recorded
+const MACRO_BLINK_COLOR: Rgb = Rgb::new(255, 0, 0);
+
+/// Give up after this many consecutive failed frames (device unplugged)
+const MAX_FRAME_ERRORS: u32 = 20;
+
+fn default_period_ms() -> u32 {
+    3000
+}
+
+/// Color used while the DPI is at or above `dpi`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DpiColor {
+    pub dpi: u16,
+    pub color: Rgb,
+}
+
+/// An effect rendered in software
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SoftwareEffect {
+    /// Gradient across the LEDs, scrolling once per `period_ms` (0 = still)
+    Gradient {
+        from: Rgb,
+        to: Rgb,
+        #[serde(default)]
+        period_ms: u32,
+    },
+    /// Breathing curve, moving to the next color after every breath
+    Breathing {
+        colors: Vec<Rgb>,
+        #[serde(default = "default_period_ms")]
+        period_ms: u32,
+    },
+    /// Solid color chosen by the current DPI
+    DpiStages { stages: Vec<DpiColor> },
+}
+
+impl SoftwareEffect {
+    /// Built-in presets, used by the UI and the tray IPC
+    pub fn preset(name: &str, color: Rgb) -> Option<Self> {
+        match name {
+            "gradient" => Some(SoftwareEffect::Gradient {
+                from: color,
+                to: Rgb::new(255 - color.r, 255 - color.g, 255 - color.b),
+                period_ms: default_period_ms(),
+            }),
+            "breathing" => Some(SoftwareEffect::Breathing {
+                colors: vec![color],
+                period_ms: default_period_ms(),
+            }),
+            "dpi" => Some(SoftwareEffect::DpiStages {
+                stages: vec![
+                    DpiColor { dpi: 0, color: Rgb::new(255, 0, 0) },
+                    DpiColor { dpi: 800, color: Rgb::new(255, 255, 0) },
+                    DpiColor { dpi: 1600, color: Rgb::new(0, 255, 0) },
+                    DpiColor { dpi: 3200, color: Rgb::new(0, 255, 255) },
+                    DpiColor { dpi: 6400, color: Rgb::new(0, 0, 255) },
+                ],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Human-readable effect name
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoftwareEffect::Gradient { .. } => "Gradient",
+            SoftwareEffect::Breathing { .. } => "Breathing",
+            SoftwareEffect::DpiStages { .. } => "DPI Colors",
+        }
+    }
+}
+
+/// Commands sent to the lighting thread
+#[derive(Debug)]
+pub enum LightingCommand {
+    /// Switch to another effect
+    SetEffect(SoftwareEffect),
+    /// Current DPI changed (for DPI stage colors)
+    Dpi(u16),
+    /// Blink while a macro is being recorded
+    MacroRecording(bool),
+    /// Shutdown the lighting thread
+    Shutdown,
+}
+
+/// Everything a frame depends on besides time
+#[derive(Debug, Clone)]
+pub struct RenderState {
+    pub effect: SoftwareEffect,
+    pub dpi: u16,
+    pub macro_recording: bool,
+}
+
+fn lerp(a: Rgb, b: Rgb, t: f32) -> Rgb {
+    let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    Rgb::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+fn scale(color: Rgb, factor: f32) -> Rgb {
+    lerp(Rgb::default(), color, factor)
+}
+
+/// Render one frame of `leds` colors at `elapsed` time since the effect started
+pub fn render_frame(state: &RenderState, elapsed: Duration, leds: usize) -> Vec<Rgb> {
+    let ms = elapsed.as_millis() as u64;
+
+    if state.macro_recording {
+        let period = MACRO_BLINK_PERIOD.as_millis() as u64;
+        let on = (ms / period).is_multiple_of(2);
+        let color = if on { MACRO_BLINK_COLOR } else { Rgb::default() };
+        return vec![color; leds];
+    }
+
+    match &state.effect {
+        SoftwareEffect::Gradient { from, to, period_ms } => {
+            (0..leds)
+                .map(|i| {
+                    let t = if *period_ms == 0 {
+                        // Still: first LED is `from`, last LED is `to`
+                        if leds > 1 { i as f32 / (leds - 1) as f32 } else { 0.0 }
+                    } else {
+                        // Scrolling: triangle wave so the gradient wraps without a seam
+                        let offset = (ms % *period_ms as u64) as f32 / *period_ms as f32;
+                        let pos = (i as f32 / leds as f32 + offset).fract();
+                        1.0 - (2.0 * pos - 1.0).abs()
+                    };
+                    lerp(*from, *to, t)
+                })
+                .collect()
+        }
+        SoftwareEffect::Breathing { colors, period_ms } => {
+            if colors.is_empty() {
+                return vec![Rgb::default(); leds];
+            }
+            let period = (*period_ms).max(1) as u64;
+            let color = colors[((ms / period) as usize) % colors.len()];
+            let phase = (ms % period) as f32 / period as f32;
+            let level = (1.0 - (2.0 * PI * phase).cos()) / 2.0;
+            vec![scale(color, level); leds]
+        }
+        SoftwareEffect::DpiStages { stages } => {
+            let color = stages
+                .iter()
+                .filter(|s| s.dpi <= state.dpi)
+                .max_by_key(|s| s.dpi)
+                .or_else(|| stages.iter().min_by_key(|s| s.dpi))
+                .map(|s| s.color)
+                .unwrap_or_default();
+            vec![color; leds]
+        }
+    }
+}
+
+/// Handle to control the lighting engine thread
+pub struct LightingEngine {
+    sender: Sender<LightingCommand>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl LightingEngine {
+    /// Start rendering `effect` on the given device
+    pub fn start(info: DeviceInfo, effect: SoftwareEffect, dpi: u16) -> Result<Self> {
+        let model = info
+            .model
+            .ok_or_else(|| anyhow!("Unsupported Razer device (PID {:#06x})", info.product_id))?;
+        if model.matrix.is_none() {
+            return Err(anyhow!("{} does not support per-LED custom frames", model.name));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let state = RenderState { effect, dpi, macro_recording: false };
+
+        let thread = thread::spawn(move || {
+            if let Err(e) = run_lighting_loop(info, state, rx) {
+                error!("Lighting engine stopped: {:#}", e);
+            }
+        });
+
+        Ok(Self {
+            sender: tx,
+            thread: Some(thread),
+        })
+    }
+
+    /// Switch to another effect
+    pub fn set_effect(&self, effect: SoftwareEffect) {
+        let _ = self.sender.send(LightingCommand::SetEffect(effect));
+    }
+
+    /// Report the current DPI
+    pub fn set_dpi(&self, dpi: u16) {
+        let _ = self.sender.send(LightingCommand::Dpi(dpi));
+    }
+
+    /// Blink while a macro is being recorded
+    pub fn set_macro_recording(&self, recording: bool) {
+        let _ = self.sender.send(LightingCommand::MacroRecording(recording));
+    }
+}
+
+impl Drop for LightingEngine {
+    fn drop(&mut self) {
+        let _ = self.sender.send(LightingCommand::Shutdown);
+        if let Some(handle) = self.thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_lighting_loop(
+    info: DeviceInfo,
+    mut state: RenderState,
+    rx: Receiver<LightingCommand>,
+) -> Result<()> {
+    let mut device = RazerDevice::open(&info).context("Failed to open device for lighting")?;
+    // A frame that can't be sent in time is superseded by the next one anyway
+    device.set_retry_policy(RetryPolicy {
+        timeout: FRAME_INTERVAL,
+        ..RetryPolicy::default()
+    });
+    let (rows, cols) = device
+        .model()
+        .matrix
+        .ok_or_else(|| anyhow!("Device has no LED matrix"))?;
+
+    info!("Lighting engine started: {}", state.effect.name());
+
+    let mut started = Instant::now();
+    let mut last_frame: Option<Vec<Rgb>> = None;
+    let mut errors = 0;
+
+    loop {
+        // Render and upload the frame, skipping unchanged frames
+        let frame = render_frame(&state, started.elapsed(), cols as usize);
+        if last_frame.as_ref() != Some(&frame) {
+            let result = (0..rows)
+                .try_for_each(|row| device.set_custom_frame(row, &frame))
+                .and_then(|_| device.display_custom_frame());
+            match result {
+                Ok(()) => {
+                    errors = 0;
+                    last_frame = Some(frame);
+                }
+                Err(e) => {
+                    errors += 1;
+                    warn!("Failed to send lighting frame: {:#}", e);
+                    if errors >= MAX_FRAME_ERRORS {
+                        return Err(anyhow::Error::from(e).context("Too many failed lighting frames"));
+                    }
+                }
+            }
+        }
+
+        // Wait for the next frame, handling commands as they arrive
+        let deadline = Instant::now() + FRAME_INTERVAL;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(timeout) {
+                Ok(LightingCommand::SetEffect(effect)) => {
+                    info!("Lighting effect: {}", effect.name());
+                    state.effect = effect;
+                    started = Instant::now();
+                }
+                Ok(LightingCommand::Dpi(dpi)) => state.dpi = dpi,
+                Ok(LightingCommand::MacroRecording(recording)) => {
+                    state.macro_recording = recording;
+                    started = Instant::now();
+                }
+                Ok(LightingCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                    info!("Lighting engine stopped");
+                    return Ok(());
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(effect: SoftwareEffect) -> RenderState {
+        RenderState { effect, dpi: 800, macro_recording: false }
+    }
+
+    #[test]
+    fn test_still_gradient_endpoints() {
+        let black = Rgb::new(0, 0, 0);
+        let white = Rgb::new(255, 255, 255);
+        let effect = SoftwareEffect::Gradient { from: black, to: white, period_ms: 0 };
+
+        let frame = render_frame(&state(effect), Duration::ZERO, 3);
+        assert_eq!(frame, vec![black, Rgb::new(128, 128, 128), white]);
+    }
+
+    #[test]
+    fn test_breathing_curve() {
+        let green = Rgb::new(0, 255, 0);
+        let effect = SoftwareEffect::Breathing { colors: vec![green], period_ms: 1000 };
+        let s = state(effect);
+
+        assert_eq!(render_frame(&s, Duration::ZERO, 1), vec![Rgb::default()]);
+        assert_eq!(render_frame(&s, Duration::from_millis(500), 1), vec![green]);
+    }
+
+    #[test]
+    fn test_dpi_stage_colors() {
+        let mut s = state(SoftwareEffect::preset("dpi", Rgb::default()).unwrap());
+
+        s.dpi = 400;
+        assert_eq!(render_frame(&s, Duration::ZERO, 2), vec![Rgb::new(255, 0, 0); 2]);
+        s.dpi = 1600;
+        assert_eq!(render_frame(&s, Duration::ZERO, 2), vec![Rgb::new(0, 255, 0); 2]);
+        s.dpi = 20000;
+        assert_eq!(render_frame(&s, Duration::ZERO, 2), vec![Rgb::new(0, 0, 255); 2]);
+    }
+
+    #[test]
+    fn test_macro_recording_blink() {
+        let mut s = state(SoftwareEffect::preset("breathing", Rgb::new(0, 0, 255)).unwrap());
+        s.macro_recording = true;
+
+        assert_eq!(render_frame(&s, Duration::ZERO, 1), vec![MACRO_BLINK_COLOR]);
+        assert_eq!(render_frame(&s, MACRO_BLINK_PERIOD, 1), vec![Rgb::default()]);
+    }
+}
@@ -3,9 +3,20 @@
 //! A userspace application for configuring Razer mice on Linux
 //! without requiring kernel drivers.
 
+mod battery;
+mod cli;
+mod control;
+mod daemon;
+mod dbus_service;
 mod device;
+mod devices;
+mod error;
+mod focus;
 mod hidpoll;
+mod hotplug;
+mod lighting;
 mod macro_engine;
+mod models;
 mod overlay;
 mod profile;
 mod protocol;
@@ -15,7 +26,9 @@ mod tray;
 mod tray_helper;
 
 use anyhow::Result;
-use profile::{Profile, ProfileManager};
+use models::LedZone;
+use profile::{LightingSettings, Profile, ProfileManager, ZoneColor};
+use protocol::{LedEffect, Rgb, WaveDirection};
 use settings::AppSettings;
 use std::cell::RefCell;
 use std::collections::BTreeMap;
@@ -27,11 +40,21 @@ use tracing::{error, info, warn};
 slint::include_modules!();
 
 fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    // Command-line subcommands keep stdout for their output and only log warnings
+    if cli::is_subcommand(&args[1..]) {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_max_level(tracing::Level::WARN)
+            .init();
+        return cli::run(&args[1..]);
+    }
+
     // Initialize logging
     tracing_subscriber::fmt::init();
 
     // Check if we should run as the tray helper (user-space process for system tray)
-    let args: Vec<String> = env::args().collect();
     if args.iter().any(|a| a == "--tray-helper") {
         info!("Starting as tray helper...");
         return tray_helper::run_tray_helper();
@@ -62,6 +85,12 @@ fn main() -> Result<()> {
         warn!("Failed to ensure default profile: {}", e);
     }
 
+    // Headless mode: remap and apply profiles without any window
+    if args.iter().any(|a| a == "--daemon") {
+        info!("Starting as daemon (no window)...");
+        return daemon::run_daemon();
+    }
+
     // Log all detected Razer input interfaces for debugging
     let interfaces = remap::list_razer_input_interfaces();
     if interfaces.is_empty() {
@@ -100,36 +129,68 @@ fn main() -> Result<()> {
     // Macro manager for recording and playback
     let macro_manager: Rc<RefCell<macro_engine::MacroManager>> = Rc::new(RefCell::new(macro_engine::MacroManager::new()));
 
-    // Try to find and connect to device on startup
-    connect_device(&main_window, &device);
+    // Software lighting engine - streams rendered frames to the mouse LEDs
+    let lighting_engine: Rc<RefCell<Option<lighting::LightingEngine>>> = Rc::new(RefCell::new(None));
+
+    // Every connected device; the selected one's state lives in the slots above
+    let devices: Rc<RefCell<devices::DeviceSet>> = Rc::new(RefCell::new(devices::DeviceSet::new()));
+    let slots = DeviceSlots {
+        device: device.clone(),
+        remapper: remapper.clone(),
+        mappings: remap_mappings.clone(),
+        layers: Rc::new(RefCell::new(Vec::new())),
+        dpi_poller: dpi_poller.clone(),
+        autoscroll: autoscroll_enabled.clone(),
+        overlay: autoscroll_overlay.clone(),
+        macros: macro_manager.clone(),
+        lighting: lighting_engine.clone(),
+    };
 
-    // Clone refs for use after setup_callbacks (which takes ownership)
-    let remapper_for_startup = remapper.clone();
-    let dpi_poller_for_startup = dpi_poller.clone();
-    let autoscroll_for_startup = autoscroll_enabled.clone();
-    let overlay_for_startup = autoscroll_overlay.clone();
+    // Try to find and connect to devices on startup
+    scan_devices(&main_window, &slots, &devices, &[]);
 
     // Setup callbacks
-    setup_callbacks(&main_window, device.clone(), remapper, remap_mappings.clone(), dpi_poller, autoscroll_enabled, autoscroll_overlay, macro_manager.clone());
+    setup_callbacks(&main_window, &devices, &slots);
     
-    // Load default profile on startup if configured
+    // Load each device's startup profile (the default profile unless one was
+    // set for that device); devices the window does not show get theirs in
+    // their session. Without a device, the window still shows the profile.
     if let Ok(settings) = AppSettings::load() {
-        if !settings.default_profile.is_empty() {
-            info!("Loading default profile on startup: {}", settings.default_profile);
-            load_profile_on_startup(
-                &main_window, 
-                &device, 
-                &remap_mappings, 
-                &macro_manager,
-                &remapper_for_startup,
-                &dpi_poller_for_startup,
-                &autoscroll_for_startup,
-                &overlay_for_startup,
-                &settings.default_profile
-            );
+        let keys: Vec<String> = devices.borrow().sessions().map(|s| s.identity.key()).collect();
+        let selected = devices.borrow().selected_index().unwrap_or(0);
+        let keys = if keys.is_empty() { vec![String::new()] } else { keys };
+        for (index, key) in keys.iter().enumerate() {
+            let profile_name = settings.profile_for_device(key).to_string();
+            if profile_name.is_empty() {
+                continue;
+            }
+            info!("Loading profile on startup: {}", profile_name);
+            if index == selected {
+                load_selected_device_profile(&main_window, &slots, &devices, &profile_name);
+            } else if let Some(session) = devices.borrow_mut().get_mut(index) {
+                load_parked_device_profile(&main_window.as_weak(), session, &profile_name);
+            }
         }
+        show_device_list(&main_window, &devices.borrow());
     }
 
+    // Watch for mice being plugged in or removed - MUST keep the monitor alive
+    let window_weak = main_window.as_weak();
+    let _hotplug = hotplug::HotplugMonitor::start(move |removed| {
+        let window_weak = window_weak.clone();
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(win) = window_weak.upgrade() {
+                let removed: Vec<slint::SharedString> = removed
+                    .iter()
+                    .map(|node| node.to_string_lossy().as_ref().into())
+                    .collect();
+                win.invoke_devices_changed(slint::ModelRc::new(slint::VecModel::from(removed)));
+            }
+        });
+    })
+    .map_err(|e| warn!("Hotplug monitoring unavailable: {}", e))
+    .ok();
+
     // Connect to the user-space tray helper process (which runs as the user and can show the tray icon)
     // The tray helper is started by the launcher script before running pkexec
     let tray_client = Rc::new(RefCell::new(tray_helper::TrayClient::connect()));
@@ -146,12 +207,32 @@ fn main() -> Result<()> {
     let _tray_timer = if tray_connected {
         let window_weak = main_window.as_weak();
         let client_clone = Rc::clone(&tray_client);
+        let slots_for_tray = slots.clone();
+        let devices_for_tray = devices.clone();
+        let last_battery: RefCell<Option<(i32, bool, i32)>> = RefCell::new(None);
         let timer = slint::Timer::default();
         timer.start(
             slint::TimerMode::Repeated,
             Duration::from_millis(100),
             move || {
                 if let Ok(mut c) = client_clone.try_borrow_mut() {
+                    // Forward battery changes to the tray tooltip/icon
+                    if let Some(window) = window_weak.upgrade() {
+                        let battery = (
+                            window.get_battery_level(),
+                            window.get_battery_charging(),
+                            window.get_low_battery_threshold(),
+                        );
+                        if battery.0 >= 0 && *last_battery.borrow() != Some(battery) {
+                            c.send(&tray_helper::IpcCommand::Battery {
+                                level: battery.0 as u8,
+                                charging: battery.1,
+                                threshold: battery.2.clamp(0, 100) as u8,
+                            });
+                            *last_battery.borrow_mut() = Some(battery);
+                        }
+                    }
+
                     while let Some(cmd) = c.try_recv() {
                         println!("MAIN APP: Received command: {:?}", cmd);
                         match cmd {
@@ -169,6 +250,16 @@ fn main() -> Result<()> {
                             tray_helper::IpcCommand::Quit => {
                                 slint::quit_event_loop().ok();
                             }
+                            tray_helper::IpcCommand::Lighting(preset) => {
+                                if let Some(window) = window_weak.upgrade() {
+                                    let index = software_effect_index(&preset);
+                                    window.set_software_effect(index);
+                                    let effect = software_effect_from_ui(&window);
+                                    if let Err(e) = update_software_lighting(&window, &slots_for_tray, &devices_for_tray, effect) {
+                                        window.set_status_message(format!("Error: {}", e).into());
+                                    }
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -180,6 +271,62 @@ fn main() -> Result<()> {
         None
     };
 
+    // Answer scripts and keybindings on the control socket, and desktop
+    // widgets on D-Bus - MUST store the timer to keep it alive
+    let requests = control::RequestQueue::new();
+    let control_server = control::ControlServer::start(requests.client())
+        .map_err(|e| warn!("Control socket unavailable: {:#}", e))
+        .ok();
+    let dbus_service = dbus_service::DbusService::start(requests.client())
+        .map_err(|e| warn!("D-Bus service unavailable: {:#}", e))
+        .ok();
+    // Switch profiles as applications get the focus, when the settings have
+    // rules for them; the control timer loads them
+    let (focus_tx, focus_rx) = std::sync::mpsc::channel();
+    let app_settings = AppSettings::load().unwrap_or_default();
+    let _focus_watcher = (!app_settings.app_profiles.is_empty()).then(|| {
+        focus::FocusWatcher::start(move |window| {
+            let _ = focus_tx.send(window);
+        })
+    });
+    let app_profiles = RefCell::new(focus::AppProfileSwitcher::new(app_settings));
+    let _control_timer = {
+        let window_weak = main_window.as_weak();
+        let slots = slots.clone();
+        let devices = devices.clone();
+        let states = control_device_states(&main_window, &slots, &devices.borrow());
+        if let Some(service) = dbus_service.as_ref() {
+            service.update(states.clone());
+        }
+        let last_states = RefCell::new(states);
+        let timer = slint::Timer::default();
+        timer.start(slint::TimerMode::Repeated, Duration::from_millis(50), move || {
+            let Some(win) = window_weak.upgrade() else { return };
+            while let Some(request) = requests.try_recv() {
+                let result = handle_control_request(&win, &slots, &devices, request.command.clone());
+                request.respond(result);
+            }
+            while let Ok(focused) = focus_rx.try_recv() {
+                switch_app_profiles(&win, &slots, &devices, &mut app_profiles.borrow_mut(), &focused);
+            }
+            // Report changes made in the window, by DPI buttons and by hotplug too
+            let states = control_device_states(&win, &slots, &devices.borrow());
+            if states == *last_states.borrow() {
+                return;
+            }
+            if let Some(server) = control_server.as_ref() {
+                for event in control::changes(&last_states.borrow(), &states) {
+                    server.publish(&event);
+                }
+            }
+            if let Some(service) = dbus_service.as_ref() {
+                service.update(states.clone());
+            }
+            *last_states.borrow_mut() = states;
+        });
+        timer
+    };
+
     // When user clicks X to close the window, check minimize_to_tray setting
     // If tray connected AND minimize_to_tray enabled, hide window. Otherwise quit.
     if tray_connected {
@@ -236,6 +383,7 @@ fn main() -> Result<()> {
 fn auto_save_default_profile(
     window: &MainWindow,
     remap_mappings: &Rc<RefCell<BTreeMap<u16, remap::MappingTarget>>>,
+    layers: &Rc<RefCell<Vec<profile::RemapLayer>>>,
     macro_manager: &Rc<RefCell<macro_engine::MacroManager>>,
 ) {
     let dpi_x = window.get_current_dpi_x() as u16;
@@ -243,8 +391,15 @@ fn auto_save_default_profile(
     
     let mut profile = Profile::from_device_settings("Default", dpi_x, dpi_y);
     profile.description = "Auto-saved default profile".to_string();
+    profile.polling_rate = window.get_polling_rate() as u16;
+    profile.brightness = window.get_brightness() as u8;
+    profile.lighting = lighting_from_ui(window);
+    profile.power = power_from_ui(window);
+    profile.dpi.dpi_stages = dpi_stages_from_ui(window);
+    profile.dpi.active_stage = window.get_active_dpi_stage().max(0) as usize;
     profile.remap.enabled = window.get_remap_enabled();
     profile.remap.autoscroll = window.get_autoscroll_enabled();
+    profile.remap.dpi_button_keys = !window.get_dpi_buttons_cycle();
     profile.remap.mappings = remap_mappings
         .borrow()
         .iter()
@@ -258,6 +413,7 @@ fn auto_save_default_profile(
             macro_id: None,
         })
         .collect();
+    profile.remap.layers = layers.borrow().clone();
     
     // Include macros
     profile.macros = macro_manager.borrow().export_for_profile();
@@ -276,109 +432,842 @@ fn auto_save_default_profile(
     }
 }
 
-fn connect_device(window: &MainWindow, device: &Rc<RefCell<Option<device::RazerDevice>>>) {
-    match device::find_naga_trinity() {
-        Ok(Some(device_info)) => {
-            info!("Found Razer Naga Trinity at {}", device_info.path);
-
-            match device::RazerDevice::open(&device_info.path) {
-                Ok(mut dev) => {
-                    info!("Device opened successfully!");
-
-                    // Check and log device mode
-                    match dev.get_device_mode() {
-                        Ok((mode, param)) => {
-                            info!("Device mode: {:#04x}, param: {:#04x}", mode, param);
-                            // Mode 0x00 = Normal (hardware handles buttons)
-                            // Mode 0x03 = Driver mode (software handles buttons - side buttons send keyboard keys)
-                            if mode == 0x00 {
-                                info!("Device is in Normal mode");
-                            } else if mode == 0x03 {
-                                info!("Device is in Driver mode - restoring Normal mode on startup");
-                                // Ensure we're in Normal mode on startup for clean state
-                                if let Err(e) = dev.disable_driver_mode() {
-                                    warn!("Failed to restore Normal mode: {}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to get device mode: {} (this may be normal)", e);
-                        }
-                    }
+/// Handles of the selected device that the callbacks share. Selecting another
+/// device swaps their contents with that device's `DeviceSession`.
+#[derive(Clone, Default)]
+struct DeviceSlots {
+    device: Rc<RefCell<Option<device::RazerDevice>>>,
+    remapper: Rc<RefCell<Option<remap::Remapper>>>,
+    mappings: Rc<RefCell<BTreeMap<u16, remap::MappingTarget>>>,
+    layers: Rc<RefCell<Vec<profile::RemapLayer>>>,
+    dpi_poller: Rc<RefCell<Option<hidpoll::DpiButtonPoller>>>,
+    autoscroll: Rc<RefCell<bool>>,
+    overlay: Rc<RefCell<Option<overlay::AutoscrollOverlay>>>,
+    macros: Rc<RefCell<macro_engine::MacroManager>>,
+    lighting: Rc<RefCell<Option<lighting::LightingEngine>>>,
+}
 
-                    // Update UI with device info
-                    window.set_device_name(device_info.product.into());
-                    window.set_device_connected(true);
-                    window.set_status_message("Connected".into());
+impl DeviceSlots {
+    /// Exchange the selected device's runtime state with a session
+    fn swap(&self, session: &mut devices::DeviceSession) {
+        std::mem::swap(&mut *self.device.borrow_mut(), &mut session.device);
+        std::mem::swap(&mut *self.remapper.borrow_mut(), &mut session.remapper);
+        std::mem::swap(&mut *self.mappings.borrow_mut(), &mut session.mappings);
+        std::mem::swap(&mut *self.layers.borrow_mut(), &mut session.layers);
+        std::mem::swap(&mut *self.dpi_poller.borrow_mut(), &mut session.dpi_poller);
+        std::mem::swap(&mut *self.autoscroll.borrow_mut(), &mut session.autoscroll);
+        std::mem::swap(&mut *self.overlay.borrow_mut(), &mut session.overlay);
+        std::mem::swap(&mut *self.macros.borrow_mut(), &mut session.macros);
+        std::mem::swap(&mut *self.lighting.borrow_mut(), &mut session.lighting);
+    }
 
-                    // Read firmware version
-                    match dev.get_firmware_version() {
-                        Ok(version) => window.set_firmware_version(version.into()),
-                        Err(_) => window.set_firmware_version("-".into()),
-                    }
+    /// Run `f` on the selected device's session with the runtime state
+    /// moved into it, so the session's methods work on the selected device.
+    /// Returns None if no device is selected.
+    fn with_selected<T>(
+        &self,
+        devices: &Rc<RefCell<devices::DeviceSet>>,
+        f: impl FnOnce(&mut devices::DeviceSession) -> T,
+    ) -> Option<T> {
+        let mut set = devices.borrow_mut();
+        let session = set.selected_mut()?;
+        self.swap(session);
+        let result = f(session);
+        self.swap(session);
+        Some(result)
+    }
+}
 
-                    // Read current DPI
-                    match dev.get_dpi() {
-                        Ok((dpi_x, dpi_y)) => {
-                            info!("Current DPI: {}x{}", dpi_x, dpi_y);
-                            window.set_current_dpi_x(dpi_x as i32);
-                            window.set_current_dpi_y(dpi_y as i32);
-                        }
-                        Err(e) => {
-                            warn!("Failed to read DPI: {}", e);
-                        }
+/// Read a device's settings into the window
+fn show_device_state(window: &MainWindow, dev: &mut device::RazerDevice) -> device::DeviceIdentity {
+    window.set_device_name(dev.info().display_name().into());
+    apply_model_to_ui(window, dev.model());
+    window.set_device_connected(true);
+    window.set_status_message("Connected".into());
+
+    // Read serial, firmware and mode for the About view
+    let identity = dev.identity();
+    info!("Serial: {}", identity.serial.as_deref().unwrap_or("unknown"));
+    show_device_identity(window, Some(&identity));
+
+    // Read current DPI
+    match dev.get_dpi() {
+        Ok((dpi_x, dpi_y)) => {
+            info!("Current DPI: {}x{}", dpi_x, dpi_y);
+            window.set_current_dpi_x(dpi_x as i32);
+            window.set_current_dpi_y(dpi_y as i32);
+        }
+        Err(e) => {
+            warn!("Failed to read DPI: {}", e);
+        }
+    }
+
+    if dev.model().supports(models::Feature::DpiStages) {
+        match dev.get_dpi_stages() {
+            Ok((stages, active)) => {
+                info!("DPI stages: {:?} (active {})", stages, active + 1);
+                show_dpi_stages(window, &stages, active);
+            }
+            Err(e) => warn!("Failed to read DPI stages: {}", e),
+        }
+    }
+
+    match dev.get_polling_rate() {
+        Ok(rate) => {
+            info!("Current polling rate: {} Hz", rate);
+            window.set_polling_rate(rate as i32);
+        }
+        Err(e) => {
+            warn!("Failed to read polling rate: {}", e);
+        }
+    }
+
+    if let Some(&zone) = dev.model().led_zones.first() {
+        match dev.get_led_brightness(zone) {
+            Ok(brightness) => window.set_brightness(brightness as i32),
+            Err(e) => warn!("Failed to read brightness: {}", e),
+        }
+    }
+
+    // Devices that keep power settings report them; others get
+    // them from the profile loaded after connecting
+    if dev.model().supports(models::Feature::Battery) && dev.model().stores_power_settings {
+        match dev.get_idle_time() {
+            Ok(seconds) => window.set_idle_timeout(seconds as i32),
+            Err(e) => warn!("Failed to read idle timeout: {}", e),
+        }
+        match dev.get_low_battery_threshold() {
+            Ok(percent) => window.set_low_battery_threshold(percent as i32),
+            Err(e) => warn!("Failed to read low-battery threshold: {}", e),
+        }
+    }
+
+    identity
+}
+
+/// Find connected devices, open new ones and forget unplugged ones, as well
+/// as devices that lost one of the `gone` nodes. The selected device is
+/// reopened and shown; if it was unplugged, the first remaining device is
+/// selected instead. Returns the hidraw paths of the newly opened devices.
+fn scan_devices(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    gone: &[std::path::PathBuf],
+) -> Vec<String> {
+    let found = match device::find_razer_devices() {
+        Ok(found) => found,
+        Err(e) => {
+            error!("Error scanning for devices: {}", e);
+            window.set_status_message(format!("Scan error: {}", e.user_message()).into());
+            return Vec::new();
+        }
+    };
+
+    // The selected device's workers are stopped with its session if it was unplugged
+    // If it is being remapped, it waits to be reattached with the settings shown
+    let mut set = devices.borrow_mut();
+    if let Some(session) = set.selected_mut() {
+        session.ui = Some(parked_ui_from_window(window));
+        slots.swap(session);
+    }
+    let (new, selected_removed) = set.retain_found(&found, gone);
+    if let Some(session) = set.selected_mut() {
+        slots.swap(session);
+    }
+    if selected_removed {
+        info!("Selected device was unplugged");
+        window.set_remap_enabled(false);
+    }
+
+    // Reopen the selected device; the old handle is stale after a replug
+    *slots.device.borrow_mut() = None;
+    let mut open_error = None;
+    if let Some(session) = set.selected() {
+        let remapping = slots.remapper.borrow().is_some();
+        match device::open_device(&session.info, remapping) {
+            Ok(dev) => *slots.device.borrow_mut() = Some(dev),
+            Err(e) => open_error = Some(e),
+        }
+    }
+
+    let mut opened = Vec::new();
+    for info in new {
+        match device::open_device(&info, false) {
+            Ok(mut dev) => {
+                let identity = dev.identity();
+                opened.push(info.path.clone());
+                let session = match set.take_unplugged(&identity.key()) {
+                    Some(mut session) => {
+                        let events = worker_events(&window.as_weak(), &info.path);
+                        session.reattach(info, identity, dev, events);
+                        session
+                    }
+                    None => {
+                        let mut session = devices::DeviceSession::new(info, identity);
+                        session.device = Some(dev);
+                        session
                     }
+                };
+                set.push(session);
+            }
+            Err(e) => {
+                error!("Failed to open device: {}", e);
+                open_error = Some(e);
+            }
+        }
+    }
 
-                    // Store device handle
-                    *device.borrow_mut() = Some(dev);
-                }
-                Err(e) => {
-                    error!("Failed to open device: {}", e);
-                    window.set_status_message(format!("Error: {}", e).into());
-                }
+    if set.selected_index().is_none() && !set.is_empty() {
+        set.select(0);
+        if let Some(session) = set.selected_mut() {
+            slots.swap(session);
+        }
+    }
+    start_battery_pollers(window, &mut set);
+    show_battery(window, set.selected());
+
+    let identity = slots.device.borrow_mut().as_mut().map(|dev| show_device_state(window, dev));
+    match identity {
+        Some(identity) => {
+            if let Some(session) = set.selected_mut() {
+                session.identity = identity;
             }
         }
-        Ok(None) => {
-            info!("No Razer Naga Trinity found");
+        None => {
             window.set_device_name("No device found".into());
             window.set_device_connected(false);
-            window.set_status_message("Plug in your Razer mouse".into());
+            show_device_identity(window, None);
+            match open_error {
+                Some(e) => window.set_status_message(format!("Error: {}", e.user_message()).into()),
+                None => {
+                    info!("No supported Razer device found");
+                    window.set_status_message("Plug in your Razer mouse".into());
+                }
+            }
         }
-        Err(e) => {
-            error!("Error scanning for devices: {}", e);
-            window.set_status_message(format!("Scan error: {}", e).into());
+    }
+    show_device_list(window, &set);
+    opened
+}
+
+/// Show another device in the window: park the selected device's state in
+/// its session and move the chosen device's state into the slots
+fn select_device(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    index: usize,
+) {
+    let mut set = devices.borrow_mut();
+    if set.selected_index() == Some(index) || index >= set.len() {
+        return;
+    }
+
+    if let Some(session) = set.selected_mut() {
+        session.ui = Some(parked_ui_from_window(window));
+        slots.swap(session);
+    }
+    set.select(index);
+    let Some(session) = set.selected_mut() else { return };
+    slots.swap(session);
+    info!("Selected {} at {}", session.identity.name, session.info.path);
+
+    match session.ui.clone() {
+        Some(ui) => {
+            if let Some(model) = session.info.model {
+                apply_model_to_ui(window, model);
+            }
+            window.set_device_name(session.info.display_name().into());
+            window.set_device_connected(slots.device.borrow().is_some());
+            show_device_identity(window, Some(&session.identity));
+            show_parked_ui(window, &ui);
+        }
+        None => {
+            if let Some(dev) = slots.device.borrow_mut().as_mut() {
+                session.identity = show_device_state(window, dev);
+            }
         }
     }
+
+    // Remapping state of this device
+    window.set_remap_enabled(slots.remapper.borrow().is_some());
+    window.set_remap_waiting(slots.remapper.borrow().as_ref().is_some_and(|r| r.is_waiting()));
+    window.set_autoscroll_enabled(*slots.autoscroll.borrow());
+    update_remap_summary(window, &slots.mappings.borrow());
+    {
+        let mgr = slots.macros.borrow();
+        window.set_macro_list_text(mgr.get_macros_list_text().into());
+        window.set_available_macros(mgr.get_available_macros_string().into());
+    }
+
+    if let Ok(settings) = AppSettings::load() {
+        window.set_default_profile(settings.profile_for_device(&session.identity.key()).into());
+    }
+    show_battery(window, Some(session));
+    window.set_status_message(format!("Selected {}", session.label()).into());
+    show_device_list(window, &set);
 }
 
-fn setup_callbacks(
+/// Device states for the control socket; the selected device's live in the
+/// window and the slots
+fn control_device_states(
     window: &MainWindow,
-    device: Rc<RefCell<Option<device::RazerDevice>>>,
-    remapper: Rc<RefCell<Option<remap::Remapper>>>,
-    remap_mappings: Rc<RefCell<BTreeMap<u16, remap::MappingTarget>>>,
-    dpi_poller: Rc<RefCell<Option<hidpoll::DpiButtonPoller>>>,
-    autoscroll_enabled: Rc<RefCell<bool>>,
-    autoscroll_overlay: Rc<RefCell<Option<overlay::AutoscrollOverlay>>>,
-    macro_manager: Rc<RefCell<macro_engine::MacroManager>>,
+    slots: &DeviceSlots,
+    set: &devices::DeviceSet,
+) -> Vec<control::DeviceStatus> {
+    let selected = set.selected_index();
+    set.sessions()
+        .enumerate()
+        .map(|(index, session)| {
+            let mut status = session.status();
+            if selected == Some(index) {
+                status.dpi = Some((window.get_current_dpi_x() as u16, window.get_current_dpi_y() as u16));
+                status.polling_rate = Some(window.get_polling_rate() as u16);
+                let remapper = slots.remapper.borrow();
+                status.remapping = remapper.is_some();
+                status.remap_waiting = remapper.as_ref().is_some_and(|r| r.is_waiting());
+                if status.remapping {
+                    status.mode = "driver".to_string();
+                }
+            }
+            status
+        })
+        .collect()
+}
+
+/// Answer a control socket request. Requests for the selected device go
+/// through the window; other devices are changed in their session, so the
+/// window keeps showing the device it shows.
+fn handle_control_request(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    command: control::Command,
+) -> Result<serde_json::Value> {
+    use control::Command;
+
+    match command {
+        Command::Status => {
+            let set = devices.borrow();
+            let selected = set.selected().map(|s| s.info.path.clone());
+            let status = control::Status::new("gui", selected, control_device_states(window, slots, &set));
+            return Ok(serde_json::to_value(status)?);
+        }
+        Command::ListDevices => {
+            return Ok(serde_json::to_value(control_device_states(window, slots, &devices.borrow()))?);
+        }
+        // Answered by the server
+        Command::Subscribe => return Ok(serde_json::Value::Null),
+        _ => {}
+    }
+
+    let Some(target) = parked_request_target(&devices.borrow(), &command)? else {
+        return run_control_command(window, slots, devices, command);
+    };
+    let result = run_parked_command(&window.as_weak(), devices, target, command);
+    show_device_list(window, &devices.borrow());
+    result
+}
+
+/// Session a control request is for, or None if it is for the selected device
+fn parked_request_target(set: &devices::DeviceSet, command: &control::Command) -> Result<Option<usize>> {
+    use anyhow::Context;
+
+    let target = set.resolve(command.device()).context("No such device")?;
+    Ok((set.selected_index() != Some(target)).then_some(target))
+}
+
+/// Load the profiles the `app_profiles` rules pick for the focused
+/// application; devices the window does not show get theirs in the background
+fn switch_app_profiles(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    apps: &mut focus::AppProfileSwitcher,
+    focused: &focus::FocusedWindow,
 ) {
+    // The rules and startup profiles may have been edited since
+    match AppSettings::load() {
+        Ok(settings) => apps.set_settings(settings),
+        Err(e) => warn!("Failed to reload settings, keeping the previous ones: {}", e),
+    }
+    let switches: Vec<(usize, String)> = devices
+        .borrow()
+        .sessions()
+        .enumerate()
+        .filter_map(|(index, session)| {
+            let profile = apps.switch_to(focused, &session.identity.key())?;
+            (session.active_profile.as_deref() != Some(profile.as_str())).then_some((index, profile))
+        })
+        .collect();
+    if switches.is_empty() {
+        return;
+    }
+
+    let selected = devices.borrow().selected_index();
+    for (index, profile) in switches {
+        if selected == Some(index) {
+            info!("{} has the focus, switching to '{}'", focused.class, profile);
+            match load_profile(window, slots, devices, &profile) {
+                Ok(failed) => show_profile_loaded(window, &profile, &failed),
+                Err(e) => warn!("Failed to load profile '{}': {:#}", profile, e),
+            }
+            continue;
+        }
+
+        let mut set = devices.borrow_mut();
+        let Some(session) = set.get_mut(index) else { continue };
+        info!("{} has the focus, switching {} to '{}'", focused.class, session.identity.name, profile);
+        load_parked_device_profile(&window.as_weak(), session, &profile);
+    }
+    show_device_list(window, &devices.borrow());
+}
+
+/// Run a control socket request on the selected device
+fn run_control_command(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    command: control::Command,
+) -> Result<serde_json::Value> {
+    use anyhow::Context;
+    use control::Command;
+    use serde_json::{Value, json};
+
+    match command {
+        Command::SetDpi { dpi, dpi_y, .. } => {
+            let dpi_y = dpi_y.unwrap_or(dpi);
+            slots
+                .device
+                .borrow_mut()
+                .as_mut()
+                .context("Device is not open")?
+                .set_dpi(dpi, dpi_y)
+                .map_err(|e| anyhow::anyhow!(e.user_message()))?;
+            window.set_current_dpi_x(dpi as i32);
+            window.set_current_dpi_y(dpi_y as i32);
+            if let Some(engine) = slots.lighting.borrow().as_ref() {
+                engine.set_dpi(dpi);
+            }
+            window.set_status_message(format!("DPI set to {}x{}", dpi, dpi_y).into());
+            Ok(Value::Null)
+        }
+        Command::SwitchProfile { profile, .. } => {
+            let failed = load_profile(window, slots, devices, &profile)?;
+            show_profile_loaded(window, &profile, &failed);
+            Ok(Value::Null)
+        }
+        Command::ToggleRemap { enabled, .. } => {
+            let enabled = enabled.unwrap_or(slots.remapper.borrow().is_none());
+            set_remapping(window, slots, devices, enabled)?;
+            Ok(json!({ "enabled": enabled }))
+        }
+        Command::RunMacro { target, .. } => {
+            let name = target.run(&slots.macros.borrow())?;
+            window.set_status_message(format!("Running macro '{}'", name).into());
+            Ok(json!({ "name": name }))
+        }
+        Command::Status | Command::ListDevices | Command::Subscribe => Ok(Value::Null),
+    }
+}
+
+/// Run a control socket request on a device that is not selected, without
+/// touching the window or the selection
+fn run_parked_command(
+    window: &slint::Weak<MainWindow>,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    index: usize,
+    command: control::Command,
+) -> Result<serde_json::Value> {
+    use anyhow::Context;
+    use control::Command;
+    use serde_json::{Value, json};
+
+    let mut set = devices.borrow_mut();
+    let session = set.get_mut(index).context("No such device")?;
+    match command {
+        Command::SetDpi { dpi, dpi_y, .. } => {
+            let dpi_y = dpi_y.unwrap_or(dpi);
+            let dev = session.device.as_mut().context("Device is not open")?;
+            dev.set_dpi(dpi, dpi_y).map_err(|e| anyhow::anyhow!(e.user_message()))?;
+            if let Some(ui) = session.ui.as_mut() {
+                ui.dpi = (dpi, dpi_y);
+            }
+            if let Some(engine) = session.lighting.as_ref() {
+                engine.set_dpi(dpi);
+            }
+            Ok(Value::Null)
+        }
+        Command::SwitchProfile { profile, .. } => {
+            let failed = load_parked_profile(window, session, &profile)?;
+            if !failed.is_empty() {
+                warn!("{}: failed to apply {}", session.identity.name, failed.join(", "));
+            }
+            Ok(Value::Null)
+        }
+        Command::ToggleRemap { enabled, .. } => {
+            let enabled = enabled.unwrap_or(session.remapper.is_none());
+            if enabled {
+                let dpi_buttons_cycle = session.ui.as_ref().is_none_or(|ui| ui.dpi_buttons_cycle);
+                let events = worker_events(window, &session.info.path);
+                session.start_remapping(dpi_buttons_cycle, events)?;
+            } else {
+                session.stop_remapping();
+            }
+            Ok(json!({ "enabled": enabled }))
+        }
+        Command::RunMacro { target, .. } => Ok(json!({ "name": target.run(&session.macros)? })),
+        Command::Status | Command::ListDevices | Command::Subscribe => Ok(Value::Null),
+    }
+}
+
+/// Turn remapping of the selected device on or off and show it in the window
+fn set_remapping(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    enabled: bool,
+) -> Result<()> {
+    if !enabled {
+        stop_remapper(slots, devices);
+        window.set_remap_enabled(false);
+        window.set_status_message("Remapping disabled".into());
+        return Ok(());
+    }
+    if slots.remapper.borrow().is_some() {
+        window.set_status_message("Remapping already enabled".into());
+        return Ok(());
+    }
+
+    let result = slots
+        .with_selected(devices, |session| {
+            let events = worker_events(&window.as_weak(), &session.info.path);
+            session.start_remapping(window.get_dpi_buttons_cycle(), events)
+        })
+        .unwrap_or_else(|| Err(anyhow::anyhow!("No device connected")));
+    window.set_remap_enabled(result.is_ok());
+    window.set_remap_waiting(false);
+    match &result {
+        Ok(()) => window.set_status_message("Remapping enabled (virtual device active)".into()),
+        Err(e) => window.set_status_message(format!("{:#}", e).into()),
+    }
+    result
+}
+
+/// Snapshot of the settings shown for the selected device
+fn parked_ui_from_window(window: &MainWindow) -> devices::ParkedUi {
+    devices::ParkedUi {
+        dpi: (window.get_current_dpi_x() as u16, window.get_current_dpi_y() as u16),
+        dpi_stages: dpi_stages_from_ui(window),
+        active_stage: window.get_active_dpi_stage().max(0) as usize,
+        dpi_buttons_cycle: window.get_dpi_buttons_cycle(),
+        polling_rate: window.get_polling_rate() as u16,
+        brightness: window.get_brightness() as u8,
+        lighting: lighting_from_ui(window),
+        power: power_from_ui(window),
+    }
+}
+
+/// Restore the settings shown for a device when it is selected again
+fn show_parked_ui(window: &MainWindow, ui: &devices::ParkedUi) {
+    window.set_current_dpi_x(ui.dpi.0 as i32);
+    window.set_current_dpi_y(ui.dpi.1 as i32);
+    show_dpi_stages(window, &ui.dpi_stages, ui.active_stage);
+    window.set_dpi_buttons_cycle(ui.dpi_buttons_cycle);
+    window.set_polling_rate(ui.polling_rate as i32);
+    window.set_brightness(ui.brightness as i32);
+    show_lighting_in_ui(window, &ui.lighting);
+    window.set_idle_timeout(ui.power.idle_timeout_secs as i32);
+    window.set_low_battery_threshold(ui.power.low_battery_threshold as i32);
+}
+
+/// Fill the device picker
+fn show_device_list(window: &MainWindow, set: &devices::DeviceSet) {
+    let labels: Vec<slint::SharedString> = set.labels().into_iter().map(Into::into).collect();
+    window.set_device_list(slint::ModelRc::new(slint::VecModel::from(labels)));
+    window.set_selected_device(set.selected_index().map_or(-1, |i| i as i32));
+}
+
+/// Step a parked device through its DPI stages; its DPI buttons keep working
+/// while another device is shown
+fn cycle_parked_dpi_stage(window: &MainWindow, session: &mut devices::DeviceSession, direction: i32) {
+    let step = if direction >= 0 { hidpoll::DpiStep::Up } else { hidpoll::DpiStep::Down };
+    if let Some(text) = session.cycle_dpi_stage(step) {
+        if window.get_show_dpi_notifications() {
+            devices::show_dpi_notification(&text);
+        }
+        window.set_status_message(text.into());
+    }
+}
+
+fn setup_callbacks(window: &MainWindow, devices: &Rc<RefCell<devices::DeviceSet>>, slots: &DeviceSlots) {
     // Apply DPI callback
-    let device_clone = device.clone();
+    let device_clone = slots.device.clone();
+    let lighting_clone = slots.lighting.clone();
     let window_weak = window.as_weak();
     window.on_apply_dpi(move |dpi_x, dpi_y| {
         info!("Setting DPI to {}x{}", dpi_x, dpi_y);
 
         if let Some(ref mut dev) = *device_clone.borrow_mut() {
-            match dev.set_dpi(dpi_x as u16, dpi_y as u16) {
-                Ok(()) => {
-                    info!("DPI set successfully!");
-                    if let Some(win) = window_weak.upgrade() {
-                        win.set_status_message("DPI applied!".into());
+            match dev.set_dpi(dpi_x as u16, dpi_y as u16) {
+                Ok(()) => {
+                    info!("DPI set successfully!");
+                    if let Some(engine) = lighting_clone.borrow().as_ref() {
+                        engine.set_dpi(dpi_x as u16);
+                    }
+                    if let Some(win) = window_weak.upgrade() {
+                        win.set_status_message("DPI applied!".into());
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to set DPI: {}", e);
+                    if let Some(win) = window_weak.upgrade() {
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
+                    }
+                }
+            }
+        }
+    });
+
+    // DPI stage list editing (UI only until applied)
+    let window_weak = window.as_weak();
+    window.on_add_dpi_stage(move |dpi_x, dpi_y| {
+        if let Some(win) = window_weak.upgrade() {
+            let mut stages = dpi_stages_from_ui(&win);
+            if stages.len() < protocol::MAX_DPI_STAGES {
+                stages.push((dpi_x as u16, dpi_y as u16));
+                show_dpi_stages(&win, &stages, stages.len() - 1);
+            }
+        }
+    });
+
+    let window_weak = window.as_weak();
+    window.on_remove_dpi_stage(move |index| {
+        if let Some(win) = window_weak.upgrade() {
+            let mut stages = dpi_stages_from_ui(&win);
+            let index = index.max(0) as usize;
+            if index < stages.len() {
+                stages.remove(index);
+                show_dpi_stages(&win, &stages, index.saturating_sub(1));
+            }
+        }
+    });
+
+    // DPI stages callback
+    let device_clone = slots.device.clone();
+    let lighting_clone = slots.lighting.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_dpi_stages(move || {
+        let Some(win) = window_weak.upgrade() else { return };
+        let stages = dpi_stages_from_ui(&win);
+        let active = win.get_active_dpi_stage().max(0) as usize;
+
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            match dev.set_dpi_stages(&stages, active) {
+                Ok(()) => {
+                    info!("DPI stages set: {:?} (active {})", stages, active + 1);
+                    if let Some(&(dpi_x, dpi_y)) = stages.get(active) {
+                        win.set_current_dpi_x(dpi_x as i32);
+                        win.set_current_dpi_y(dpi_y as i32);
+                        if let Some(engine) = lighting_clone.borrow().as_ref() {
+                            engine.set_dpi(dpi_x);
+                        }
+                    }
+                    win.set_status_message(format!("{} DPI stages applied", stages.len()).into());
+                }
+                Err(e) => {
+                    error!("Failed to set DPI stages: {}", e);
+                    win.set_status_message(format!("Error: {}", e.user_message()).into());
+                }
+            }
+        }
+    });
+
+    // DPI buttons stepping through the stage list (while remapping)
+    let device_clone = slots.device.clone();
+    let lighting_clone = slots.lighting.clone();
+    let window_weak = window.as_weak();
+    window.on_cycle_dpi_stage(move |direction| {
+        let Some(win) = window_weak.upgrade() else { return };
+        let stages = dpi_stages_from_ui(&win);
+        if stages.is_empty() {
+            win.set_status_message("No DPI stages configured - add some on the DPI tab".into());
+            return;
+        }
+
+        let step = if direction >= 0 { hidpoll::DpiStep::Up } else { hidpoll::DpiStep::Down };
+        let index = step.next_stage(win.get_active_dpi_stage().max(0) as usize, stages.len());
+        let (dpi_x, dpi_y) = stages[index];
+
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            match dev.set_dpi(dpi_x, dpi_y) {
+                Ok(()) => {
+                    info!("DPI stage {}/{}: {}x{}", index + 1, stages.len(), dpi_x, dpi_y);
+                    win.set_active_dpi_stage(index as i32);
+                    win.set_current_dpi_x(dpi_x as i32);
+                    win.set_current_dpi_y(dpi_y as i32);
+                    if let Some(engine) = lighting_clone.borrow().as_ref() {
+                        engine.set_dpi(dpi_x);
+                    }
+                    let text = devices::dpi_stage_text(dpi_x, dpi_y, index, stages.len());
+                    if win.get_show_dpi_notifications() {
+                        devices::show_dpi_notification(&text);
+                    }
+                    win.set_status_message(text.into());
+                }
+                Err(e) => {
+                    error!("Failed to set DPI stage: {}", e);
+                    win.set_status_message(format!("Error: {}", e.user_message()).into());
+                }
+            }
+        }
+    });
+
+    // DPI buttons: cycle stages or send F13/F14
+    let dpi_poller_clone = slots.dpi_poller.clone();
+    window.on_set_dpi_buttons_cycle(move |enabled| {
+        if let Some(poller) = dpi_poller_clone.borrow().as_ref() {
+            poller.set_cycle_stages(enabled);
+        }
+    });
+
+    // Polling rate callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_polling_rate(move |rate| {
+        info!("Setting polling rate to {} Hz", rate);
+
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Some(win) = window_weak.upgrade() {
+                match dev.set_polling_rate(rate as u16) {
+                    Ok(()) => {
+                        win.set_polling_rate(rate);
+                        win.set_status_message(format!("Polling rate set to {} Hz", rate).into());
+                    }
+                    Err(e) => {
+                        error!("Failed to set polling rate: {}", e);
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
+                    }
+                }
+            }
+        }
+    });
+
+    // Idle timeout callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_idle_timeout(move |seconds| {
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Some(win) = window_weak.upgrade() {
+                match dev.set_idle_time(seconds as u16) {
+                    Ok(()) => {
+                        win.set_idle_timeout(seconds);
+                        win.set_status_message(format!("Sleep after {} min idle", seconds / 60).into());
+                    }
+                    Err(e) => {
+                        error!("Failed to set idle timeout: {}", e);
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
+                    }
+                }
+            }
+        }
+    });
+
+    // Low-battery threshold callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_low_battery_threshold(move |percent| {
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Some(win) = window_weak.upgrade() {
+                match dev.set_low_battery_threshold(percent as u8) {
+                    Ok(()) => {
+                        win.set_low_battery_threshold(percent);
+                        win.set_status_message(format!("Low-battery warning at {}%", percent).into());
+                    }
+                    Err(e) => {
+                        error!("Failed to set low-battery threshold: {}", e);
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
+                    }
+                }
+            }
+        }
+    });
+
+    // Brightness callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_brightness(move |brightness| {
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Err(e) = dev.set_brightness(brightness.clamp(0, 255) as u8) {
+                error!("Failed to set brightness: {}", e);
+                if let Some(win) = window_weak.upgrade() {
+                    win.set_status_message(format!("Error: {}", e.user_message()).into());
+                }
+            }
+        }
+    });
+
+    // Lighting effect callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_lighting(move |index, r, g, b| {
+        let color = Rgb::new(r as u8, g as u8, b as u8);
+        let Some(effect) = lighting_effect_from_index(index, color) else {
+            return;
+        };
+        info!("Setting lighting effect: {:?}", effect);
+
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Some(win) = window_weak.upgrade() {
+                match dev.set_effect(&effect) {
+                    Ok(()) => win.set_status_message(format!("{} lighting applied", effect.name()).into()),
+                    Err(e) => {
+                        error!("Failed to set lighting effect: {}", e);
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
+                    }
+                }
+            }
+        }
+    });
+
+    // Software lighting callback
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_software_lighting(move || {
+        if let Some(win) = window_weak.upgrade() {
+            let effect = software_effect_from_ui(&win);
+            match update_software_lighting(&win, &slots_clone, &devices_clone, effect.clone()) {
+                Ok(()) => {
+                    let name = effect.as_ref().map_or("Software lighting off", |e| e.name());
+                    win.set_status_message(format!("{} applied", name).into());
+                }
+                Err(e) => {
+                    error!("Failed to start software lighting: {}", e);
+                    win.set_status_message(format!("Error: {}", e).into());
+                }
+            }
+        }
+    });
+
+    // Per-zone color callback
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_apply_zone_color(move |zone_index, r, g, b| {
+        let zone = if zone_index == 0 { LedZone::Logo } else { LedZone::ScrollWheel };
+        let color = Rgb::new(r as u8, g as u8, b as u8);
+
+        if let Some(ref mut dev) = *device_clone.borrow_mut() {
+            if let Some(win) = window_weak.upgrade() {
+                match dev.set_zone_color(zone, color) {
+                    Ok(()) => {
+                        set_zone_color_property(&win, zone, slint::Color::from_rgb_u8(color.r, color.g, color.b));
+                        win.set_status_message(format!("{} color applied", zone.name()).into());
                     }
-                }
-                Err(e) => {
-                    error!("Failed to set DPI: {}", e);
-                    if let Some(win) = window_weak.upgrade() {
-                        win.set_status_message(format!("Error: {}", e).into());
+                    Err(e) => {
+                        error!("Failed to set {} color: {}", zone.name(), e);
+                        win.set_status_message(format!("Error: {}", e.user_message()).into());
                     }
                 }
             }
@@ -386,25 +1275,148 @@ fn setup_callbacks(
     });
 
     // Refresh device callback
-    let device_clone = device.clone();
+    let device_clone = slots.device.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     let window_weak = window.as_weak();
     window.on_refresh_device(move || {
         info!("Refreshing device connection...");
         if let Some(win) = window_weak.upgrade() {
-            // Clear current device
-            *device_clone.borrow_mut() = None;
             win.set_device_connected(false);
             win.set_status_message("Scanning...".into());
 
-            // Try to reconnect
-            connect_device_inner(&win, &device_clone);
+            // Rescan and reopen the selected device
+            scan_devices(&win, &slots_clone, &devices_clone, &[]);
+            resend_power_settings(&win, &device_clone);
+        }
+    });
+
+    // Device picker
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
+    let window_weak = window.as_weak();
+    window.on_select_device(move |index| {
+        if let Some(win) = window_weak.upgrade() {
+            if index >= 0 {
+                select_device(&win, &slots_clone, &devices_clone, index as usize);
+            }
+        }
+    });
+
+    // Hotplug: reopen replugged devices and bring back their profile
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
+    let window_weak = window.as_weak();
+    window.on_devices_changed(move |removed| {
+        use slint::Model;
+        let Some(win) = window_weak.upgrade() else { return };
+        let removed: Vec<std::path::PathBuf> = removed.iter().map(|node| node.as_str().into()).collect();
+        let previous = devices_clone.borrow().selected().map(|s| s.identity.key());
+
+        let opened = scan_devices(&win, &slots_clone, &devices_clone, &removed);
+        resend_power_settings(&win, &slots_clone.device);
+
+        // Re-apply the profile a replugged device had, or its startup profile;
+        // devices the window does not show get it in their session
+        let settings = AppSettings::load().unwrap_or_default();
+        for path in &opened {
+            let mut set = devices_clone.borrow_mut();
+            let Some(index) = set.index_of_path(path) else { continue };
+            let Some(key) = set.get_mut(index).map(|s| s.identity.key()) else { continue };
+            let last = set.take_last_profile(&key);
+            let profile_name = last.unwrap_or_else(|| settings.profile_for_device(&key).to_string());
+            if profile_name.is_empty() {
+                continue;
+            }
+            info!("Hotplug: loading profile '{}' on {}", profile_name, path);
+            if set.selected_index() == Some(index) {
+                drop(set);
+                load_selected_device_profile(&win, &slots_clone, &devices_clone, &profile_name);
+            } else if let Some(session) = set.get_mut(index) {
+                load_parked_device_profile(&win.as_weak(), session, &profile_name);
+            }
+        }
+
+        // Show the device that was selected before, if it is still (or again) connected
+        let index = previous.and_then(|key| devices_clone.borrow().index_of_key(&key));
+        if let Some(index) = index {
+            select_device(&win, &slots_clone, &devices_clone, index);
+        }
+        show_device_list(&win, &devices_clone.borrow());
+    });
+
+    // DPI button steps, routed to the device whose buttons were pressed
+    let devices_clone = devices.clone();
+    let window_weak = window.as_weak();
+    window.on_cycle_device_dpi_stage(move |path, direction| {
+        let Some(win) = window_weak.upgrade() else { return };
+        let mut set = devices_clone.borrow_mut();
+        if set.selected().is_none_or(|s| s.info.path == path.as_str()) {
+            drop(set);
+            win.invoke_cycle_dpi_stage(direction);
+        } else if let Some(session) = set.find_by_path(&path) {
+            cycle_parked_dpi_stage(&win, session, direction);
+        }
+    });
+
+    // Battery readings, routed to the device they are for
+    let devices_clone = devices.clone();
+    let window_weak = window.as_weak();
+    window.on_device_battery_changed(move |path, level, charging| {
+        let Some(win) = window_weak.upgrade() else { return };
+        let status = battery::BatteryStatus { level: level.clamp(0, 100) as u8, charging };
+        let mut set = devices_clone.borrow_mut();
+        let selected = set.selected().is_some_and(|s| s.info.path == path.as_str());
+        let Some(session) = set.find_by_path(&path) else { return };
+        if selected {
+            // Warns by the threshold the window shows
+            session.battery_status = Some(status);
+            show_battery_status(&win, status);
+        } else if session.set_battery_status(status) {
+            warn!("{}: battery low ({}%)", session.identity.name, status.level);
+            win.set_status_message(
+                format!("🪫 {} battery low ({}%) - connect the charger soon", session.identity.name, status.level)
+                    .into(),
+            );
+        }
+    });
+
+    // A remapped device disappeared, or its remapper grabbed it again
+    let devices_clone = devices.clone();
+    let window_weak = window.as_weak();
+    window.on_remap_source_changed(move |path, waiting| {
+        let Some(win) = window_weak.upgrade() else { return };
+        let mut set = devices_clone.borrow_mut();
+        let selected = set.selected().is_none_or(|s| s.info.path == path.as_str());
+        if selected {
+            win.set_remap_waiting(waiting);
+        }
+        let session = if selected { set.selected_mut() } else { set.find_by_path(&path) };
+        let Some(session) = session else { return };
+
+        let message = if waiting {
+            format!("{} disconnected - remapping resumes when it is back", session.identity.name)
+        } else {
+            format!("{} is back - remapping resumed", session.identity.name)
+        };
+        win.set_status_message(message.into());
+    });
+
+    // Re-read the About view (the mode changes while remapping)
+    let device_clone = slots.device.clone();
+    let window_weak = window.as_weak();
+    window.on_refresh_device_identity(move || {
+        if let Some(win) = window_weak.upgrade() {
+            let identity = device_clone.borrow_mut().as_mut().map(|dev| dev.identity());
+            show_device_identity(&win, identity.as_ref());
         }
     });
 
     // Save profile callback
-    let remap_mappings_clone = remap_mappings.clone();
-    let remapper_clone = remapper.clone();
-    let macro_mgr_clone = macro_manager.clone();
+    let remap_mappings_clone = slots.mappings.clone();
+    let layers_clone = slots.layers.clone();
+    let remapper_clone = slots.remapper.clone();
+    let macro_mgr_clone = slots.macros.clone();
     let window_weak = window.as_weak();
     window.on_save_profile(move |profile_name| {
         info!("Saving profile: {}", profile_name);
@@ -418,8 +1430,15 @@ fn setup_callbacks(
             let dpi_x = win.get_current_dpi_x() as u16;
             let dpi_y = win.get_current_dpi_y() as u16;
             let mut profile = Profile::from_device_settings(&name, dpi_x, dpi_y);
+            profile.polling_rate = win.get_polling_rate() as u16;
+            profile.brightness = win.get_brightness() as u8;
+            profile.lighting = lighting_from_ui(&win);
+            profile.power = power_from_ui(&win);
+            profile.dpi.dpi_stages = dpi_stages_from_ui(&win);
+            profile.dpi.active_stage = win.get_active_dpi_stage().max(0) as usize;
             profile.remap.enabled = win.get_remap_enabled();
             profile.remap.autoscroll = win.get_autoscroll_enabled();
+            profile.remap.dpi_button_keys = !win.get_dpi_buttons_cycle();
             profile.remap.mappings = remap_mappings_clone
                 .borrow()
                 .iter()
@@ -433,10 +1452,16 @@ fn setup_callbacks(
                     macro_id: None,
                 })
                 .collect();
+            profile.remap.layers = layers_clone.borrow().clone();
                 
             // Include macros in the profile
             profile.macros = macro_mgr_clone.borrow().export_for_profile();
 
+            // Pin to the connected mouse if asked and its serial is known
+            if win.get_pin_profile_to_device() {
+                profile.device_serial = current_serial(&win);
+            }
+
             // If remapping is currently active, store the detected/selected device if any.
             if profile.remap.enabled {
                 profile.remap.source_device = None;
@@ -459,13 +1484,8 @@ fn setup_callbacks(
     });
 
     // Load profile callback
-    let device_clone = device.clone();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remapper_clone = remapper.clone();
-    let dpi_poller_clone = dpi_poller.clone();
-    let autoscroll_clone = autoscroll_enabled.clone();
-    let overlay_clone = autoscroll_overlay.clone();
-    let macro_mgr_clone = macro_manager.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     let window_weak = window.as_weak();
     window.on_load_profile(move |profile_name| {
         info!("Loading profile: {}", profile_name);
@@ -476,134 +1496,51 @@ fn setup_callbacks(
                 return;
             }
 
-            match ProfileManager::new() {
-                Ok(manager) => {
-                    match manager.load_profile(&name) {
-                        Ok(profile) => {
-                            // Update UI with profile settings
-                            win.set_current_dpi_x(profile.dpi.x as i32);
-                            win.set_current_dpi_y(profile.dpi.y as i32);
-
-                            // Apply to device if connected
-                            if let Some(ref mut dev) = *device_clone.borrow_mut() {
-                                if let Err(e) = dev.set_dpi(profile.dpi.x, profile.dpi.y) {
-                                    error!("Failed to apply profile DPI: {}", e);
-                                }
-                            }
-
-                            // Load remap mappings into UI state
-                            {
-                                let mut map = remap_mappings_clone.borrow_mut();
-                                map.clear();
-                                for m in &profile.remap.mappings {
-                                    map.insert(
-                                        m.source,
-                                        remap::MappingTarget {
-                                            base: m.target,
-                                            mods: remap::Modifiers {
-                                                ctrl: m.ctrl,
-                                                alt: m.alt,
-                                                shift: m.shift,
-                                                meta: m.meta,
-                                            },
-                                        },
-                                    );
-                                }
-                            }
-                            win.set_remap_enabled(profile.remap.enabled);
-                            update_remap_summary(&win, &remap_mappings_clone.borrow());
-                            
-                            // Load macros from profile
-                            {
-                                let mut mgr = macro_mgr_clone.borrow_mut();
-                                mgr.load_from_profile(profile.macros.clone());
-                                win.set_macro_list_text(mgr.get_macros_list_text().into());
-                                win.set_available_macros(mgr.get_available_macros_string().into());
-                            }
-                            
-                            // Load autoscroll setting from profile
-                            *autoscroll_clone.borrow_mut() = profile.remap.autoscroll;
-                            win.set_autoscroll_enabled(profile.remap.autoscroll);
-
-                            // Start/stop remapper to match profile
-                            if profile.remap.enabled {
-                                let autoscroll = profile.remap.autoscroll;
-                                start_remapper(&win, &device_clone, &remapper_clone, &remap_mappings_clone, &dpi_poller_clone, &overlay_clone, autoscroll, &macro_mgr_clone);
-                            } else {
-                                stop_remapper(&device_clone, &remapper_clone, &dpi_poller_clone, &overlay_clone);
-                            }
-
-                            win.set_status_message(format!("Profile '{}' loaded!", name).into());
-                        }
-                        Err(e) => win.set_status_message(format!("Load error: {}", e).into()),
-                    }
-                }
-                Err(e) => win.set_status_message(format!("Error: {}", e).into()),
+            match load_profile(&win, &slots_clone, &devices_clone, &name) {
+                Ok(failed) => show_profile_loaded(&win, &name, &failed),
+                Err(e) => win.set_status_message(format!("Load error: {:#}", e).into()),
             }
         }
     });
 
     // Remap enable/disable
     let window_weak = window.as_weak();
-    let device_clone = device.clone();
-    let remapper_clone = remapper.clone();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remap_mappings_save = remap_mappings.clone();
-    let dpi_poller_clone = dpi_poller.clone();
-    let autoscroll_clone = autoscroll_enabled.clone();
-    let overlay_clone = autoscroll_overlay.clone();
-    let macro_mgr_clone = macro_manager.clone();
-    let macro_mgr_save = macro_manager.clone();
+    let remap_mappings_save = slots.mappings.clone();
+    let macro_mgr_save = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remap_set_enabled(move |enabled| {
         if let Some(win) = window_weak.upgrade() {
-            if enabled {
-                let autoscroll = *autoscroll_clone.borrow();
-                start_remapper(&win, &device_clone, &remapper_clone, &remap_mappings_clone, &dpi_poller_clone, &overlay_clone, autoscroll, &macro_mgr_clone);
-            } else {
-                stop_remapper(&device_clone, &remapper_clone, &dpi_poller_clone, &overlay_clone);
-                win.set_status_message("Remapping disabled".into());
+            if let Err(e) = set_remapping(&win, &slots_clone, &devices_clone, enabled) {
+                error!("Failed to start remapping: {:#}", e);
             }
             // Auto-save state to Default profile
-            auto_save_default_profile(&win, &remap_mappings_save, &macro_mgr_save);
+            auto_save_default_profile(&win, &remap_mappings_save, &slots_clone.layers, &macro_mgr_save);
         }
     });
 
-    // Autoscroll toggle - requires restart of remapper to take effect
+    // Autoscroll toggle - a running remapper picks it up right away
     let window_weak = window.as_weak();
-    let autoscroll_clone = autoscroll_enabled.clone();
-    let remapper_clone = remapper.clone();
-    let device_clone = device.clone();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remap_mappings_save = remap_mappings.clone();
-    let dpi_poller_clone = dpi_poller.clone();
-    let overlay_clone = autoscroll_overlay.clone();
-    let macro_mgr_clone = macro_manager.clone();
-    let macro_mgr_save = macro_manager.clone();
+    let autoscroll_clone = slots.autoscroll.clone();
+    let remap_mappings_save = slots.mappings.clone();
+    let macro_mgr_save = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_autoscroll_set_enabled(move |enabled| {
         info!("Autoscroll set to: {}", enabled);
         *autoscroll_clone.borrow_mut() = enabled;
-        
-        // If remapper is running, restart it to apply new autoscroll setting
-        if remapper_clone.borrow().is_some() {
-            if let Some(win) = window_weak.upgrade() {
-                info!("Restarting remapper to apply autoscroll setting");
-                stop_remapper(&device_clone, &remapper_clone, &dpi_poller_clone, &overlay_clone);
-                // Give time for devices to be properly ungrabbed
-                std::thread::sleep(std::time::Duration::from_millis(200));
-                start_remapper(&win, &device_clone, &remapper_clone, &remap_mappings_clone, &dpi_poller_clone, &overlay_clone, enabled, &macro_mgr_clone);
-            }
-        }
+        update_remapper(&slots_clone, &devices_clone);
         
         // Auto-save state to Default profile
         if let Some(win) = window_weak.upgrade() {
-            auto_save_default_profile(&win, &remap_mappings_save, &macro_mgr_save);
+            auto_save_default_profile(&win, &remap_mappings_save, &slots_clone.layers, &macro_mgr_save);
         }
     });
 
     // Learn next button/key code (temporarily pause remapper so grabs don't block input)
     // Note: We use pause_remapper here to keep driver mode enabled, so side buttons can be learned
     let window_weak = window.as_weak();
-    let remapper_clone = remapper.clone();
+    let remapper_clone = slots.remapper.clone();
     window.on_remap_learn_source(move || {
         let was_enabled = remapper_clone.borrow().is_some();
         if was_enabled {
@@ -656,9 +1593,11 @@ fn setup_callbacks(
 
     // Add mapping
     let window_weak = window.as_weak();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remap_mappings_save = remap_mappings.clone();
-    let macro_mgr_save = macro_manager.clone();
+    let remap_mappings_clone = slots.mappings.clone();
+    let remap_mappings_save = slots.mappings.clone();
+    let macro_mgr_save = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remap_add_mapping(move |source, target, ctrl, alt, shift, meta| {
         if let Some(win) = window_weak.upgrade() {
             let s = source as u16;
@@ -676,7 +1615,8 @@ fn setup_callbacks(
                 },
             );
             update_remap_summary(&win, &remap_mappings_clone.borrow());
-            win.set_status_message(format!(
+            let dropped = update_remapper(&slots_clone, &devices_clone);
+            let mut message = format!(
                 "Mapped {} -> {}",
                 s,
                 format_mapping_target(&remap::MappingTarget {
@@ -688,8 +1628,12 @@ fn setup_callbacks(
                         meta,
                     },
                 })
-            )
-            .into());
+            );
+            if dropped.contains(&s) {
+                // The running virtual device was created without the target key
+                message.push_str(" - turn remapping off and on to apply it");
+            }
+            win.set_status_message(message.into());
             
             // Reset source code and modifiers so user can configure next mapping cleanly
             win.set_remap_source_code(0);
@@ -707,13 +1651,15 @@ fn setup_callbacks(
             );
             
             // Auto-save to Default profile
-            auto_save_default_profile(&win, &remap_mappings_save, &macro_mgr_save);
+            auto_save_default_profile(&win, &remap_mappings_save, &slots_clone.layers, &macro_mgr_save);
         }
     });
     
     // Add macro mapping (special handling for target codes 1000+)
     let window_weak = window.as_weak();
-    let remap_mappings_clone = remap_mappings.clone();
+    let remap_mappings_clone = slots.mappings.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remap_add_macro_mapping(move |source, macro_id| {
         if let Some(win) = window_weak.upgrade() {
             let s = source as u16;
@@ -727,38 +1673,45 @@ fn setup_callbacks(
                 },
             );
             update_remap_summary(&win, &remap_mappings_clone.borrow());
+            update_remapper(&slots_clone, &devices_clone);
             win.set_status_message(format!("Mapped button {} -> Macro {}", s, macro_id).into());
         }
     });
 
     // Clear mappings
     let window_weak = window.as_weak();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remap_mappings_save = remap_mappings.clone();
-    let macro_mgr_save = macro_manager.clone();
+    let remap_mappings_clone = slots.mappings.clone();
+    let remap_mappings_save = slots.mappings.clone();
+    let macro_mgr_save = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remap_clear(move || {
         if let Some(win) = window_weak.upgrade() {
             remap_mappings_clone.borrow_mut().clear();
             update_remap_summary(&win, &remap_mappings_clone.borrow());
+            update_remapper(&slots_clone, &devices_clone);
             win.set_status_message("Mappings cleared".into());
             // Auto-save to Default profile
-            auto_save_default_profile(&win, &remap_mappings_save, &macro_mgr_save);
+            auto_save_default_profile(&win, &remap_mappings_save, &slots_clone.layers, &macro_mgr_save);
         }
     });
 
     // Remove a single mapping by source code
     let window_weak = window.as_weak();
-    let remap_mappings_clone = remap_mappings.clone();
-    let remap_mappings_save = remap_mappings.clone();
-    let macro_mgr_save = macro_manager.clone();
+    let remap_mappings_clone = slots.mappings.clone();
+    let remap_mappings_save = slots.mappings.clone();
+    let macro_mgr_save = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remap_remove_mapping(move |source| {
         if let Some(win) = window_weak.upgrade() {
             let s = source as u16;
             if remap_mappings_clone.borrow_mut().remove(&s).is_some() {
                 update_remap_summary(&win, &remap_mappings_clone.borrow());
+                update_remapper(&slots_clone, &devices_clone);
                 win.set_status_message(format!("Removed mapping for button (code {})", s).into());
                 // Auto-save to Default profile
-                auto_save_default_profile(&win, &remap_mappings_save, &macro_mgr_save);
+                auto_save_default_profile(&win, &remap_mappings_save, &slots_clone.layers, &macro_mgr_save);
             } else {
                 win.set_status_message(format!("No mapping found for code {}", s).into());
             }
@@ -781,7 +1734,7 @@ fn setup_callbacks(
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     window.on_edit_macro(move |macro_id| {
         if let Some(win) = window_weak.upgrade() {
             info!("Edit macro {} requested", macro_id);
@@ -803,7 +1756,9 @@ fn setup_callbacks(
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_delete_macro(move |macro_id| {
         if let Some(win) = window_weak.upgrade() {
             info!("Delete macro {} requested", macro_id);
@@ -818,11 +1773,15 @@ fn setup_callbacks(
             } else {
                 win.set_status_message(format!("Macro {} not found", macro_id).into());
             }
+            drop(mgr);
+            update_remapper(&slots_clone, &devices_clone);
         }
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_save_macro(move |name, repeat| {
         if let Some(win) = window_weak.upgrade() {
             info!("Save macro '{}' with repeat={}", name, repeat);
@@ -849,6 +1808,8 @@ fn setup_callbacks(
             
             win.set_macro_list_text(mgr.get_macros_list_text().into());
             win.set_available_macros(mgr.get_available_macros_string().into());
+            drop(mgr);
+            update_remapper(&slots_clone, &devices_clone);
         }
     });
     
@@ -856,8 +1817,9 @@ fn setup_callbacks(
     let key_listener: Rc<RefCell<Option<remap::KeyCaptureListener>>> = Rc::new(RefCell::new(None));
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     let key_listener_ref = key_listener.clone();
+    let lighting_clone = slots.lighting.clone();
     window.on_start_macro_recording(move || {
         if let Some(win) = window_weak.upgrade() {
             let mut mgr = macro_mgr.borrow_mut();
@@ -870,6 +1832,9 @@ fn setup_callbacks(
                     *key_listener_ref.borrow_mut() = Some(listener);
                     mgr.start_recording(macro_name);
                     win.set_macro_recording(true);
+                    if let Some(engine) = lighting_clone.borrow().as_ref() {
+                        engine.set_macro_recording(true);
+                    }
                     win.set_selected_action_index(-1);  // Clear action selection
                     // Clear the actions list
                     let empty_list: Vec<slint::SharedString> = Vec::new();
@@ -891,14 +1856,20 @@ fn setup_callbacks(
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     let key_listener_ref = key_listener.clone();
+    let lighting_clone = slots.lighting.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_stop_macro_recording(move || {
         if let Some(win) = window_weak.upgrade() {
             // Stop the key listener
             if let Some(listener) = key_listener_ref.borrow_mut().take() {
                 listener.stop();
             }
+            if let Some(engine) = lighting_clone.borrow().as_ref() {
+                engine.set_macro_recording(false);
+            }
             
             let mut mgr = macro_mgr.borrow_mut();
             
@@ -918,12 +1889,14 @@ fn setup_callbacks(
                 win.set_macro_recording(false);
                 win.set_status_message("No recording in progress".into());
             }
+            drop(mgr);
+            update_remapper(&slots_clone, &devices_clone);
         }
     });
     
     // Polling timer to check for captured keys during recording
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     let key_listener_poll = key_listener.clone();
     let poll_timer = slint::Timer::default();
     poll_timer.start(slint::TimerMode::Repeated, std::time::Duration::from_millis(16), move || {
@@ -962,7 +1935,7 @@ fn setup_callbacks(
     std::mem::forget(poll_timer);
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     window.on_add_macro_keypress(move || {
         if let Some(win) = window_weak.upgrade() {
             let is_recording = macro_mgr.borrow().is_recording();
@@ -976,7 +1949,7 @@ fn setup_callbacks(
     
     // Handler for captured keys from background thread
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     window.on_record_captured_key(move |key_code, include_release| {
         if let Some(win) = window_weak.upgrade() {
             let mut mgr = macro_mgr.borrow_mut();
@@ -992,7 +1965,7 @@ fn setup_callbacks(
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     window.on_add_macro_delay(move || {
         if let Some(win) = window_weak.upgrade() {
             let mut mgr = macro_mgr.borrow_mut();
@@ -1013,7 +1986,9 @@ fn setup_callbacks(
     
     // Handler to remove an action from recording or saved macro
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
+    let devices_clone = devices.clone();
+    let slots_clone = slots.clone();
     window.on_remove_macro_action(move |index| {
         if let Some(win) = window_weak.upgrade() {
             let mut mgr = macro_mgr.borrow_mut();
@@ -1041,11 +2016,15 @@ fn setup_callbacks(
                 win.set_macro_actions_list(slint::ModelRc::new(slint::VecModel::from(actions)));
                 win.set_status_message("Removed action".into());
             }
+            drop(mgr);
+            if removed && !is_recording {
+                update_remapper(&slots_clone, &devices_clone);
+            }
         }
     });
     
     let window_weak = window.as_weak();
-    let macro_mgr = macro_manager.clone();
+    let macro_mgr = slots.macros.clone();
     window.on_test_macro(move || {
         if let Some(win) = window_weak.upgrade() {
             let selected_id = win.get_selected_macro_id();
@@ -1084,6 +2063,7 @@ fn setup_callbacks(
                 win.set_autostart_enabled(settings.autostart || settings::is_autostart_enabled());
                 win.set_default_profile(settings.default_profile.clone().into());
                 win.set_minimize_to_tray(settings.minimize_to_tray);
+                win.set_show_dpi_notifications(settings.show_dpi_notifications);
                 
                 // Systemd user service status
                 win.set_systemd_available(settings::is_systemd_available());
@@ -1158,13 +2138,23 @@ fn setup_callbacks(
     
     // Set default profile callback
     let window_weak = window.as_weak();
+    let devices_clone = devices.clone();
     window.on_set_default_profile(move |profile_name| {
         let name = profile_name.to_string();
         info!("Setting default profile: '{}'", name);
         if let Some(win) = window_weak.upgrade() {
             match AppSettings::load() {
                 Ok(mut settings) => {
-                    if let Err(e) = settings.set_default_profile(&name) {
+                    // With several devices connected, the choice is per device
+                    let device_key = {
+                        let set = devices_clone.borrow();
+                        set.selected().filter(|_| set.len() > 1).map(|s| s.identity.key())
+                    };
+                    let result = match &device_key {
+                        Some(key) => settings.set_device_profile(key, &name),
+                        None => settings.set_default_profile(&name),
+                    };
+                    if let Err(e) = result {
                         error!("Failed to set default profile: {}", e);
                         win.set_status_message(format!("Failed to save setting: {}", e).into());
                     } else {
@@ -1199,6 +2189,25 @@ fn setup_callbacks(
         }
     });
     
+    // DPI change notifications callback
+    let window_weak = window.as_weak();
+    window.on_set_show_dpi_notifications(move |enabled| {
+        if let Some(win) = window_weak.upgrade() {
+            match AppSettings::load() {
+                Ok(mut settings) => {
+                    if let Err(e) = settings.set_show_dpi_notifications(enabled) {
+                        error!("Failed to set DPI notifications: {}", e);
+                        win.set_status_message(format!("Failed to save setting: {}", e).into());
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to load settings: {}", e);
+                    win.set_status_message(format!("Settings error: {}", e).into());
+                }
+            }
+        }
+    });
+
     // Set minimize to tray callback
     let window_weak = window.as_weak();
     window.on_set_minimize_to_tray(move |enabled| {
@@ -1226,145 +2235,50 @@ fn setup_callbacks(
     });
 }
 
-fn start_remapper(
-    win: &MainWindow,
-    device: &Rc<RefCell<Option<device::RazerDevice>>>,
-    remapper: &Rc<RefCell<Option<remap::Remapper>>>,
-    mappings: &Rc<RefCell<BTreeMap<u16, remap::MappingTarget>>>,
-    dpi_poller: &Rc<RefCell<Option<hidpoll::DpiButtonPoller>>>,
-    autoscroll_overlay: &Rc<RefCell<Option<overlay::AutoscrollOverlay>>>,
-    autoscroll_enabled: bool,
-    macro_manager: &Rc<RefCell<macro_engine::MacroManager>>,
-) {
-    if remapper.borrow().is_some() {
-        win.set_status_message("Remapping already enabled".into());
-        return;
-    }
-
-    // Enable Driver Mode - this makes side buttons send keyboard keys
-    // which can then be captured and remapped
-    if let Some(ref mut dev) = *device.borrow_mut() {
-        match dev.enable_driver_mode() {
-            Ok(()) => {
-                info!("Driver mode enabled for side button remapping");
-            }
-            Err(e) => {
-                warn!("Failed to enable driver mode: {} - side buttons may not work", e);
-                win.set_status_message(format!("Warning: Could not enable driver mode: {}", e).into());
-            }
-        }
-    } else {
-        warn!("No device connected - cannot enable driver mode");
-    }
-
-    let config = remap::RemapConfig {
-        source_device: None,
-        mappings: mappings.borrow().clone(),
-        autoscroll_enabled,
-    };
-
-    // Start the DPI button poller FIRST so its virtual device exists
-    // when the remapper enumerates devices
-    if dpi_poller.borrow().is_none() {
-        match hidpoll::DpiButtonPoller::start() {
-            Ok(poller) => {
-                info!("DPI button poller started");
-                *dpi_poller.borrow_mut() = Some(poller);
-                // Brief delay to let uinput device be created
-                std::thread::sleep(std::time::Duration::from_millis(100));
-            }
-            Err(e) => {
-                warn!("Failed to start DPI poller: {} - DPI buttons won't be remappable", e);
-            }
-        }
-    }
-
-    // Create overlay for autoscroll if enabled
-    let overlay_sender = if autoscroll_enabled {
-        match overlay::AutoscrollOverlay::start() {
-            Ok(ol) => {
-                let sender = ol.sender();
-                *autoscroll_overlay.borrow_mut() = Some(ol);
-                info!("Autoscroll overlay created");
-                Some(sender)
-            }
-            Err(e) => {
-                warn!("Failed to create autoscroll overlay: {} - will work without visual indicator", e);
-                None
-            }
-        }
-    } else {
-        None
-    };
-
-    // Clone macros for the remapper thread
-    // Note: Macros are cloned at remapper start time. If macros are edited while
-    // remapper is running, the remapper won't see the changes until restart.
-    let macros_for_remapper: std::collections::HashMap<u32, profile::Macro> = {
-        let mgr = macro_manager.borrow();
-        mgr.export_for_profile()
-            .into_iter()
-            .map(|m| (m.id, m))
-            .collect()
-    };
-
-    match remap::Remapper::start(config, overlay_sender, macros_for_remapper) {
-        Ok(r) => {
-            *remapper.borrow_mut() = Some(r);
-            win.set_status_message("Remapping enabled (virtual device active)".into());
-        }
-        Err(e) => {
-            // If remapper fails, restore normal mode
-            if let Some(ref mut dev) = *device.borrow_mut() {
-                let _ = dev.disable_driver_mode();
-            }
-            // Also stop DPI poller if remapper fails
-            if let Some(poller) = dpi_poller.borrow_mut().take() {
-                poller.stop();
-            }
-            // Clean up overlay
-            if let Some(ol) = autoscroll_overlay.borrow_mut().take() {
-                ol.shutdown();
-            }
-            win.set_remap_enabled(false);
-            win.set_status_message(format!("Remap start failed: {e}").into());
-        }
+/// Worker events of a device, handed to the UI thread, which owns the device
+/// handles. DPI button steps and source changes are tagged with the device
+/// path, so this works for parked devices too.
+fn worker_events(window: &slint::Weak<MainWindow>, device_path: &str) -> devices::WorkerEvents {
+    let step_window = window.clone();
+    let step_path = device_path.to_string();
+    let state_window = window.clone();
+    let state_path = device_path.to_string();
+    devices::WorkerEvents {
+        on_step: Box::new(move |step| {
+            let window_weak = step_window.clone();
+            let device_path = step_path.clone();
+            let _ = slint::invoke_from_event_loop(move || {
+                if let Some(win) = window_weak.upgrade() {
+                    let direction = if step == hidpoll::DpiStep::Up { 1 } else { -1 };
+                    win.invoke_cycle_device_dpi_stage(device_path.into(), direction);
+                }
+            });
+        }),
+        on_state: Box::new(move |state| {
+            let window_weak = state_window.clone();
+            let device_path = state_path.clone();
+            let _ = slint::invoke_from_event_loop(move || {
+                if let Some(win) = window_weak.upgrade() {
+                    let waiting = state == remap::RemapState::Waiting;
+                    win.invoke_remap_source_changed(device_path.into(), waiting);
+                }
+            });
+        }),
     }
 }
 
-fn stop_remapper(
-    device: &Rc<RefCell<Option<device::RazerDevice>>>,
-    remapper: &Rc<RefCell<Option<remap::Remapper>>>,
-    dpi_poller: &Rc<RefCell<Option<hidpoll::DpiButtonPoller>>>,
-    autoscroll_overlay: &Rc<RefCell<Option<overlay::AutoscrollOverlay>>>,
-) {
-    if let Some(r) = remapper.borrow_mut().take() {
-        r.stop();
-    }
-    
-    // Stop the DPI button poller
-    if let Some(p) = dpi_poller.borrow_mut().take() {
-        p.stop();
-        info!("DPI button poller stopped");
-    }
-    
-    // Stop the autoscroll overlay
-    if let Some(ol) = autoscroll_overlay.borrow_mut().take() {
-        ol.shutdown();
-        info!("Autoscroll overlay stopped");
-    }
+/// Hand the selected device's mappings, macros and autoscroll setting to its
+/// running remapper (see `DeviceSession::update_remapping`). Returns the
+/// source codes of mappings it leaves out until remapping restarts.
+fn update_remapper(slots: &DeviceSlots, devices: &Rc<RefCell<devices::DeviceSet>>) -> Vec<u16> {
+    slots
+        .with_selected(devices, devices::DeviceSession::update_remapping)
+        .unwrap_or_default()
+}
 
-    // Disable Driver Mode - restore normal operation
-    if let Some(ref mut dev) = *device.borrow_mut() {
-        match dev.disable_driver_mode() {
-            Ok(()) => {
-                info!("Driver mode disabled - restored normal mode");
-            }
-            Err(e) => {
-                warn!("Failed to disable driver mode: {}", e);
-            }
-        }
-    }
+/// Stop remapping the selected device and restore Normal mode
+fn stop_remapper(slots: &DeviceSlots, devices: &Rc<RefCell<devices::DeviceSet>>) {
+    slots.with_selected(devices, devices::DeviceSession::stop_remapping);
 }
 
 /// Stop remapper without changing device mode (used when pausing for learning)
@@ -1540,120 +2454,400 @@ fn code_to_digit(code: u16) -> Option<char> {
     }
 }
 
-// Helper function for use inside callbacks (can't use &MainWindow in closure)
-fn connect_device_inner(window: &MainWindow, device: &Rc<RefCell<Option<device::RazerDevice>>>) {
-    match device::find_naga_trinity() {
-        Ok(Some(device_info)) => match device::RazerDevice::open(&device_info.path) {
-            Ok(mut dev) => {
-                window.set_device_name(device_info.product.into());
-                window.set_device_connected(true);
-                window.set_status_message("Connected".into());
+/// Adapt DPI limits and the side panel to the connected device model
+fn apply_model_to_ui(window: &MainWindow, model: &models::DeviceModel) {
+    window.set_dpi_min(model.dpi_min as i32);
+    window.set_dpi_max(model.dpi_max as i32);
+    window.set_side_button_count(model.buttons.side_buttons as i32);
+    window.set_has_logo_led(model.led_zones.contains(&LedZone::Logo));
+    window.set_has_scroll_led(model.led_zones.contains(&LedZone::ScrollWheel));
+    window.set_has_led_matrix(model.matrix.is_some());
+    window.set_has_battery(model.supports(models::Feature::Battery));
+    window.set_has_dpi_stages(model.supports(models::Feature::DpiStages));
+}
 
-                if let Ok(version) = dev.get_firmware_version() {
-                    window.set_firmware_version(version.into());
-                }
+/// Read the DPI stage list from the UI
+fn dpi_stages_from_ui(window: &MainWindow) -> Vec<(u16, u16)> {
+    use slint::Model;
 
-                if let Ok((dpi_x, dpi_y)) = dev.get_dpi() {
-                    window.set_current_dpi_x(dpi_x as i32);
-                    window.set_current_dpi_y(dpi_y as i32);
-                }
+    window
+        .get_dpi_stages()
+        .iter()
+        .map(|stage| (stage.x as u16, stage.y as u16))
+        .collect()
+}
 
-                *device.borrow_mut() = Some(dev);
-            }
-            Err(e) => {
-                window.set_status_message(format!("Error: {}", e).into());
-            }
-        },
-        Ok(None) => {
-            window.set_device_name("No device found".into());
-            window.set_device_connected(false);
-            window.set_status_message("No device found".into());
+/// Show a DPI stage list in the UI
+fn show_dpi_stages(window: &MainWindow, stages: &[(u16, u16)], active: usize) {
+    let model: Vec<DpiStage> = stages
+        .iter()
+        .map(|&(x, y)| DpiStage { x: x as i32, y: y as i32 })
+        .collect();
+    window.set_dpi_stages(slint::ModelRc::new(slint::VecModel::from(model)));
+    window.set_active_dpi_stage(active.min(stages.len().saturating_sub(1)) as i32);
+}
+
+/// Re-send the idle timeout and low-battery threshold shown in the UI to a
+/// device that forgets them when powered off
+fn resend_power_settings(window: &MainWindow, device: &Rc<RefCell<Option<device::RazerDevice>>>) {
+    if let Some(ref mut dev) = *device.borrow_mut() {
+        let model = dev.model();
+        if !model.supports(models::Feature::Battery) || model.stores_power_settings {
+            return;
         }
-        Err(e) => {
-            window.set_status_message(format!("Scan error: {}", e).into());
+        if let Err(e) = dev.set_idle_time(window.get_idle_timeout() as u16) {
+            warn!("Failed to restore idle timeout: {}", e);
         }
+        if let Err(e) = dev.set_low_battery_threshold(window.get_low_battery_threshold() as u8) {
+            warn!("Failed to restore low-battery threshold: {}", e);
+        }
+    }
+}
+
+/// Show serial, firmware, interfaces and mode in the About view (None clears it)
+fn show_device_identity(window: &MainWindow, identity: Option<&device::DeviceIdentity>) {
+    let Some(identity) = identity else {
+        window.set_firmware_version("-".into());
+        window.set_device_serial("".into());
+        window.set_device_product_id("-".into());
+        window.set_device_mode("-".into());
+        window.set_device_hid_path("-".into());
+        window.set_device_input_paths("-".into());
+        return;
+    };
+
+    window.set_firmware_version(identity.firmware.clone().unwrap_or_else(|| "-".into()).into());
+    window.set_device_serial(identity.serial.clone().unwrap_or_default().into());
+    window.set_device_product_id(format!("{:#06x}", identity.product_id).into());
+    window.set_device_mode(identity.mode_name().into());
+    window.set_device_hid_path(identity.hid_path.clone().into());
+    let inputs: Vec<String> = identity.nodes.input.iter().map(|p| p.display().to_string()).collect();
+    window.set_device_input_paths(if inputs.is_empty() { "-".into() } else { inputs.join("\n").into() });
+}
+
+/// Serial of the connected mouse, if it reported one
+fn current_serial(window: &MainWindow) -> Option<String> {
+    let serial = window.get_device_serial();
+    (!serial.is_empty()).then(|| serial.to_string())
+}
+
+/// Collect the power section of a profile from the UI
+fn power_from_ui(window: &MainWindow) -> profile::PowerSettings {
+    profile::PowerSettings {
+        idle_timeout_secs: window.get_idle_timeout() as u16,
+        low_battery_threshold: window.get_low_battery_threshold() as u8,
+    }
+}
+
+/// Poll the battery of every wireless device not polled yet. Readings reach
+/// the window tagged with the device path.
+fn start_battery_pollers(window: &MainWindow, set: &mut devices::DeviceSet) {
+    for session in set.sessions_mut() {
+        let window_weak = window.as_weak();
+        let device_path = session.info.path.clone();
+        session.start_battery_poller(move |status| {
+            let window_weak = window_weak.clone();
+            let device_path = device_path.clone();
+            let _ = slint::invoke_from_event_loop(move || {
+                if let Some(win) = window_weak.upgrade() {
+                    win.invoke_device_battery_changed(device_path.into(), status.level as i32, status.charging);
+                }
+            });
+        });
+    }
+}
+
+/// Show the last battery reading of the selected device (None clears it)
+fn show_battery(window: &MainWindow, session: Option<&devices::DeviceSession>) {
+    let status = session.and_then(|s| s.battery_status);
+    window.set_battery_level(status.map_or(-1, |s| s.level as i32));
+    window.set_battery_charging(status.is_some_and(|s| s.charging));
+}
+
+/// Show a battery reading in the window, warning once when it runs low
+fn show_battery_status(window: &MainWindow, status: battery::BatteryStatus) {
+    let previous = (window.get_battery_level() >= 0).then(|| battery::BatteryStatus {
+        level: window.get_battery_level() as u8,
+        charging: window.get_battery_charging(),
+    });
+
+    window.set_battery_level(status.level as i32);
+    window.set_battery_charging(status.charging);
+
+    let threshold = window.get_low_battery_threshold().clamp(0, 100) as u8;
+    if status.should_warn(previous, threshold) {
+        warn!("Battery low: {}%", status.level);
+        window.set_status_message(
+            format!("🪫 Battery low ({}%) - connect the charger soon", status.level).into(),
+        );
+    }
+}
+
+/// Map the Lighting tab's effect index and color to an effect (0 = unchanged)
+fn lighting_effect_from_index(index: i32, color: Rgb) -> Option<LedEffect> {
+    match index {
+        1 => Some(LedEffect::Off),
+        2 => Some(LedEffect::Static { color }),
+        3 => Some(LedEffect::Spectrum),
+        4 => Some(LedEffect::Breathing { color, secondary: None }),
+        5 => Some(LedEffect::Wave { direction: WaveDirection::Left }),
+        6 => Some(LedEffect::Reactive { color, speed: 2 }),
+        _ => None,
+    }
+}
+
+/// Inverse of `lighting_effect_from_index`, also returning the effect color if it has one
+fn lighting_effect_to_index(effect: Option<&LedEffect>) -> (i32, Option<Rgb>) {
+    match effect {
+        None => (0, None),
+        Some(LedEffect::Off) => (1, None),
+        Some(LedEffect::Static { color }) => (2, Some(*color)),
+        Some(LedEffect::Spectrum) => (3, None),
+        Some(LedEffect::Breathing { color, .. }) => (4, Some(*color)),
+        Some(LedEffect::Wave { .. }) => (5, None),
+        Some(LedEffect::Reactive { color, .. }) => (6, Some(*color)),
+    }
+}
+
+fn zone_color_property(zone: LedZone, window: &MainWindow) -> slint::Color {
+    match zone {
+        LedZone::Logo => window.get_logo_color(),
+        _ => window.get_scroll_wheel_color(),
     }
 }
 
-/// Load a profile on startup (simplified version without starting remapper)
-fn load_profile_on_startup(
+fn set_zone_color_property(window: &MainWindow, zone: LedZone, color: slint::Color) {
+    match zone {
+        LedZone::Logo => window.set_logo_color(color),
+        LedZone::ScrollWheel => window.set_scroll_wheel_color(color),
+        _ => {}
+    }
+}
+
+/// Software effect presets in the order of the Lighting tab's list (0 = none)
+const SOFTWARE_EFFECT_PRESETS: [&str; 3] = ["gradient", "breathing", "dpi"];
+
+/// Index of a software effect preset in the Lighting tab's list (0 = none)
+fn software_effect_index(preset: &str) -> i32 {
+    SOFTWARE_EFFECT_PRESETS
+        .iter()
+        .position(|p| *p == preset)
+        .map_or(0, |i| i as i32 + 1)
+}
+
+/// Build the software effect selected in the Lighting tab
+fn software_effect_from_ui(window: &MainWindow) -> Option<lighting::SoftwareEffect> {
+    let index = window.get_software_effect();
+    let preset = SOFTWARE_EFFECT_PRESETS.get(usize::try_from(index - 1).ok()?)?;
+    let color = Rgb::new(
+        window.get_lighting_red() as u8,
+        window.get_lighting_green() as u8,
+        window.get_lighting_blue() as u8,
+    );
+    lighting::SoftwareEffect::preset(preset, color)
+}
+
+/// Collect the lighting section of a profile from the Lighting tab
+fn lighting_from_ui(window: &MainWindow) -> LightingSettings {
+    let color = Rgb::new(
+        window.get_lighting_red() as u8,
+        window.get_lighting_green() as u8,
+        window.get_lighting_blue() as u8,
+    );
+
+    // Zones without a color are shown as transparent and not saved
+    let zones = [LedZone::Logo, LedZone::ScrollWheel]
+        .into_iter()
+        .filter_map(|zone| {
+            let c = zone_color_property(zone, window);
+            (c.alpha() > 0).then(|| ZoneColor {
+                zone,
+                color: Rgb::new(c.red(), c.green(), c.blue()),
+            })
+        })
+        .collect();
+
+    LightingSettings {
+        effect: lighting_effect_from_index(window.get_lighting_effect(), color),
+        zones,
+        software: software_effect_from_ui(window),
+    }
+}
+
+/// Show a profile's lighting section in the Lighting tab
+fn show_lighting_in_ui(window: &MainWindow, lighting: &LightingSettings) {
+    let (index, color) = lighting_effect_to_index(lighting.effect.as_ref());
+    window.set_lighting_effect(index);
+    if let Some(color) = color {
+        window.set_lighting_red(color.r as i32);
+        window.set_lighting_green(color.g as i32);
+        window.set_lighting_blue(color.b as i32);
+    }
+
+    set_zone_color_property(window, LedZone::Logo, slint::Color::from_argb_u8(0, 0, 0, 0));
+    set_zone_color_property(window, LedZone::ScrollWheel, slint::Color::from_argb_u8(0, 0, 0, 0));
+    for zone in &lighting.zones {
+        let c = zone.color;
+        set_zone_color_property(window, zone.zone, slint::Color::from_rgb_u8(c.r, c.g, c.b));
+    }
+
+    let software_index = match &lighting.software {
+        None => 0,
+        Some(lighting::SoftwareEffect::Gradient { .. }) => software_effect_index("gradient"),
+        Some(lighting::SoftwareEffect::Breathing { .. }) => software_effect_index("breathing"),
+        Some(lighting::SoftwareEffect::DpiStages { .. }) => software_effect_index("dpi"),
+    };
+    window.set_software_effect(software_index);
+}
+
+/// Show a profile's DPI, polling rate, brightness, power and lighting settings in the UI
+fn show_profile_in_ui(window: &MainWindow, profile: &Profile) {
+    window.set_current_dpi_x(profile.dpi.x as i32);
+    window.set_current_dpi_y(profile.dpi.y as i32);
+    if !profile.dpi.dpi_stages.is_empty() {
+        show_dpi_stages(window, &profile.dpi.dpi_stages, profile.dpi.active_stage);
+    }
+    window.set_polling_rate(profile.polling_rate as i32);
+    window.set_brightness(profile.brightness as i32);
+    window.set_idle_timeout(profile.power.idle_timeout_secs as i32);
+    window.set_low_battery_threshold(profile.power.low_battery_threshold as i32);
+    show_lighting_in_ui(window, &profile.lighting);
+}
+
+/// Start, retarget or stop the selected device's software lighting engine
+fn update_software_lighting(
     window: &MainWindow,
-    device: &Rc<RefCell<Option<device::RazerDevice>>>,
-    remap_mappings: &Rc<RefCell<BTreeMap<u16, remap::MappingTarget>>>,
-    macro_manager: &Rc<RefCell<macro_engine::MacroManager>>,
-    remapper: &Rc<RefCell<Option<remap::Remapper>>>,
-    dpi_poller: &Rc<RefCell<Option<hidpoll::DpiButtonPoller>>>,
-    autoscroll_enabled: &Rc<RefCell<bool>>,
-    autoscroll_overlay: &Rc<RefCell<Option<overlay::AutoscrollOverlay>>>,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    effect: Option<lighting::SoftwareEffect>,
+) -> Result<()> {
+    let dpi = window.get_current_dpi_x() as u16;
+    slots
+        .with_selected(devices, |session| session.set_software_lighting(effect, dpi))
+        .unwrap_or(Ok(()))
+}
+
+/// Load a profile on the selected device: show it in the window, push it to
+/// the device and start, update or stop remapping to match. Returns the names
+/// of settings that failed to apply.
+fn load_profile(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
     profile_name: &str,
-) {
-    match ProfileManager::new() {
-        Ok(manager) => {
-            match manager.load_profile(profile_name) {
-                Ok(profile) => {
-                    // Update UI with profile settings
-                    window.set_current_dpi_x(profile.dpi.x as i32);
-                    window.set_current_dpi_y(profile.dpi.y as i32);
-
-                    // Apply DPI to device if connected
-                    if let Some(ref mut dev) = *device.borrow_mut() {
-                        if let Err(e) = dev.set_dpi(profile.dpi.x, profile.dpi.y) {
-                            error!("Failed to apply profile DPI on startup: {}", e);
-                        }
-                    }
+) -> Result<Vec<&'static str>> {
+    let profile = devices::read_profile(profile_name, current_serial(window).as_deref())?;
+    window.set_pin_profile_to_device(profile.device_serial.is_some());
+    show_profile_in_ui(window, &profile);
+
+    // Profiles without DPI stages keep the ones the window shows
+    let failed = slots
+        .with_selected(devices, |session| {
+            session.ui = Some(parked_ui_from_window(window));
+            let events = worker_events(&window.as_weak(), &session.info.path);
+            session.apply_profile(profile_name, &profile, events)
+        })
+        .unwrap_or_default();
+
+    window.set_remap_enabled(slots.remapper.borrow().is_some());
+    window.set_remap_waiting(false);
+    window.set_autoscroll_enabled(profile.remap.autoscroll);
+    window.set_dpi_buttons_cycle(!profile.remap.dpi_button_keys);
+    update_remap_summary(window, &slots.mappings.borrow());
+    {
+        let mgr = slots.macros.borrow();
+        window.set_macro_list_text(mgr.get_macros_list_text().into());
+        window.set_available_macros(mgr.get_available_macros_string().into());
+    }
+    show_device_list(window, &devices.borrow());
+    Ok(failed)
+}
 
-                    // Load remap mappings into state
-                    {
-                        let mut map = remap_mappings.borrow_mut();
-                        map.clear();
-                        for m in &profile.remap.mappings {
-                            map.insert(
-                                m.source,
-                                remap::MappingTarget {
-                                    base: m.target,
-                                    mods: remap::Modifiers {
-                                        ctrl: m.ctrl,
-                                        alt: m.alt,
-                                        shift: m.shift,
-                                        meta: m.meta,
-                                    },
-                                },
-                            );
-                        }
-                    }
-                    window.set_remap_enabled(profile.remap.enabled);
-                    update_remap_summary(window, &remap_mappings.borrow());
-                    
-                    // Load autoscroll setting from profile
-                    *autoscroll_enabled.borrow_mut() = profile.remap.autoscroll;
-                    window.set_autoscroll_enabled(profile.remap.autoscroll);
-                    
-                    // Load macros from profile
-                    {
-                        let mut mgr = macro_manager.borrow_mut();
-                        mgr.load_from_profile(profile.macros.clone());
-                        window.set_macro_list_text(mgr.get_macros_list_text().into());
-                        window.set_available_macros(mgr.get_available_macros_string().into());
-                    }
+/// Load a profile on a device the window does not show. The window's
+/// settings for it are parked in its session until it is selected.
+fn load_parked_profile(
+    window: &slint::Weak<MainWindow>,
+    session: &mut devices::DeviceSession,
+    profile_name: &str,
+) -> Result<Vec<&'static str>> {
+    let events = worker_events(window, &session.info.path);
+    session.load_profile(profile_name, events)
+}
 
-                    // Start the remapper if profile has it enabled
-                    if profile.remap.enabled {
-                        let autoscroll = profile.remap.autoscroll;  // Use profile setting
-                        info!("Starting remapper from startup profile (autoscroll: {})", autoscroll);
-                        start_remapper(window, device, remapper, remap_mappings, dpi_poller, autoscroll_overlay, autoscroll, macro_manager);
-                    }
+/// Report a loaded profile in the status line
+fn show_profile_loaded(window: &MainWindow, profile_name: &str, failed: &[&str]) {
+    if failed.is_empty() {
+        window.set_status_message(format!("Profile '{}' loaded!", profile_name).into());
+    } else {
+        window.set_status_message(
+            format!("Profile '{}' loaded, but failed to apply: {}", profile_name, failed.join(", ")).into(),
+        );
+    }
+}
 
-                    window.set_status_message(format!("Profile '{}' loaded!", profile_name).into());
-                    info!("Loaded default profile '{}' on startup", profile_name);
-                }
-                Err(e) => {
-                    warn!("Failed to load default profile '{}': {}", profile_name, e);
-                    window.set_status_message(format!("Profile '{}' not found", profile_name).into());
-                }
+/// Load a device's startup profile, or the profile it had before it was
+/// replugged, on the selected device
+fn load_selected_device_profile(
+    window: &MainWindow,
+    slots: &DeviceSlots,
+    devices: &Rc<RefCell<devices::DeviceSet>>,
+    profile_name: &str,
+) {
+    match load_profile(window, slots, devices, profile_name) {
+        Ok(failed) => {
+            if !failed.is_empty() {
+                warn!("Profile '{}': failed to apply {}", profile_name, failed.join(", "));
             }
+            show_profile_loaded(window, profile_name, &failed);
         }
         Err(e) => {
-            error!("Failed to create profile manager: {}", e);
+            warn!("Failed to load profile '{}': {:#}", profile_name, e);
+            window.set_status_message(format!("Profile '{}' not loaded", profile_name).into());
+        }
+    }
+}
+
+/// Load a device's startup profile, or the profile it had before it was
+/// replugged, on a device the window does not show
+fn load_parked_device_profile(
+    window: &slint::Weak<MainWindow>,
+    session: &mut devices::DeviceSession,
+    profile_name: &str,
+) {
+    match load_parked_profile(window, session, profile_name) {
+        Ok(failed) if !failed.is_empty() => {
+            warn!("{}: failed to apply {}", session.identity.name, failed.join(", "));
         }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to load profile '{}' on {}: {:#}", profile_name, session.identity.name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::tests::session;
+
+    #[test]
+    fn test_request_for_parked_device_keeps_selection() {
+        let devices = Rc::new(RefCell::new(devices::DeviceSet::new()));
+        devices.borrow_mut().push(session("/dev/hidraw1", "PM1111"));
+        devices.borrow_mut().push(session("/dev/hidraw4", "PM2222"));
+        devices.borrow_mut().select(0);
+
+        let command = control::Command::ToggleRemap { enabled: None, device: None };
+        assert_eq!(parked_request_target(&devices.borrow(), &command).unwrap(), None);
+        let command = control::Command::SwitchProfile { profile: "fps".into(), device: Some("PM3333".into()) };
+        assert!(parked_request_target(&devices.borrow(), &command).is_err());
+
+        // Requests for the second mouse run on its session, not through the window
+        let window = slint::Weak::default();
+        let command = control::Command::ToggleRemap { enabled: Some(false), device: Some("PM2222".into()) };
+        let target = parked_request_target(&devices.borrow(), &command).unwrap();
+        assert_eq!(target, Some(1));
+        assert!(run_parked_command(&window, &devices, 1, command).is_ok());
+        let command = control::Command::SetDpi { dpi: 800, dpi_y: None, device: Some("/dev/hidraw4".into()) };
+        assert!(run_parked_command(&window, &devices, 1, command).is_err());
+        assert_eq!(devices.borrow().selected_index(), Some(0));
     }
 }
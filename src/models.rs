@@ -0,0 +1,339 @@
+//! Supported Razer device models
+//!
+//! Data-driven table describing every mouse RazerLinux knows how to talk to.
+//! Device detection, the protocol layer, the DPI button poller and the UI all
+//! consult this table instead of hard-coding a single product ID.
+//!
+//! Product IDs and transaction IDs are taken from the OpenRazer project.
+
+use crate::protocol::{TRANSACTION_ID_NEW, TRANSACTION_ID_OLD, TRANSACTION_ID_WIRELESS};
+use serde::{Deserialize, Serialize};
+
+/// Optional protocol features a device model supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// DPI get/set (mouse class 0x04)
+    Dpi,
+    /// On-device DPI stage list cycled by the DPI buttons
+    DpiStages,
+    /// Polling rate get/set
+    PollingRate,
+    /// Driver/normal mode switching
+    DeviceMode,
+    /// Firmware version query
+    Firmware,
+    /// Serial number query
+    Serial,
+    /// Battery level and charging status (power class 0x07)
+    Battery,
+}
+
+/// LED zones addressable through the LED command class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedZone {
+    ScrollWheel,
+    Logo,
+    Backlight,
+    Underglow,
+}
+
+impl LedZone {
+    /// LED ID used in LED class commands (OpenRazer naming)
+    pub fn led_id(&self) -> u8 {
+        match self {
+            LedZone::ScrollWheel => 0x01,
+            LedZone::Logo => 0x04,
+            LedZone::Backlight => 0x05,
+            LedZone::Underglow => 0x0A,
+        }
+    }
+
+    /// Human-readable zone name
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedZone::ScrollWheel => "Scroll Wheel",
+            LedZone::Logo => "Logo",
+            LedZone::Backlight => "Backlight",
+            LedZone::Underglow => "Underglow",
+        }
+    }
+}
+
+/// Physical button layout of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonLayout {
+    /// Number of side panel buttons (sent as KEY_1..KEY_EQUAL in Driver Mode)
+    pub side_buttons: u8,
+    /// Whether the mouse has BTN_SIDE/BTN_EXTRA thumb buttons
+    pub thumb_buttons: bool,
+    /// Whether the DPI buttons report through hidraw (see hidpoll.rs)
+    pub dpi_buttons: bool,
+}
+
+/// Static description of a supported device model
+#[derive(Debug)]
+pub struct DeviceModel {
+    /// USB product ID
+    pub product_id: u16,
+    /// Marketing name
+    pub name: &'static str,
+    /// Transaction ID the device answers to
+    pub transaction_id: u8,
+    /// Minimum supported DPI
+    pub dpi_min: u16,
+    /// Maximum supported DPI
+    pub dpi_max: u16,
+    /// Supported protocol features
+    pub features: &'static [Feature],
+    /// Button layout
+    pub buttons: ButtonLayout,
+    /// LED zones
+    pub led_zones: &'static [LedZone],
+    /// Per-LED matrix as (rows, columns) for extended matrix custom frames,
+    /// None for devices that only take legacy LED commands
+    pub matrix: Option<(u8, u8)>,
+    /// Whether the device connects through a wireless dongle
+    pub wireless: bool,
+    /// Whether the idle timeout and low-battery threshold survive a power cycle.
+    /// If not, they are sent again from the active profile on connect.
+    pub stores_power_settings: bool,
+}
+
+impl DeviceModel {
+    /// Check whether this model supports a feature
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Check whether a DPI value is within the supported range
+    pub fn dpi_in_range(&self, dpi: u16) -> bool {
+        (self.dpi_min..=self.dpi_max).contains(&dpi)
+    }
+}
+
+/// Features shared by all mice currently in the table
+const MOUSE_FEATURES: &[Feature] = &[
+    Feature::Dpi,
+    Feature::DpiStages,
+    Feature::PollingRate,
+    Feature::DeviceMode,
+    Feature::Firmware,
+    Feature::Serial,
+];
+
+/// Features of mice that also run on a battery
+const WIRELESS_MOUSE_FEATURES: &[Feature] = &[
+    Feature::Dpi,
+    Feature::DpiStages,
+    Feature::PollingRate,
+    Feature::DeviceMode,
+    Feature::Firmware,
+    Feature::Serial,
+    Feature::Battery,
+];
+
+/// Razer Naga Trinity Product ID
+pub const NAGA_TRINITY_PID: u16 = 0x0067;
+
+/// All supported device models
+pub static DEVICE_MODELS: &[DeviceModel] = &[
+    DeviceModel {
+        product_id: NAGA_TRINITY_PID,
+        name: "Razer Naga Trinity",
+        transaction_id: TRANSACTION_ID_OLD,
+        dpi_min: 100,
+        dpi_max: 16000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 12, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 3)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x0096,
+        name: "Razer Naga X",
+        transaction_id: TRANSACTION_ID_WIRELESS,
+        dpi_min: 100,
+        dpi_max: 18000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 12, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 2)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x008F,
+        name: "Razer Naga Pro (Wired)",
+        transaction_id: TRANSACTION_ID_WIRELESS,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: WIRELESS_MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 12, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 3)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x0090,
+        name: "Razer Naga Pro (Wireless)",
+        transaction_id: TRANSACTION_ID_WIRELESS,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: WIRELESS_MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 12, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 3)),
+        wireless: true,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x005C,
+        name: "Razer DeathAdder Elite",
+        transaction_id: TRANSACTION_ID_NEW,
+        dpi_min: 100,
+        dpi_max: 16000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: false },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: None,
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x0084,
+        name: "Razer DeathAdder V2",
+        transaction_id: TRANSACTION_ID_NEW,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: false },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 2)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x0085,
+        name: "Razer Basilisk V2",
+        transaction_id: TRANSACTION_ID_NEW,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo],
+        matrix: Some((1, 14)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x0099,
+        name: "Razer Basilisk V3",
+        transaction_id: TRANSACTION_ID_WIRELESS,
+        dpi_min: 100,
+        dpi_max: 26000,
+        features: MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: true },
+        led_zones: &[LedZone::ScrollWheel, LedZone::Logo, LedZone::Underglow],
+        matrix: Some((1, 13)),
+        wireless: false,
+        stores_power_settings: false,
+    },
+    DeviceModel {
+        product_id: 0x007A,
+        name: "Razer Viper Ultimate (Wired)",
+        transaction_id: TRANSACTION_ID_NEW,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: WIRELESS_MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: false },
+        led_zones: &[LedZone::Logo],
+        matrix: Some((1, 1)),
+        wireless: false,
+        stores_power_settings: true,
+    },
+    DeviceModel {
+        product_id: 0x007B,
+        name: "Razer Viper Ultimate (Wireless)",
+        transaction_id: TRANSACTION_ID_NEW,
+        dpi_min: 100,
+        dpi_max: 20000,
+        features: WIRELESS_MOUSE_FEATURES,
+        buttons: ButtonLayout { side_buttons: 0, thumb_buttons: true, dpi_buttons: false },
+        led_zones: &[LedZone::Logo],
+        matrix: Some((1, 1)),
+        wireless: true,
+        stores_power_settings: true,
+    },
+];
+
+/// Look up a device model by USB product ID
+pub fn find_model(product_id: u16) -> Option<&'static DeviceModel> {
+    DEVICE_MODELS.iter().find(|m| m.product_id == product_id)
+}
+
+/// Check whether a product ID belongs to a supported model
+pub fn is_supported(product_id: u16) -> bool {
+    find_model(product_id).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_naga_trinity() {
+        let model = find_model(NAGA_TRINITY_PID).unwrap();
+        assert_eq!(model.name, "Razer Naga Trinity");
+        assert_eq!(model.transaction_id, TRANSACTION_ID_OLD);
+        assert_eq!(model.buttons.side_buttons, 12);
+        assert!(model.supports(Feature::Dpi));
+    }
+
+    #[test]
+    fn test_wireless_models_report_battery() {
+        for model in DEVICE_MODELS.iter().filter(|m| m.wireless) {
+            assert!(model.supports(Feature::Battery), "{} should report battery", model.name);
+        }
+    }
+
+    #[test]
+    fn test_unknown_pid() {
+        assert!(find_model(0xFFFF).is_none());
+        assert!(!is_supported(0xFFFF));
+    }
+
+    #[test]
+    fn test_product_ids_unique() {
+        for (i, a) in DEVICE_MODELS.iter().enumerate() {
+            for b in &DEVICE_MODELS[i + 1..] {
+                assert_ne!(a.product_id, b.product_id, "{} and {} share a PID", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dpi_ranges_valid() {
+        for model in DEVICE_MODELS {
+            assert!(model.dpi_min < model.dpi_max, "{} has an empty DPI range", model.name);
+            assert!(model.dpi_in_range(800), "{} should support 800 DPI", model.name);
+        }
+    }
+
+    #[test]
+    fn test_matrix_fits_one_report() {
+        for model in DEVICE_MODELS {
+            if let Some((rows, cols)) = model.matrix {
+                assert!(rows > 0 && cols > 0, "{} has an empty matrix", model.name);
+                assert!(
+                    cols as usize <= crate::protocol::MAX_FRAME_COLUMNS,
+                    "{} matrix row does not fit in one report",
+                    model.name
+                );
+            }
+        }
+    }
+}
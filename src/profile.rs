@@ -2,11 +2,15 @@
 //!
 //! Handles saving and loading mouse configuration profiles to TOML files.
 
+use crate::device::RazerDevice;
+use crate::lighting::SoftwareEffect;
+use crate::models::{Feature, LedZone};
+use crate::protocol::{LedEffect, Rgb};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
-use tracing::info;
+use tracing::{error, info};
 
 /// A mouse configuration profile
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +22,10 @@ pub struct Profile {
     #[serde(default)]
     pub description: String,
 
+    /// Serial number of the mouse this profile is pinned to (None = any mouse)
+    #[serde(default)]
+    pub device_serial: Option<String>,
+
     /// DPI settings
     pub dpi: DpiSettings,
 
@@ -29,6 +37,14 @@ pub struct Profile {
     #[serde(default = "default_brightness")]
     pub brightness: u8,
 
+    /// Lighting effect and per-zone colors
+    #[serde(default)]
+    pub lighting: LightingSettings,
+
+    /// Sleep and battery settings for wireless mice
+    #[serde(default)]
+    pub power: PowerSettings,
+
     /// Software remapping settings (evdev/uinput)
     #[serde(default)]
     pub remap: RemapSettings,
@@ -56,24 +72,101 @@ pub struct DpiSettings {
     /// Whether X and Y are linked
     #[serde(default = "default_linked")]
     pub linked: bool,
+    /// DPI stages (X, Y) cycled by the DPI buttons, up to 5 (empty = leave the device's list)
+    #[serde(default)]
+    pub dpi_stages: Vec<(u16, u16)>,
+    /// Index of the active stage in `dpi_stages`
+    #[serde(default)]
+    pub active_stage: usize,
 }
 
 fn default_linked() -> bool {
     true
 }
 
+/// Sleep and battery settings for wireless mice, stored in profiles.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PowerSettings {
+    /// Seconds of inactivity before the mouse goes to sleep (60-900)
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_secs: u16,
+
+    /// Battery level (percent, 5-25) at which the mouse signals low battery
+    #[serde(default = "default_low_battery_threshold")]
+    pub low_battery_threshold: u8,
+}
+
+fn default_idle_timeout() -> u16 {
+    300
+}
+
+fn default_low_battery_threshold() -> u8 {
+    15
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: default_idle_timeout(),
+            low_battery_threshold: default_low_battery_threshold(),
+        }
+    }
+}
+
+/// Lighting settings stored in profiles.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct LightingSettings {
+    /// Device-wide effect (None = leave the device as it is)
+    #[serde(default)]
+    pub effect: Option<LedEffect>,
+
+    /// Static colors for individual LED zones, applied after the effect
+    #[serde(default)]
+    pub zones: Vec<ZoneColor>,
+
+    /// Effect rendered by the software lighting engine, replacing the hardware
+    /// effect while the profile is active
+    #[serde(default)]
+    pub software: Option<SoftwareEffect>,
+}
+
+impl LightingSettings {
+    /// Set the hardware effect, then the zone colors on top of it
+    pub fn apply_to_device(&self, dev: &mut RazerDevice) -> crate::error::Result<()> {
+        if let Some(effect) = &self.effect {
+            dev.set_effect(effect)?;
+        }
+        for zone in &self.zones {
+            dev.set_zone_color(zone.zone, zone.color)?;
+        }
+        Ok(())
+    }
+}
+
+/// Static color for one LED zone
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ZoneColor {
+    pub zone: LedZone,
+    pub color: Rgb,
+}
+
 impl Default for Profile {
     fn default() -> Self {
         Self {
             name: "Default".to_string(),
             description: "Default profile".to_string(),
+            device_serial: None,
             dpi: DpiSettings {
                 x: 800,
                 y: 800,
                 linked: true,
+                dpi_stages: Vec::new(),
+                active_stage: 0,
             },
             polling_rate: 1000,
             brightness: 255,
+            lighting: LightingSettings::default(),
+            power: PowerSettings::default(),
             remap: RemapSettings::default(),
             macros: Vec::new(),
         }
@@ -89,18 +182,74 @@ impl Profile {
         }
     }
 
+    /// Whether this profile may be applied to the mouse with the given serial.
+    /// Unpinned profiles fit any mouse, and so does a mouse whose serial is unknown.
+    pub fn fits_device(&self, serial: Option<&str>) -> bool {
+        match (&self.device_serial, serial) {
+            (Some(pinned), Some(serial)) => pinned == serial,
+            _ => true,
+        }
+    }
+
+    /// Push the profile's DPI, polling rate, power and hardware lighting
+    /// settings to a device. Returns the names of settings that failed.
+    pub fn apply_to_device(&self, dev: &mut RazerDevice) -> Vec<&'static str> {
+        let mut failed = Vec::new();
+        if !self.dpi.dpi_stages.is_empty() && dev.model().supports(Feature::DpiStages) {
+            // Stages go first so the explicit DPI below stays in effect
+            if let Err(e) = dev.set_dpi_stages(&self.dpi.dpi_stages, self.dpi.active_stage) {
+                error!("Failed to apply profile DPI stages: {}", e);
+                failed.push("DPI stages");
+            }
+        }
+        if let Err(e) = dev.set_dpi(self.dpi.x, self.dpi.y) {
+            error!("Failed to apply profile DPI: {}", e);
+            failed.push("DPI");
+        }
+        if let Err(e) = dev.set_polling_rate(self.polling_rate) {
+            error!("Failed to apply profile polling rate: {}", e);
+            failed.push("polling rate");
+        }
+        if dev.model().supports(Feature::Battery) {
+            if let Err(e) = dev.set_idle_time(self.power.idle_timeout_secs) {
+                error!("Failed to apply profile idle timeout: {}", e);
+                failed.push("idle timeout");
+            }
+            if let Err(e) = dev.set_low_battery_threshold(self.power.low_battery_threshold) {
+                error!("Failed to apply profile low-battery threshold: {}", e);
+                failed.push("low-battery threshold");
+            }
+        }
+        if !dev.model().led_zones.is_empty() {
+            if let Err(e) = self.lighting.apply_to_device(dev) {
+                error!("Failed to apply profile lighting: {}", e);
+                failed.push("lighting");
+            }
+            if let Err(e) = dev.set_brightness(self.brightness) {
+                error!("Failed to apply profile brightness: {}", e);
+                failed.push("brightness");
+            }
+        }
+        failed
+    }
+
     /// Create a profile from current device settings
     pub fn from_device_settings(name: impl Into<String>, dpi_x: u16, dpi_y: u16) -> Self {
         Self {
             name: name.into(),
             description: format!("Profile created from device settings"),
+            device_serial: None,
             dpi: DpiSettings {
                 x: dpi_x,
                 y: dpi_y,
                 linked: dpi_x == dpi_y,
+                dpi_stages: Vec::new(),
+                active_stage: 0,
             },
             polling_rate: 1000,
             brightness: 255,
+            lighting: LightingSettings::default(),
+            power: PowerSettings::default(),
             remap: RemapSettings::default(),
             macros: Vec::new(),
         }
@@ -122,16 +271,24 @@ pub struct RemapSettings {
     #[serde(default)]
     pub source_device: Option<String>,
 
+    /// Send F13/F14 from the DPI buttons instead of cycling the DPI stages
+    #[serde(default)]
+    pub dpi_button_keys: bool,
+
     /// Key/button code mappings (Linux input codes)
     #[serde(default)]
     pub mappings: Vec<RemapMapping>,
+
+    /// Hypershift layers, resolved before the base mappings while active
+    #[serde(default)]
+    pub layers: Vec<RemapLayer>,
     
     /// User-defined macros
     #[serde(default)]
     pub macros: Vec<Macro>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RemapMapping {
     pub source: u16,
         /// Base key/button code
@@ -150,6 +307,36 @@ pub struct RemapMapping {
         pub macro_id: Option<u32>,
 }
 
+/// A Hypershift layer: while its key activates it, buttons resolve through
+/// its mappings first. Buttons it doesn't map keep their base mapping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemapLayer {
+    /// Label for the layer (e.g. "CAD")
+    #[serde(default)]
+    pub name: String,
+    /// Source code of the button that activates the layer
+    pub key: u16,
+    /// How the layer key activates the layer
+    #[serde(default)]
+    pub mode: LayerMode,
+    /// Mappings while the layer is active
+    #[serde(default)]
+    pub mappings: Vec<RemapMapping>,
+}
+
+/// How a layer key activates its layer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerMode {
+    /// Active while the key is held
+    #[default]
+    Hold,
+    /// Each press turns the layer on or off
+    Toggle,
+    /// Active for the next button press only
+    OneShot,
+}
+
 /// A macro action (single step in a macro)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MacroAction {
@@ -406,6 +593,136 @@ brightness = 255
         let profile: Profile = toml::from_str(toml).unwrap();
         assert_eq!(profile.name, "Gaming");
         assert_eq!(profile.dpi.x, 1600);
+        assert_eq!(profile.lighting, LightingSettings::default());
+        assert_eq!(profile.power, PowerSettings::default());
+        assert!(profile.dpi.dpi_stages.is_empty());
+    }
+
+    #[test]
+    fn test_dpi_stages_roundtrip() {
+        let mut profile = Profile::new("Stages");
+        profile.dpi.dpi_stages = vec![(400, 400), (800, 800), (1600, 1600)];
+        profile.dpi.active_stage = 1;
+
+        let toml = toml::to_string_pretty(&profile).unwrap();
+        let parsed: Profile = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.dpi.dpi_stages, profile.dpi.dpi_stages);
+        assert_eq!(parsed.dpi.active_stage, 1);
+    }
+
+    #[test]
+    fn test_device_serial_pinning() {
+        let mut profile = Profile::new("Naga A");
+        assert!(profile.fits_device(Some("PM1234")));
+
+        profile.device_serial = Some("PM1234".into());
+        assert!(profile.fits_device(Some("PM1234")));
+        assert!(!profile.fits_device(Some("PM9999")));
+        assert!(profile.fits_device(None));
+
+        let toml = toml::to_string_pretty(&profile).unwrap();
+        let parsed: Profile = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.device_serial.as_deref(), Some("PM1234"));
+    }
+
+    #[test]
+    fn test_remap_layers() {
+        let toml = r#"
+name = "CAD"
+
+[dpi]
+x = 800
+y = 800
+
+[remap]
+enabled = true
+
+[[remap.mappings]]
+source = 2
+target = 30
+
+[[remap.layers]]
+name = "Hypershift"
+key = 13
+
+[[remap.layers.mappings]]
+source = 2
+target = 46
+ctrl = true
+
+[[remap.layers]]
+key = 12
+mode = "one_shot"
+"#;
+        let profile: Profile = toml::from_str(toml).unwrap();
+        let layers = &profile.remap.layers;
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].mode, LayerMode::Hold);
+        assert_eq!(layers[0].mappings[0].target, 46);
+        assert!(layers[0].mappings[0].ctrl);
+        assert_eq!(layers[1].mode, LayerMode::OneShot);
+        assert!(layers[1].mappings.is_empty());
+
+        let toml = toml::to_string_pretty(&profile).unwrap();
+        let parsed: Profile = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.remap.layers, profile.remap.layers);
+    }
+
+    #[test]
+    fn test_power_settings_roundtrip() {
+        let mut profile = Profile::new("Wireless");
+        profile.power.idle_timeout_secs = 600;
+        profile.power.low_battery_threshold = 10;
+
+        let toml = toml::to_string_pretty(&profile).unwrap();
+        assert!(toml.contains("[power]"));
+
+        let parsed: Profile = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.power, profile.power);
+    }
+
+    #[test]
+    fn test_lighting_roundtrip() {
+        let mut profile = Profile::new("RGB");
+        profile.lighting.effect = Some(LedEffect::Breathing {
+            color: Rgb::new(0, 255, 0),
+            secondary: None,
+        });
+        profile.lighting.zones.push(ZoneColor {
+            zone: LedZone::ScrollWheel,
+            color: Rgb::new(255, 0, 0),
+        });
+
+        let toml = toml::to_string_pretty(&profile).unwrap();
+        assert!(toml.contains("type = \"breathing\""));
+        assert!(toml.contains("zone = \"scroll_wheel\""));
+
+        let parsed: Profile = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.lighting, profile.lighting);
+    }
+
+    #[test]
+    fn test_software_lighting_deserialization() {
+        let toml = r#"
+name = "Status"
+
+[dpi]
+x = 800
+y = 800
+
+[lighting.software]
+type = "breathing"
+colors = [{ r = 255, g = 0, b = 0 }, { r = 0, g = 0, b = 255 }]
+"#;
+
+        let profile: Profile = toml::from_str(toml).unwrap();
+        match profile.lighting.software {
+            Some(SoftwareEffect::Breathing { colors, period_ms }) => {
+                assert_eq!(colors.len(), 2);
+                assert_eq!(period_ms, 3000);
+            }
+            other => panic!("unexpected software effect: {:?}", other),
+        }
     }
 
     #[test]
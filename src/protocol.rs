@@ -14,17 +14,33 @@
 //! - Byte 88: CRC (XOR of bytes 2-87)
 //! - Byte 89: Reserved (0x00)
 
-use anyhow::{Result, anyhow};
+use crate::error::{RazerError, Result};
+use serde::{Deserialize, Serialize};
 
 /// Variable storage types used by Razer devices
 pub const VARSTORE: u8 = 0x01; // Store in device persistent memory
 pub const NOSTORE: u8 = 0x00; // Don't store, temporary
 
+/// Response status codes
+pub const STATUS_NEW: u8 = 0x00;
+pub const STATUS_BUSY: u8 = 0x01;
+pub const STATUS_SUCCESS: u8 = 0x02;
+pub const STATUS_FAILURE: u8 = 0x03;
+pub const STATUS_TIMEOUT: u8 = 0x04;
+pub const STATUS_NOT_SUPPORTED: u8 = 0x05;
+
 /// Transaction IDs for different device types
 pub const TRANSACTION_ID_OLD: u8 = 0xFF; // Older devices like Naga Trinity
 pub const TRANSACTION_ID_NEW: u8 = 0x3F; // Newer Chroma devices  
 pub const TRANSACTION_ID_WIRELESS: u8 = 0x1F; // Wireless devices (newer)
 
+/// All known transaction IDs, in the order they are probed
+pub const TRANSACTION_IDS: [u8; 3] = [
+    TRANSACTION_ID_OLD,
+    TRANSACTION_ID_NEW,
+    TRANSACTION_ID_WIRELESS,
+];
+
 /// Command classes for Razer devices
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -32,6 +48,8 @@ pub enum CommandClass {
     General = 0x00,
     Led = 0x03,
     Mouse = 0x04,
+    Power = 0x07,
+    ExtendedMatrix = 0x0F,
 }
 
 /// Specific commands
@@ -48,6 +66,30 @@ pub enum Command {
     // Mouse commands
     GetDpi,
     SetDpi,
+    GetDpiStages,
+    SetDpiStages,
+
+    // Power commands (wireless devices)
+    GetBatteryLevel,
+    GetChargingStatus,
+    GetIdleTime,
+    SetIdleTime,
+    GetLowBatteryThreshold,
+    SetLowBatteryThreshold,
+
+    // LED commands
+    SetLedState,
+    SetLedRgb,
+    SetLedEffect,
+    SetLedBrightness,
+    GetLedBrightness,
+    SetMatrixEffect,
+
+    // Extended matrix commands (newer Chroma devices)
+    SetExtendedEffect,
+    SetCustomFrame,
+    SetExtendedBrightness,
+    GetExtendedBrightness,
 }
 
 impl Command {
@@ -65,6 +107,30 @@ impl Command {
             // Mouse commands (class 0x04)
             Command::GetDpi => (0x04, 0x85),
             Command::SetDpi => (0x04, 0x05),
+            Command::GetDpiStages => (0x04, 0x86),
+            Command::SetDpiStages => (0x04, 0x06),
+
+            // Power commands (class 0x07)
+            Command::GetBatteryLevel => (0x07, 0x80),
+            Command::GetChargingStatus => (0x07, 0x84),
+            Command::GetIdleTime => (0x07, 0x83),
+            Command::SetIdleTime => (0x07, 0x03),
+            Command::GetLowBatteryThreshold => (0x07, 0x81),
+            Command::SetLowBatteryThreshold => (0x07, 0x01),
+
+            // LED commands (class 0x03)
+            Command::SetLedState => (0x03, 0x00),
+            Command::SetLedRgb => (0x03, 0x01),
+            Command::SetLedEffect => (0x03, 0x02),
+            Command::SetLedBrightness => (0x03, 0x03),
+            Command::GetLedBrightness => (0x03, 0x83),
+            Command::SetMatrixEffect => (0x03, 0x0A),
+
+            // Extended matrix commands (class 0x0F)
+            Command::SetExtendedEffect => (0x0F, 0x02),
+            Command::SetCustomFrame => (0x0F, 0x03),
+            Command::SetExtendedBrightness => (0x0F, 0x04),
+            Command::GetExtendedBrightness => (0x0F, 0x84),
         }
     }
 
@@ -80,6 +146,262 @@ impl Command {
             Command::SetDeviceMode => 0x02,      // Takes 2 bytes (mode, param)
             Command::GetDpi => 0x07, // CRITICAL: must be 0x07 for DPI query
             Command::SetDpi => 0x07,
+            Command::GetDpiStages => 0x26, // Storage, active, count + 7 bytes per stage
+            Command::SetDpiStages => 0x26,
+            Command::GetBatteryLevel => 0x02,   // Returns 2 bytes (reserved, level 0-255)
+            Command::GetChargingStatus => 0x02, // Returns 2 bytes (reserved, 0/1)
+            Command::GetIdleTime => 0x02,       // Seconds as big-endian u16
+            Command::SetIdleTime => 0x02,
+            Command::GetLowBatteryThreshold => 0x01, // Threshold scaled to 0-255
+            Command::SetLowBatteryThreshold => 0x01,
+            Command::SetLedState => 0x03,       // Storage, LED ID, on/off
+            Command::SetLedRgb => 0x05,         // Storage, LED ID, R, G, B
+            Command::SetLedEffect => 0x03,      // Storage, LED ID, effect
+            Command::SetLedBrightness => 0x03,  // Storage, LED ID, brightness
+            Command::GetLedBrightness => 0x03,
+            Command::SetMatrixEffect => 0x01,   // Varies by effect, see LedEffect::matrix_args
+            Command::SetExtendedEffect => 0x0C, // Varies by effect, see LedEffect::extended_args
+            Command::SetCustomFrame => 0x47,    // Varies by row length, see custom_frame_args
+            Command::SetExtendedBrightness => 0x03, // Storage, LED ID, brightness
+            Command::GetExtendedBrightness => 0x03,
+        }
+    }
+}
+
+/// Most argument bytes a single report carries; longer payloads are chained
+pub const MAX_REPORT_ARGS: usize = 80;
+
+/// Most DPI stages a device stores
+pub const MAX_DPI_STAGES: usize = 5;
+
+/// Bytes per stage in DPI stage reports: stage number, X (BE), Y (BE), 2 reserved
+const DPI_STAGE_LEN: usize = 7;
+
+/// Arguments for `Command::SetDpiStages`.
+///
+/// `active` is a zero-based index into `stages`; the device numbers the
+/// active stage from 1.
+pub fn dpi_stages_args(storage: u8, stages: &[(u16, u16)], active: usize) -> Result<Vec<u8>> {
+    if stages.is_empty() || stages.len() > MAX_DPI_STAGES {
+        return Err(RazerError::InvalidArgument(format!(
+            "DPI stage list must have 1-{} stages (got {})",
+            MAX_DPI_STAGES,
+            stages.len()
+        )));
+    }
+    if active >= stages.len() {
+        return Err(RazerError::InvalidArgument(format!(
+            "Active DPI stage {} out of range",
+            active + 1
+        )));
+    }
+
+    let mut args = vec![storage, active as u8 + 1, stages.len() as u8];
+    for (i, &(x, y)) in stages.iter().enumerate() {
+        args.push(i as u8);
+        args.extend(x.to_be_bytes());
+        args.extend(y.to_be_bytes());
+        args.extend([0x00, 0x00]);
+    }
+    // The firmware expects the full-size report regardless of the stage count
+    args.resize(Command::SetDpiStages.data_size() as usize, 0x00);
+    Ok(args)
+}
+
+/// Parse the arguments of a `Command::GetDpiStages` response into the stage
+/// list and the zero-based active stage
+pub fn parse_dpi_stages(args: &[u8]) -> Result<(Vec<(u16, u16)>, usize)> {
+    if args.len() < 3 {
+        return Err(RazerError::InvalidResponse("DPI stage response too short".into()));
+    }
+
+    let count = (args[2] as usize).min(MAX_DPI_STAGES);
+    let stages: Vec<(u16, u16)> = args[3..]
+        .chunks_exact(DPI_STAGE_LEN)
+        .take(count)
+        .map(|s| {
+            (
+                u16::from_be_bytes([s[1], s[2]]),
+                u16::from_be_bytes([s[3], s[4]]),
+            )
+        })
+        .collect();
+    if stages.len() != count {
+        return Err(RazerError::InvalidResponse("DPI stage response truncated".into()));
+    }
+
+    // The active stage is 1-based; clamp whatever the firmware reports
+    let active = (args[1] as usize).saturating_sub(1).min(count.saturating_sub(1));
+    Ok((stages, active))
+}
+
+/// Parse the arguments of a `Command::GetSerialNumber` response.
+/// The serial is NUL-padded ASCII; an all-blank serial is an error.
+pub fn parse_serial(args: &[u8]) -> Result<String> {
+    let end = args.iter().position(|&b| b == 0).unwrap_or(args.len());
+    let raw = &args[..end];
+    if !raw.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        return Err(RazerError::InvalidResponse("serial number is not ASCII".into()));
+    }
+
+    let serial = String::from_utf8_lossy(raw).trim().to_string();
+    if serial.is_empty() {
+        return Err(RazerError::InvalidResponse("device reported an empty serial number".into()));
+    }
+    Ok(serial)
+}
+
+/// Idle timeout range accepted by wireless devices (seconds)
+pub const IDLE_TIME_MIN: u16 = 60;
+pub const IDLE_TIME_MAX: u16 = 900;
+
+/// Low-battery threshold range accepted by wireless devices (percent)
+pub const LOW_BATTERY_THRESHOLD_MIN: u8 = 5;
+pub const LOW_BATTERY_THRESHOLD_MAX: u8 = 25;
+
+/// Convert a low-battery threshold in percent to the 0-255 scale the device uses
+pub fn low_battery_threshold_to_raw(percent: u8) -> u8 {
+    let percent = percent.clamp(LOW_BATTERY_THRESHOLD_MIN, LOW_BATTERY_THRESHOLD_MAX) as u16;
+    ((percent * 255 + 50) / 100) as u8
+}
+
+/// Convert a raw 0-255 threshold reported by the device to percent
+pub fn low_battery_threshold_from_raw(raw: u8) -> u8 {
+    ((raw as u16 * 100 + 127) / 255) as u8
+}
+
+/// Per-LED effect IDs for `Command::SetLedEffect`
+pub const LED_EFFECT_STATIC: u8 = 0x00;
+pub const LED_EFFECT_BLINKING: u8 = 0x01;
+pub const LED_EFFECT_BREATHING: u8 = 0x02;
+pub const LED_EFFECT_SPECTRUM: u8 = 0x04;
+
+/// Extended matrix effect ID that displays the uploaded custom frame
+pub const EXTENDED_EFFECT_CUSTOM_FRAME: u8 = 0x08;
+
+/// Most LEDs a single custom frame report can carry (5 header bytes + 3 per LED)
+pub const MAX_FRAME_COLUMNS: usize = (80 - 5) / 3;
+
+/// An RGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// Wave effect direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveDirection {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Device-wide lighting effect (standard matrix effect, class 0x03 ID 0x0A)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LedEffect {
+    /// All LEDs off
+    Off,
+    /// Single static color
+    Static { color: Rgb },
+    /// Cycle through the color spectrum
+    Spectrum,
+    /// Fade in and out, alternating between two colors if `secondary` is set
+    Breathing {
+        color: Rgb,
+        #[serde(default)]
+        secondary: Option<Rgb>,
+    },
+    /// Color wave across the device
+    Wave {
+        #[serde(default)]
+        direction: WaveDirection,
+    },
+    /// Light up on click, fading out at `speed` (1 = short, 3 = long)
+    Reactive { color: Rgb, speed: u8 },
+}
+
+impl LedEffect {
+    /// Arguments for `Command::SetMatrixEffect` (OpenRazer standard matrix layout).
+    /// The report data size must be set to the returned length.
+    pub fn matrix_args(&self) -> Vec<u8> {
+        match *self {
+            LedEffect::Off => vec![0x00],
+            LedEffect::Wave { direction } => {
+                let dir = match direction {
+                    WaveDirection::Left => 0x01,
+                    WaveDirection::Right => 0x02,
+                };
+                vec![0x01, dir]
+            }
+            LedEffect::Reactive { color, speed } => {
+                vec![0x02, speed.clamp(1, 3), color.r, color.g, color.b]
+            }
+            LedEffect::Breathing { color, secondary: None } => {
+                vec![0x03, 0x01, color.r, color.g, color.b, 0x00, 0x00, 0x00]
+            }
+            LedEffect::Breathing { color, secondary: Some(second) } => {
+                vec![0x03, 0x02, color.r, color.g, color.b, second.r, second.g, second.b]
+            }
+            LedEffect::Spectrum => vec![0x04],
+            LedEffect::Static { color } => vec![0x06, color.r, color.g, color.b],
+        }
+    }
+
+    /// Arguments for `Command::SetExtendedEffect` on one LED (OpenRazer extended matrix layout).
+    /// The report data size must be set to the returned length.
+    pub fn extended_args(&self, storage: u8, led_id: u8) -> Vec<u8> {
+        let mut args = vec![storage, led_id];
+        match *self {
+            LedEffect::Off => args.extend([0x00, 0x00, 0x00, 0x00]),
+            LedEffect::Static { color } => {
+                args.extend([0x01, 0x00, 0x00, 0x01, color.r, color.g, color.b])
+            }
+            LedEffect::Breathing { color, secondary: None } => {
+                args.extend([0x02, 0x01, 0x00, 0x01, color.r, color.g, color.b])
+            }
+            LedEffect::Breathing { color, secondary: Some(second) } => args.extend([
+                0x02, 0x02, 0x00, 0x02, color.r, color.g, color.b, second.r, second.g, second.b,
+            ]),
+            LedEffect::Spectrum => args.extend([0x03, 0x00, 0x00, 0x00]),
+            LedEffect::Wave { direction } => {
+                let dir = match direction {
+                    WaveDirection::Left => 0x01,
+                    WaveDirection::Right => 0x02,
+                };
+                args.extend([0x04, dir, 0x28, 0x00])
+            }
+            LedEffect::Reactive { color, speed } => args.extend([
+                0x05,
+                0x00,
+                speed.clamp(1, 3),
+                0x01,
+                color.r,
+                color.g,
+                color.b,
+            ]),
+        }
+        args
+    }
+
+    /// Human-readable effect name
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedEffect::Off => "Off",
+            LedEffect::Static { .. } => "Static",
+            LedEffect::Spectrum => "Spectrum",
+            LedEffect::Breathing { .. } => "Breathing",
+            LedEffect::Wave { .. } => "Wave",
+            LedEffect::Reactive { .. } => "Reactive",
         }
     }
 }
@@ -99,12 +421,15 @@ pub struct RazerReport {
 
 impl RazerReport {
     /// Create a new report for a command
+    ///
+    /// Uses the old-style transaction ID (0xFF); `RazerDevice` builds its
+    /// reports with `new_with_transaction_id` using the ID of the connected model.
     pub fn new(command: Command) -> Self {
         let (class, id) = command.class_and_id();
 
         Self {
-            status: 0x00,              // New command
-            transaction_id: 0xFF,      // Naga Trinity uses 0xFF for DPI/old-style commands
+            status: STATUS_NEW,
+            transaction_id: TRANSACTION_ID_OLD,
             remaining_packets: 0x0000, // Single packet (u16)
             protocol_type: 0x00,
             data_size: command.data_size(),
@@ -121,10 +446,84 @@ impl RazerReport {
         report
     }
 
+    /// Create a report carrying a variable-length argument list.
+    /// The data size is set to the number of arguments.
+    pub fn with_args(command: Command, transaction_id: u8, args: &[u8]) -> Result<Self> {
+        if args.len() > MAX_REPORT_ARGS {
+            return Err(RazerError::InvalidArgument(format!(
+                "Too many arguments for one report ({} > {})",
+                args.len(),
+                MAX_REPORT_ARGS
+            )));
+        }
+
+        let mut report = Self::new_with_transaction_id(command, transaction_id);
+        report.data[..args.len()].copy_from_slice(args);
+        report.data_size = args.len() as u8;
+        Ok(report)
+    }
+
+    /// Split a payload of any length over a chain of reports.
+    ///
+    /// Each report carries up to 80 bytes; `remaining_packets` counts the
+    /// reports still to come, so the last one has 0. A payload that fits in one
+    /// report gives a single ordinary report.
+    pub fn chain(command: Command, transaction_id: u8, payload: &[u8]) -> Result<Vec<Self>> {
+        if payload.len() <= MAX_REPORT_ARGS {
+            return Ok(vec![Self::with_args(command, transaction_id, payload)?]);
+        }
+
+        let chunks: Vec<&[u8]> = payload.chunks(MAX_REPORT_ARGS).collect();
+        let count = u16::try_from(chunks.len()).map_err(|_| {
+            RazerError::InvalidArgument(format!("Payload too large ({} bytes)", payload.len()))
+        })?;
+
+        chunks
+            .into_iter()
+            .zip((0..count).rev())
+            .map(|(chunk, remaining)| {
+                let mut report = Self::with_args(command, transaction_id, chunk)?;
+                report.remaining_packets = remaining;
+                Ok(report)
+            })
+            .collect()
+    }
+
+    /// Reassemble the payload carried by a chain of reports, checking that they
+    /// belong to one command and arrived complete and in order
+    pub fn assemble(reports: &[RazerReport]) -> Result<Vec<u8>> {
+        let first = reports
+            .first()
+            .ok_or_else(|| RazerError::InvalidResponse("empty report chain".into()))?;
+
+        let mut payload = Vec::with_capacity(reports.len() * MAX_REPORT_ARGS);
+        for (i, report) in reports.iter().enumerate() {
+            if (report.command_class, report.command_id) != (first.command_class, first.command_id) {
+                return Err(RazerError::InvalidResponse(format!(
+                    "report {} of chain is for command {:#04x}/{:#04x}",
+                    i, report.command_class, report.command_id
+                )));
+            }
+            let expected = reports.len() - 1 - i;
+            if report.remaining_packets as usize != expected {
+                return Err(RazerError::InvalidResponse(format!(
+                    "report {} of chain has {} remaining packets, expected {}",
+                    i, report.remaining_packets, expected
+                )));
+            }
+            payload.extend_from_slice(report.args());
+        }
+        Ok(payload)
+    }
+
+    /// Arguments, trimmed to the reported data size
+    pub fn args(&self) -> &[u8] {
+        &self.data[..(self.data_size as usize).min(80)]
+    }
+
     /// Calculate CRC (XOR of bytes 2-87)
     fn calculate_crc(&self) -> u8 {
-        let bytes = self.to_bytes_without_crc();
-        bytes[2..88].iter().fold(0u8, |acc, &x| acc ^ x)
+        crc(&self.to_bytes_without_crc())
     }
 
     /// Convert to bytes without CRC (for CRC calculation)
@@ -151,12 +550,12 @@ impl RazerReport {
         bytes
     }
 
-    /// Parse a response from bytes
-    pub fn from_bytes(bytes: &[u8; 90]) -> Result<Self> {
+    /// Parse a response from bytes without looking at the status
+    pub fn parse(bytes: &[u8; 90]) -> Self {
         let mut data = [0u8; 80];
         data.copy_from_slice(&bytes[8..88]); // Arguments at bytes 8-87
 
-        let report = Self {
+        Self {
             status: bytes[0],
             transaction_id: bytes[1],
             remaining_packets: u16::from_be_bytes([bytes[2], bytes[3]]),
@@ -165,18 +564,75 @@ impl RazerReport {
             command_class: bytes[6],
             command_id: bytes[7],
             data,
-        };
+        }
+    }
+
+    /// Parse a response from bytes, checking the CRC and the status
+    pub fn from_bytes(bytes: &[u8; 90]) -> Result<Self> {
+        let expected = crc(bytes);
+        if bytes[88] != expected {
+            return Err(RazerError::CrcMismatch {
+                expected,
+                actual: bytes[88],
+            });
+        }
+
+        let report = Self::parse(bytes);
+
+        let (class, id) = (report.command_class, report.command_id);
 
         // Check status
         match report.status {
-            0x02 => Ok(report), // Success
-            0x01 => Err(anyhow!("Device busy")),
-            0x03 => Err(anyhow!("Command failed")),
-            0x04 => Err(anyhow!("Command timeout")),
-            0x05 => Err(anyhow!("Command not supported")),
+            STATUS_SUCCESS => Ok(report),
+            STATUS_BUSY => Err(RazerError::DeviceBusy),
+            STATUS_FAILURE => Err(RazerError::CommandFailed { class, id }),
+            STATUS_TIMEOUT => Err(RazerError::Timeout { class, id }),
+            STATUS_NOT_SUPPORTED => Err(RazerError::NotSupported { class, id }),
             _ => Ok(report), // Unknown status, try to continue
         }
     }
+
+    /// Whether this response answers `request`: same command class, command
+    /// ID and transaction ID. Anything else is a stale reply to an earlier command.
+    pub fn is_reply_to(&self, request: &RazerReport) -> bool {
+        self.command_class == request.command_class
+            && self.command_id == request.command_id
+            && self.transaction_id == request.transaction_id
+    }
+}
+
+/// CRC of a raw report: XOR of bytes 2-87 (stored in byte 88)
+pub fn crc(bytes: &[u8; 90]) -> u8 {
+    bytes[2..88].iter().fold(0u8, |acc, &x| acc ^ x)
+}
+
+/// Arguments for `Command::SetCustomFrame`: one row of per-LED colors starting at `start_col`
+pub fn custom_frame_args(row: u8, start_col: u8, colors: &[Rgb]) -> Result<Vec<u8>> {
+    if colors.is_empty() || colors.len() > MAX_FRAME_COLUMNS {
+        return Err(RazerError::InvalidArgument(format!(
+            "Custom frame row must have 1-{} LEDs (got {})",
+            MAX_FRAME_COLUMNS,
+            colors.len()
+        )));
+    }
+
+    let stop_col = start_col as usize + colors.len() - 1;
+    let stop_col = u8::try_from(stop_col)
+        .map_err(|_| RazerError::InvalidArgument("Custom frame column out of range".into()))?;
+
+    let mut args = vec![0x00, 0x00, row, start_col, stop_col];
+    for color in colors {
+        args.extend([color.r, color.g, color.b]);
+    }
+    Ok(args)
+}
+
+/// Arguments for `Command::SetExtendedEffect` that display the uploaded custom frame
+pub fn custom_frame_effect_args() -> Vec<u8> {
+    let mut args = vec![0u8; 0x0C];
+    args[0] = NOSTORE;
+    args[2] = EXTENDED_EFFECT_CUSTOM_FRAME;
+    args
 }
 
 #[cfg(test)]
@@ -204,4 +660,279 @@ mod tests {
         assert_eq!(bytes[6], 0x04); // command_class
         assert_eq!(bytes[7], 0x85); // command_id
     }
+
+    #[test]
+    fn test_report_with_transaction_id() {
+        let report = RazerReport::new_with_transaction_id(Command::GetDpi, TRANSACTION_ID_WIRELESS);
+        let bytes = report.to_bytes();
+        assert_eq!(bytes[1], 0x1F);
+        // Transaction ID is not part of the CRC
+        assert_eq!(bytes[88], RazerReport::new(Command::GetDpi).to_bytes()[88]);
+    }
+
+    #[test]
+    fn test_led_effect_matrix_args() {
+        let red = Rgb::new(0xFF, 0x00, 0x00);
+        assert_eq!(LedEffect::Off.matrix_args(), vec![0x00]);
+        assert_eq!(LedEffect::Spectrum.matrix_args(), vec![0x04]);
+        assert_eq!(LedEffect::Static { color: red }.matrix_args(), vec![0x06, 0xFF, 0x00, 0x00]);
+        assert_eq!(
+            LedEffect::Wave { direction: WaveDirection::Right }.matrix_args(),
+            vec![0x01, 0x02]
+        );
+        assert_eq!(
+            LedEffect::Reactive { color: red, speed: 9 }.matrix_args(),
+            vec![0x02, 0x03, 0xFF, 0x00, 0x00]
+        );
+
+        let breathing = LedEffect::Breathing { color: red, secondary: None }.matrix_args();
+        assert_eq!(breathing.len(), 8);
+        assert_eq!(&breathing[0..2], &[0x03, 0x01]);
+    }
+
+    #[test]
+    fn test_custom_frame_report() {
+        let colors = [Rgb::new(1, 2, 3), Rgb::new(4, 5, 6), Rgb::new(7, 8, 9)];
+        let args = custom_frame_args(0, 0, &colors).unwrap();
+        let report = RazerReport::with_args(Command::SetCustomFrame, TRANSACTION_ID_NEW, &args).unwrap();
+
+        let bytes = report.to_bytes();
+        assert_eq!(bytes[5], 5 + 3 * 3); // data_size
+        assert_eq!(bytes[6], 0x0F); // command_class
+        assert_eq!(bytes[7], 0x03); // command_id
+        assert_eq!(&bytes[8..13], &[0x00, 0x00, 0x00, 0x00, 0x02]); // row 0, cols 0-2
+        assert_eq!(&bytes[13..22], &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let parsed = RazerReport::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.args(), args.as_slice());
+    }
+
+    #[test]
+    fn test_custom_frame_limits() {
+        assert!(custom_frame_args(0, 0, &[]).is_err());
+        assert!(custom_frame_args(0, 0, &[Rgb::default(); MAX_FRAME_COLUMNS]).is_ok());
+        assert!(custom_frame_args(0, 0, &[Rgb::default(); MAX_FRAME_COLUMNS + 1]).is_err());
+        assert!(RazerReport::with_args(Command::SetCustomFrame, TRANSACTION_ID_NEW, &[0; 81]).is_err());
+    }
+
+    #[test]
+    fn test_extended_effect_args() {
+        let green = Rgb::new(0, 255, 0);
+        let args = LedEffect::Static { color: green }.extended_args(VARSTORE, 0x04);
+        assert_eq!(args, vec![0x01, 0x04, 0x01, 0x00, 0x00, 0x01, 0x00, 0xFF, 0x00]);
+        assert_eq!(LedEffect::Off.extended_args(VARSTORE, 0x01).len(), 6);
+        assert_eq!(custom_frame_effect_args()[2], EXTENDED_EFFECT_CUSTOM_FRAME);
+    }
+
+    #[test]
+    fn test_extended_zone_color_args() {
+        // Static effect on the scroll wheel LED only (LED ID 0x01)
+        let color = Rgb::new(0x12, 0x34, 0x56);
+        let args = LedEffect::Static { color }.extended_args(VARSTORE, 0x01);
+        let report = RazerReport::with_args(Command::SetExtendedEffect, TRANSACTION_ID_NEW, &args).unwrap();
+        let bytes = report.to_bytes();
+        assert_eq!(bytes[5], 9); // data_size
+        assert_eq!((bytes[6], bytes[7]), (0x0F, 0x02)); // command_class, command_id
+        assert_eq!(&bytes[8..17], &[0x01, 0x01, 0x01, 0x00, 0x00, 0x01, 0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn test_led_command_ids() {
+        assert_eq!(Command::SetLedRgb.class_and_id(), (CommandClass::Led as u8, 0x01));
+        assert_eq!(Command::SetLedBrightness.class_and_id(), (0x03, 0x03));
+        assert_eq!(Command::SetMatrixEffect.class_and_id(), (0x03, 0x0A));
+    }
+
+    #[test]
+    fn test_power_command_ids() {
+        assert_eq!(Command::GetBatteryLevel.class_and_id(), (CommandClass::Power as u8, 0x80));
+        assert_eq!(Command::GetChargingStatus.class_and_id(), (0x07, 0x84));
+        assert_eq!(Command::GetBatteryLevel.data_size(), 0x02);
+        assert_eq!(Command::SetIdleTime.class_and_id(), (0x07, 0x03));
+        assert_eq!(Command::SetLowBatteryThreshold.class_and_id(), (0x07, 0x01));
+    }
+
+    #[test]
+    fn test_low_battery_threshold_scale() {
+        assert_eq!(low_battery_threshold_to_raw(5), 0x0D);
+        assert_eq!(low_battery_threshold_to_raw(25), 0x40);
+        // Out-of-range values are clamped
+        assert_eq!(low_battery_threshold_to_raw(0), low_battery_threshold_to_raw(5));
+        assert_eq!(low_battery_threshold_to_raw(90), low_battery_threshold_to_raw(25));
+
+        for percent in LOW_BATTERY_THRESHOLD_MIN..=LOW_BATTERY_THRESHOLD_MAX {
+            let raw = low_battery_threshold_to_raw(percent);
+            assert_eq!(low_battery_threshold_from_raw(raw), percent);
+        }
+    }
+
+    #[test]
+    fn test_dpi_stages_roundtrip() {
+        let stages = [(400, 400), (800, 800), (1600, 1200)];
+        let args = dpi_stages_args(VARSTORE, &stages, 1).unwrap();
+        assert_eq!(&args[..3], &[VARSTORE, 2, 3]);
+        assert_eq!(&args[3..10], &[0, 0x01, 0x90, 0x01, 0x90, 0, 0]);
+        assert_eq!(args.len(), Command::SetDpiStages.data_size() as usize);
+
+        let report = RazerReport::with_args(Command::SetDpiStages, TRANSACTION_ID_NEW, &args).unwrap();
+        let bytes = report.to_bytes();
+        assert_eq!((bytes[6], bytes[7]), (0x04, 0x06));
+
+        let (parsed, active) = parse_dpi_stages(&args).unwrap();
+        assert_eq!(parsed, stages);
+        assert_eq!(active, 1);
+    }
+
+    #[test]
+    fn test_dpi_stages_limits() {
+        assert!(dpi_stages_args(VARSTORE, &[], 0).is_err());
+        assert!(dpi_stages_args(VARSTORE, &[(800, 800); MAX_DPI_STAGES], 4).is_ok());
+        assert!(dpi_stages_args(VARSTORE, &[(800, 800); MAX_DPI_STAGES + 1], 0).is_err());
+        assert!(dpi_stages_args(VARSTORE, &[(800, 800)], 1).is_err());
+        // A full list fills the report exactly
+        assert_eq!(3 + MAX_DPI_STAGES * DPI_STAGE_LEN, Command::SetDpiStages.data_size() as usize);
+        assert!(parse_dpi_stages(&[VARSTORE, 1, 2, 0, 0x03, 0x20, 0x03, 0x20, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_reply_matching() {
+        let request = RazerReport::new_with_transaction_id(Command::GetDpi, TRANSACTION_ID_NEW);
+        let mut bytes = request.to_bytes();
+        bytes[0] = STATUS_SUCCESS;
+        assert!(RazerReport::parse(&bytes).is_reply_to(&request));
+
+        // Reply to a different command
+        let other = RazerReport::new_with_transaction_id(Command::GetPollingRate, TRANSACTION_ID_NEW);
+        assert!(!RazerReport::parse(&other.to_bytes()).is_reply_to(&request));
+
+        // Same command, different transaction ID
+        let mut stale = bytes;
+        stale[1] = TRANSACTION_ID_OLD;
+        assert!(!RazerReport::parse(&stale).is_reply_to(&request));
+
+        // Busy is reported by from_bytes but still parses
+        bytes[0] = STATUS_BUSY;
+        assert!(matches!(RazerReport::from_bytes(&bytes), Err(RazerError::DeviceBusy)));
+        assert_eq!(RazerReport::parse(&bytes).status, STATUS_BUSY);
+    }
+
+    #[test]
+    fn test_status_errors() {
+        let mut bytes = RazerReport::new(Command::GetDpiStages).to_bytes();
+        bytes[0] = STATUS_NOT_SUPPORTED;
+        assert!(matches!(
+            RazerReport::from_bytes(&bytes),
+            Err(RazerError::NotSupported { class: 0x04, id: 0x86 })
+        ));
+        bytes[0] = STATUS_FAILURE;
+        assert!(matches!(RazerReport::from_bytes(&bytes), Err(RazerError::CommandFailed { .. })));
+        bytes[0] = STATUS_TIMEOUT;
+        assert!(matches!(RazerReport::from_bytes(&bytes), Err(RazerError::Timeout { .. })));
+    }
+
+    /// A successful GetDpi response for 1600x800 with a valid CRC
+    fn dpi_response() -> [u8; 90] {
+        let mut report = RazerReport::new_with_transaction_id(Command::GetDpi, TRANSACTION_ID_NEW);
+        report.status = STATUS_SUCCESS;
+        report.data[..5].copy_from_slice(&[NOSTORE, 0x06, 0x40, 0x03, 0x20]);
+        report.to_bytes()
+    }
+
+    #[test]
+    fn test_crc_valid_response() {
+        let bytes = dpi_response();
+        assert_eq!(bytes[88], crc(&bytes));
+        let parsed = RazerReport::from_bytes(&bytes).unwrap();
+        assert_eq!(&parsed.data[1..5], &[0x06, 0x40, 0x03, 0x20]);
+    }
+
+    #[test]
+    fn test_crc_corrupted_argument() {
+        let mut bytes = dpi_response();
+        bytes[9] ^= 0x10; // flip a bit in the DPI value
+        assert!(matches!(
+            RazerReport::from_bytes(&bytes),
+            Err(RazerError::CrcMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crc_short_read() {
+        // A short read leaves the tail of the buffer zeroed, including the CRC
+        let mut bytes = dpi_response();
+        bytes[10..].fill(0);
+        let err = RazerReport::from_bytes(&bytes).unwrap_err();
+        assert!(matches!(err, RazerError::CrcMismatch { actual: 0, .. }));
+    }
+
+    #[test]
+    fn test_crc_ignores_status_and_transaction_id() {
+        // Bytes 0-1 are outside the checksum, so a busy reply still validates
+        let mut bytes = dpi_response();
+        bytes[0] = STATUS_BUSY;
+        bytes[1] = TRANSACTION_ID_OLD;
+        assert!(matches!(RazerReport::from_bytes(&bytes), Err(RazerError::DeviceBusy)));
+    }
+
+    #[test]
+    fn test_chain_single_report() {
+        let reports = RazerReport::chain(Command::SetMatrixEffect, TRANSACTION_ID_NEW, &[0x04]).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].remaining_packets, 0);
+        assert_eq!(reports[0].args(), &[0x04]);
+    }
+
+    #[test]
+    fn test_chain_roundtrip() {
+        let payload: Vec<u8> = (0..200u16).map(|i| i as u8).collect();
+        let reports = RazerReport::chain(Command::SetCustomFrame, TRANSACTION_ID_NEW, &payload).unwrap();
+
+        assert_eq!(reports.len(), 3);
+        let remaining: Vec<u16> = reports.iter().map(|r| r.remaining_packets).collect();
+        assert_eq!(remaining, vec![2, 1, 0]);
+        assert_eq!(reports[0].data_size, 80);
+        assert_eq!(reports[2].data_size, 40);
+
+        // Through the wire format and back
+        let parsed: Vec<RazerReport> = reports
+            .iter()
+            .map(|r| {
+                let mut bytes = r.to_bytes();
+                bytes[0] = STATUS_SUCCESS;
+                assert_eq!(u16::from_be_bytes([bytes[2], bytes[3]]), r.remaining_packets);
+                RazerReport::from_bytes(&bytes).unwrap()
+            })
+            .collect();
+        assert_eq!(RazerReport::assemble(&parsed).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_assemble_rejects_broken_chains() {
+        let payload = [0xAB; 170];
+        let reports = RazerReport::chain(Command::SetCustomFrame, TRANSACTION_ID_NEW, &payload).unwrap();
+
+        assert!(RazerReport::assemble(&[]).is_err());
+        // Missing the last report
+        assert!(RazerReport::assemble(&reports[..2]).is_err());
+        // Out of order
+        let swapped = [reports[1].clone(), reports[0].clone(), reports[2].clone()];
+        assert!(RazerReport::assemble(&swapped).is_err());
+        // Report for another command in the middle
+        let mut mixed = reports.clone();
+        mixed[1].command_id = 0x02;
+        assert!(RazerReport::assemble(&mixed).is_err());
+    }
+
+    #[test]
+    fn test_parse_serial() {
+        let mut args = [0u8; 0x16];
+        args[..12].copy_from_slice(b"PM1234H56789");
+        assert_eq!(parse_serial(&args).unwrap(), "PM1234H56789");
+
+        // Padding and surrounding spaces are dropped
+        assert_eq!(parse_serial(b" XY42 \0\0").unwrap(), "XY42");
+
+        assert!(parse_serial(&[0u8; 0x16]).is_err());
+        assert!(parse_serial(&[0xFF, 0x10, 0x00]).is_err());
+    }
 }
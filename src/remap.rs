@@ -1,24 +1,45 @@
 //! Software button remapping (evdev grab + uinput virtual device)
 
+use crate::device::DeviceNodes;
 use crate::overlay::OverlayCommand;
+use crate::profile::LayerMode;
 use anyhow::{Context, Result};
 use evdev::{AttributeSet, Device, EventType, InputEvent, InputEventKind, Key, uinput::VirtualDeviceBuilder};
-use std::collections::BTreeMap;
+use evdev::uinput::VirtualDevice;
+use std::collections::{BTreeMap, BTreeSet};
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
-    mpsc::Sender,
+    mpsc::{Receiver, Sender},
 };
 use std::thread;
 use std::time::{Duration, Instant};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+
+/// Name of the uinput device remapped events are emitted through
+const VIRTUAL_DEVICE_NAME: &str = "RazerLinux Virtual Device";
+
+/// How often a remapper retries grabbing the nodes of a reattached device
+/// (udev may not have applied their permissions yet)
+const REACQUIRE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Keyboard keys the virtual device always advertises (KEY_ESC..=KEY_MICMUTE),
+/// so mappings added while remapping can be emitted without recreating it
+const KEYBOARD_KEYS: std::ops::RangeInclusive<u16> = 1..=248;
+
+/// Mouse buttons the virtual device can emit (BTN_LEFT..=BTN_TASK)
+const MOUSE_BUTTONS: std::ops::RangeInclusive<u16> = 272..=279;
 
 #[derive(Debug, Clone, Default)]
 pub struct RemapConfig {
     pub source_device: Option<String>,
+    /// Nodes of the device being remapped; empty grabs every Razer interface
+    pub nodes: DeviceNodes,
     pub mappings: BTreeMap<u16, MappingTarget>,
+    /// Hypershift layers on top of `mappings`
+    pub layers: Vec<Layer>,
     /// Enable Windows-style autoscroll (middle click to enter scroll mode)
     pub autoscroll_enabled: bool,
 }
@@ -29,6 +50,35 @@ pub struct RemapConfigExt {
     pub overlay_sender: Option<Sender<OverlayCommand>>,
     /// Macros available for execution (cloned from MacroManager at start)
     pub macros: std::collections::HashMap<u32, crate::profile::Macro>,
+    /// Called from the remapper thread when its source devices go away or come back
+    pub on_state: Box<dyn Fn(RemapState) + Send>,
+}
+
+/// The device a waiting remapper remaps again, once it is plugged back in
+struct Reattach {
+    nodes: DeviceNodes,
+    on_state: Box<dyn Fn(RemapState) + Send>,
+}
+
+/// Settings a running remapper can swap without releasing its source
+/// devices or recreating the virtual device
+pub struct RemapUpdate {
+    pub mappings: BTreeMap<u16, MappingTarget>,
+    pub layers: Vec<Layer>,
+    pub macros: std::collections::HashMap<u32, crate::profile::Macro>,
+    pub autoscroll_enabled: bool,
+    /// Overlay for the autoscroll indicator; None hides it
+    pub overlay_sender: Option<Sender<OverlayCommand>>,
+}
+
+/// Whether the remapper currently holds its source devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemapState {
+    /// Source interfaces are grabbed and remapped
+    Active,
+    /// A source interface disappeared (unplug, USB reset); all grabs are
+    /// released until the device is reattached
+    Waiting,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -37,6 +87,163 @@ pub struct MappingTarget {
     pub mods: Modifiers,
 }
 
+/// Mappings of a profile, keyed by source code
+pub fn mappings_from_profile(mappings: &[crate::profile::RemapMapping]) -> BTreeMap<u16, MappingTarget> {
+    mappings
+        .iter()
+        .map(|m| {
+            let target = MappingTarget {
+                base: m.target,
+                mods: Modifiers {
+                    ctrl: m.ctrl,
+                    alt: m.alt,
+                    shift: m.shift,
+                    meta: m.meta,
+                },
+            };
+            (m.source, target)
+        })
+        .collect()
+}
+
+/// A Hypershift layer: while active, its mappings take precedence over the
+/// base mappings
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Source code of the layer key
+    pub key: u16,
+    pub mode: LayerMode,
+    pub mappings: BTreeMap<u16, MappingTarget>,
+}
+
+/// Layers of a profile
+pub fn layers_from_profile(layers: &[crate::profile::RemapLayer]) -> Vec<Layer> {
+    layers
+        .iter()
+        .map(|layer| Layer {
+            key: layer.key,
+            mode: layer.mode,
+            mappings: mappings_from_profile(&layer.mappings),
+        })
+        .collect()
+}
+
+/// Which layers are active, and what each held source key was pressed as
+#[derive(Debug, Default)]
+struct LayerState {
+    /// Indices of the active layers, most recently activated last
+    active: Vec<usize>,
+    /// Active one-shot layers, dropped after the next press
+    one_shot: Vec<usize>,
+    /// Target each held source key was pressed with (None = passed through).
+    /// Its release and repeats use the same target even if the layers
+    /// changed in between, so no key sticks.
+    held: BTreeMap<u16, Option<MappingTarget>>,
+    /// Held keys whose target was released when the mappings changed; their
+    /// repeats and release are dropped
+    released: BTreeSet<u16>,
+}
+
+impl LayerState {
+    /// Target of a key event: Some(None) passes it through unmapped, None
+    /// drops it (layer keys only switch layers)
+    fn resolve(
+        &mut self,
+        mappings: &BTreeMap<u16, MappingTarget>,
+        layers: &[Layer],
+        code: u16,
+        value: i32,
+    ) -> Option<Option<MappingTarget>> {
+        if self.released.contains(&code) {
+            if value != 1 {
+                if value == 0 {
+                    self.released.remove(&code);
+                }
+                return None;
+            }
+            self.released.remove(&code);
+        }
+        if let Some(index) = layers.iter().position(|layer| layer.key == code) {
+            self.switch_layer(index, layers[index].mode, value);
+            return None;
+        }
+
+        let target = match value {
+            1 => {
+                let target = self.lookup(mappings, layers, code);
+                self.held.insert(code, target.clone());
+                let one_shot = std::mem::take(&mut self.one_shot);
+                self.active.retain(|index| !one_shot.contains(index));
+                target
+            }
+            0 => match self.held.remove(&code) {
+                Some(target) => target,
+                None => self.lookup(mappings, layers, code),
+            },
+            _ => match self.held.get(&code) {
+                Some(target) => target.clone(),
+                None => self.lookup(mappings, layers, code),
+            },
+        };
+        Some(target)
+    }
+
+    /// Handle a press (1), release (0) or repeat (2) of a layer key
+    fn switch_layer(&mut self, index: usize, mode: LayerMode, value: i32) {
+        let active = self.active.contains(&index);
+        match (mode, value) {
+            (LayerMode::Hold, 1) if !active => self.active.push(index),
+            (LayerMode::Hold, 0) => self.deactivate(index),
+            (LayerMode::Toggle | LayerMode::OneShot, 1) if active => self.deactivate(index),
+            (LayerMode::Toggle, 1) => self.active.push(index),
+            (LayerMode::OneShot, 1) => {
+                self.active.push(index);
+                self.one_shot.push(index);
+            }
+            _ => return,
+        }
+        debug!("Active layers: {:?}", self.active);
+    }
+
+    /// Start over for new mappings and layers, returning the targets held
+    /// keys were pressed with so they can be released. Those keys stay
+    /// swallowed until they go up, so the new mappings never see a release
+    /// or repeat without a press; passed-through keys stay held as they are.
+    fn take_held_targets(&mut self) -> Vec<(u16, MappingTarget)> {
+        self.active.clear();
+        self.one_shot.clear();
+        let mut targets = Vec::new();
+        for (code, target) in std::mem::take(&mut self.held) {
+            match target {
+                Some(target) => {
+                    self.released.insert(code);
+                    targets.push((code, target));
+                }
+                None => {
+                    self.held.insert(code, None);
+                }
+            }
+        }
+        targets
+    }
+
+    fn deactivate(&mut self, index: usize) {
+        self.active.retain(|&active| active != index);
+        self.one_shot.retain(|&active| active != index);
+    }
+
+    /// Mapping of a source key through the active layers, newest first,
+    /// then the base mappings
+    fn lookup(&self, mappings: &BTreeMap<u16, MappingTarget>, layers: &[Layer], code: u16) -> Option<MappingTarget> {
+        self.active
+            .iter()
+            .rev()
+            .find_map(|&index| layers[index].mappings.get(&code))
+            .or_else(|| mappings.get(&code))
+            .cloned()
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Modifiers {
     pub ctrl: bool,
@@ -138,36 +345,94 @@ pub fn list_razer_input_interfaces() -> Vec<RazerInputInterface> {
 
 pub struct Remapper {
     stop: Arc<AtomicBool>,
+    waiting: Arc<AtomicBool>,
+    updates: Sender<RemapUpdate>,
+    reattach: Sender<Reattach>,
+    /// Key codes the virtual device advertises for mapping targets
+    emittable: BTreeSet<u16>,
     join: Option<thread::JoinHandle<()>>,
 }
 
 impl Remapper {
-    pub fn start(
+    /// Start remapping. `on_state` is called from the remapper thread when
+    /// the source devices disappear and when they have been grabbed again.
+    pub fn start<F>(
         config: RemapConfig, 
         overlay_sender: Option<Sender<OverlayCommand>>,
         macros: std::collections::HashMap<u32, crate::profile::Macro>,
-    ) -> Result<Self> {
+        on_state: F,
+    ) -> Result<Self>
+    where
+        F: Fn(RemapState) + Send + 'static,
+    {
         let stop = Arc::new(AtomicBool::new(false));
         let stop_thread = stop.clone();
+        let emittable = emittable_keys(&config);
+        let waiting = Arc::new(AtomicBool::new(false));
+        let waiting_thread = waiting.clone();
 
         let ext_config = RemapConfigExt {
             config,
             overlay_sender,
             macros,
+            on_state: track_waiting(waiting_thread, on_state),
         };
 
+        let (updates, updates_rx) = mpsc::channel();
+        let (reattach, reattach_rx) = mpsc::channel();
         let join = thread::spawn(move || {
-            if let Err(e) = run_remapper_loop(stop_thread, ext_config) {
+            if let Err(e) = run_remapper_loop(stop_thread, ext_config, updates_rx, reattach_rx) {
                 warn!("remapper stopped: {e:#}");
             }
         });
 
         Ok(Self {
             stop,
+            waiting,
+            updates,
+            reattach,
+            emittable,
             join: Some(join),
         })
     }
 
+    /// Remap a device that was plugged in again. The remapper grabs the new
+    /// nodes (retrying until they are accessible) and keeps its virtual
+    /// device; `on_state` replaces the callback given to `start`, since the
+    /// device may have a new hidraw path.
+    pub fn reattach<F>(&self, nodes: DeviceNodes, on_state: F)
+    where
+        F: Fn(RemapState) + Send + 'static,
+    {
+        let on_state = track_waiting(self.waiting.clone(), on_state);
+        let _ = self.reattach.send(Reattach { nodes, on_state });
+    }
+
+    /// Replace the mappings, macros and autoscroll setting at once. The
+    /// source devices stay grabbed and the virtual device stays, so
+    /// applications don't see input drop or a device go away. The virtual
+    /// device keeps the keys it was created with: mappings targeting other
+    /// keys are left out until remapping restarts, and their source codes
+    /// are returned.
+    pub fn update(&self, mut update: RemapUpdate) -> Vec<u16> {
+        let mut dropped = retain_emittable(&mut update.mappings, &self.emittable);
+        for layer in &mut update.layers {
+            dropped.extend(retain_emittable(&mut layer.mappings, &self.emittable));
+        }
+        dropped.sort_unstable();
+        dropped.dedup();
+        if !dropped.is_empty() {
+            warn!("Ignoring mappings of {:?}: the virtual device can't emit their target keys", dropped);
+        }
+        let _ = self.updates.send(update);
+        dropped
+    }
+
+    /// Whether the remapper lost its source devices and waits for them
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
     pub fn stop(mut self) {
         self.stop.store(true, Ordering::Relaxed);
         if let Some(handle) = self.join.take() {
@@ -185,14 +450,31 @@ impl Drop for Remapper {
     }
 }
 
-fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Result<()> {
-    let config = ext_config.config;
-    let overlay_sender = ext_config.overlay_sender;
-    let macros = ext_config.macros;
+/// Wrap a state callback so `Remapper::is_waiting` follows the state
+fn track_waiting<F>(waiting: Arc<AtomicBool>, on_state: F) -> Box<dyn Fn(RemapState) + Send>
+where
+    F: Fn(RemapState) + Send + 'static,
+{
+    Box::new(move |state| {
+        waiting.store(state == RemapState::Waiting, Ordering::Relaxed);
+        on_state(state);
+    })
+}
+
+fn run_remapper_loop(
+    stop: Arc<AtomicBool>,
+    ext_config: RemapConfigExt,
+    updates: Receiver<RemapUpdate>,
+    reattach: Receiver<Reattach>,
+) -> Result<()> {
+    let mut config = ext_config.config;
+    let mut overlay_sender = ext_config.overlay_sender;
+    let mut macros = ext_config.macros;
+    let mut on_state = ext_config.on_state;
     
     // Find ALL Razer keyboard interfaces - the Naga Trinity sends side button keys
     // through multiple interfaces (event9 AND event11), so we need to grab them all
-    let source_paths = select_all_razer_keyboard_devices(&config.source_device);
+    let source_paths = select_all_razer_keyboard_devices(&config.source_device, &config.nodes);
     
     if source_paths.is_empty() {
         anyhow::bail!("No suitable Razer keyboard interfaces found for remapping");
@@ -201,21 +483,11 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
     info!("Starting remapper on {} device(s): {:?}", source_paths.len(), source_paths);
 
     // Open and grab all source devices
-    let mut devices: Vec<Device> = Vec::new();
+    let mut devices = grab_source_devices(&source_paths)?;
     let mut all_keys: AttributeSet<Key> = AttributeSet::new();
     let mut all_rel: AttributeSet<evdev::RelativeAxisType> = AttributeSet::new();
     
-    for source_path in &source_paths {
-        let mut dev = Device::open(source_path)
-            .with_context(|| format!("Failed to open evdev device: {source_path:?}"))?;
-
-        set_nonblocking(&dev).context("Failed to set evdev device non-blocking")?;
-
-        // Grab the device so the original events don't reach the system.
-        dev.grab().with_context(|| format!("Failed to grab evdev device: {source_path:?}"))?;
-        
-        info!("Grabbed device: {:?}", source_path);
-
+    for dev in &devices {
         // Collect key capabilities from all devices
         if let Some(src_keys) = dev.supported_keys() {
             let key_count = src_keys.iter().count();
@@ -233,46 +505,15 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
                 all_rel.insert(axis);
             }
         }
-        
-        devices.push(dev);
     }
     
-    // Add target keys to capabilities (except special scroll codes)
-    for target in config.mappings.values() {
-        // Skip special scroll codes - they are REL events, not KEY events
-        if target.base == 280 || target.base == 281 {
-            continue;
-        }
-        all_keys.insert(Key::new(target.base));
-        for m in target.mods.to_key_codes() {
-            all_keys.insert(Key::new(m));
-        }
-    }
-    
-    // Always add BTN_FORWARD and BTN_BACK in case they're used as targets
-    all_keys.insert(Key::BTN_FORWARD);
-    all_keys.insert(Key::BTN_BACK);
-
-    // Build virtual device
-    let mut vbuilder = VirtualDeviceBuilder::new().context("Failed to create uinput builder")?;
-    vbuilder = vbuilder.name(&"RazerLinux Virtual Device");
-
-    vbuilder = vbuilder
-        .with_keys(&all_keys)
-        .context("Failed to set key capabilities")?;
-    
-    // Add relative axes if any were found (for scroll wheel, mouse movement)
-    let has_rel_axes = all_rel.iter().next().is_some();
-    if has_rel_axes {
-        info!("Virtual device will have relative axes (scroll wheel, mouse movement)");
-        vbuilder = vbuilder
-            .with_relative_axes(&all_rel)
-            .context("Failed to set relative axis capabilities")?;
-    } else {
-        warn!("No relative axes found - scroll wheel may not work!");
+    // Add every key a mapping can target, so updates never need a new
+    // virtual device, plus the initial targets
+    for code in emittable_keys(&config) {
+        all_keys.insert(Key::new(code));
     }
 
-    let mut vdev = vbuilder.build().context("Failed to build uinput device")?;
+    let mut vdev = build_virtual_device(&all_keys, &all_rel)?;
     
     info!("Virtual device created, processing events from {} source(s)...", devices.len());
     info!("Active mappings: {:?}", config.mappings);
@@ -294,7 +535,69 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
     const REL_WHEEL: u16 = 8;
     const REL_HWHEEL: u16 = 6;
 
+    // Set when a source device disappeared; the virtual device stays, so
+    // applications don't see a new device when the mouse comes back
+    let mut source_lost = false;
+    // Set once the owner reattached the device, until its nodes are grabbed
+    let mut reattached = false;
+
+    let mut layer_state = LayerState::default();
+
     while !stop.load(Ordering::Relaxed) {
+        while let Ok(update) = updates.try_recv() {
+            // Layers start over, and keys held through the old mappings are
+            // released so no target key sticks
+            for (code, target) in layer_state.take_held_targets() {
+                let release = InputEvent::new(EventType::KEY, code, 0);
+                let Some(events) = remap_events(Some(&target), release, &macros) else { continue };
+                if let Err(e) = vdev.emit(&events) {
+                    warn!("uinput emit failed: {e}");
+                }
+            }
+            if autoscroll_active && !update.autoscroll_enabled {
+                autoscroll_active = false;
+                if let Some(ref sender) = overlay_sender {
+                    let _ = sender.send(OverlayCommand::Hide);
+                }
+            }
+
+            config.mappings = update.mappings;
+            config.layers = update.layers;
+            config.autoscroll_enabled = update.autoscroll_enabled;
+            macros = update.macros;
+            overlay_sender = update.overlay_sender;
+            info!("Remap configuration updated: {:?}", config.mappings);
+            info!("Autoscroll enabled: {}", config.autoscroll_enabled);
+        }
+
+        // The owner only reattaches a device after it was unplugged, so the
+        // old nodes are gone even if no read has failed yet
+        while let Ok(update) = reattach.try_recv() {
+            info!("Device reattached with nodes {:?}", update.nodes);
+            config.nodes = update.nodes;
+            on_state = update.on_state;
+            reattached = true;
+            if !source_lost {
+                for mut dev in devices.drain(..) {
+                    let _ = dev.ungrab();
+                }
+                source_lost = true;
+            }
+        }
+
+        if source_lost {
+            if reattached && let Some(regrabbed) = regrab_source_devices(&config) {
+                info!("Source devices are back, remapping {} interface(s) again", regrabbed.len());
+                devices = regrabbed;
+                source_lost = false;
+                reattached = false;
+                on_state(RemapState::Active);
+            } else {
+                thread::sleep(REACQUIRE_INTERVAL);
+            }
+            continue;
+        }
+
         let mut had_events = false;
         
         for dev in &mut devices {
@@ -451,7 +754,15 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
                                 info!("OTHER event: type={:?}, code={}, value={}", ev.event_type(), ev.code(), ev.value());
                             }
                         }
-                        if let Some(mapped_events) = remap_events(&config.mappings, ev, &macros) {
+                        let target = match ev.kind() {
+                            InputEventKind::Key(key) => {
+                                layer_state.resolve(&config.mappings, &config.layers, key.code(), ev.value())
+                            }
+                            _ => Some(None),
+                        };
+                        // Layer keys only switch layers
+                        let Some(target) = target else { continue };
+                        if let Some(mapped_events) = remap_events(target.as_ref(), ev, &macros) {
                             if let Err(e) = vdev.emit(&mapped_events) {
                                 warn!("uinput emit failed: {e}");
                             }
@@ -461,9 +772,32 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     // No events available, continue to next device
                 }
+                Err(e) if is_device_gone(&e) => {
+                    warn!("Source device disappeared: {e}");
+                    source_lost = true;
+                    break;
+                }
                 Err(e) => return Err(e).context("Failed to read events from evdev device"),
             }
         }
+
+        if source_lost {
+            // The other interfaces of an unplugged mouse are going away too;
+            // release whatever is left until they all come back
+            for mut dev in devices.drain(..) {
+                let _ = dev.ungrab();
+            }
+            layer_state = LayerState::default();
+            if autoscroll_active {
+                autoscroll_active = false;
+                if let Some(ref sender) = overlay_sender {
+                    let _ = sender.send(OverlayCommand::Hide);
+                }
+            }
+            info!("Released source devices, waiting for them to reappear");
+            on_state(RemapState::Waiting);
+            continue;
+        }
         
         if !had_events {
             thread::sleep(Duration::from_millis(5));
@@ -477,8 +811,113 @@ fn run_remapper_loop(stop: Arc<AtomicBool>, ext_config: RemapConfigExt) -> Resul
     Ok(())
 }
 
+/// Targets of the base mappings and of every layer
+fn all_targets<'a>(
+    mappings: &'a BTreeMap<u16, MappingTarget>,
+    layers: &'a [Layer],
+) -> impl Iterator<Item = &'a MappingTarget> {
+    mappings.values().chain(layers.iter().flat_map(|layer| layer.mappings.values()))
+}
+
+/// Key codes the targets emit, modifiers included. Scroll codes are REL
+/// events and macro codes emit nothing, so neither is a key.
+fn target_key_codes<'a>(targets: impl Iterator<Item = &'a MappingTarget>) -> impl Iterator<Item = u16> {
+    targets
+        .filter(|target| target.base != 280 && target.base != 281 && !(1000..2000).contains(&target.base))
+        .flat_map(|target| std::iter::once(target.base).chain(target.mods.to_key_codes()))
+}
+
+/// Keys the virtual device advertises for mapping targets: every keyboard
+/// key and mouse button, plus the initial targets
+fn emittable_keys(config: &RemapConfig) -> BTreeSet<u16> {
+    KEYBOARD_KEYS
+        .chain(MOUSE_BUTTONS)
+        .chain(target_key_codes(all_targets(&config.mappings, &config.layers)))
+        .collect()
+}
+
+/// Remove mappings whose target emits a key not in `keys`, returning their
+/// source codes
+fn retain_emittable(mappings: &mut BTreeMap<u16, MappingTarget>, keys: &BTreeSet<u16>) -> Vec<u16> {
+    let mut dropped = Vec::new();
+    mappings.retain(|&source, target| {
+        let emittable = target_key_codes(std::iter::once(&*target)).all(|code| keys.contains(&code));
+        if !emittable {
+            dropped.push(source);
+        }
+        emittable
+    });
+    dropped
+}
+
+/// Create the uinput device remapped events are emitted through
+fn build_virtual_device(
+    keys: &AttributeSet<Key>,
+    rel: &AttributeSet<evdev::RelativeAxisType>,
+) -> Result<VirtualDevice> {
+    let mut vbuilder = VirtualDeviceBuilder::new().context("Failed to create uinput builder")?;
+    vbuilder = vbuilder.name(&VIRTUAL_DEVICE_NAME);
+
+    vbuilder = vbuilder
+        .with_keys(keys)
+        .context("Failed to set key capabilities")?;
+    
+    // Add relative axes if any were found (for scroll wheel, mouse movement)
+    if rel.iter().next().is_some() {
+        info!("Virtual device will have relative axes (scroll wheel, mouse movement)");
+        vbuilder = vbuilder
+            .with_relative_axes(rel)
+            .context("Failed to set relative axis capabilities")?;
+    } else {
+        warn!("No relative axes found - scroll wheel may not work!");
+    }
+
+    vbuilder.build().context("Failed to build uinput device")
+}
+
+/// Open the source interfaces and grab them so their original events don't
+/// reach the system. Grabs already taken are released (by dropping the
+/// devices) if a later interface fails.
+fn grab_source_devices(paths: &[PathBuf]) -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    for source_path in paths {
+        let mut dev = Device::open(source_path)
+            .with_context(|| format!("Failed to open evdev device: {source_path:?}"))?;
+
+        set_nonblocking(&dev).context("Failed to set evdev device non-blocking")?;
+
+        dev.grab().with_context(|| format!("Failed to grab evdev device: {source_path:?}"))?;
+        
+        info!("Grabbed device: {:?}", source_path);
+        devices.push(dev);
+    }
+    Ok(devices)
+}
+
+/// Whether a read error means the evdev node is gone (unplug, USB reset)
+fn is_device_gone(e: &std::io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENODEV)
+}
+
+/// Grab the source interfaces of a reattached device, None if they are not
+/// there or not accessible yet
+fn regrab_source_devices(config: &RemapConfig) -> Option<Vec<Device>> {
+    let paths = select_all_razer_keyboard_devices(&config.source_device, &config.nodes);
+    if paths.is_empty() {
+        return None;
+    }
+    // The nodes may not be accessible yet while udev applies permissions
+    match grab_source_devices(&paths) {
+        Ok(devices) => Some(devices),
+        Err(e) => {
+            debug!("Source devices not ready yet: {e:#}");
+            None
+        }
+    }
+}
+
 fn remap_events(
-    mappings: &BTreeMap<u16, MappingTarget>,
+    target: Option<&MappingTarget>,
     ev: InputEvent,
     macros: &std::collections::HashMap<u32, crate::profile::Macro>,
 ) -> Option<Vec<InputEvent>> {
@@ -494,7 +933,7 @@ fn remap_events(
         InputEventKind::Key(key) => {
             let src_code: u16 = key.code();
             let value = ev.value();
-            if let Some(target) = mappings.get(&src_code) {
+            if let Some(target) = target {
                 info!("REMAP: code {} -> {} (value={})", src_code, target.base, value);
                 let mut out: Vec<InputEvent> = Vec::new();
 
@@ -910,7 +1349,8 @@ pub fn capture_key_for_macro(timeout: Duration) -> Result<(u16, bool)> {
 /// Select ALL Razer keyboard interfaces for grabbing.
 /// The Naga Trinity sends side button keys through multiple interfaces (event9 AND event11),
 /// so we need to grab all of them to properly intercept the keys.
-fn select_all_razer_keyboard_devices(preferred_device: &Option<String>) -> Vec<PathBuf> {
+/// With scoped `nodes`, only that device's interfaces and DPI virtual device are used.
+fn select_all_razer_keyboard_devices(preferred_device: &Option<String>, nodes: &DeviceNodes) -> Vec<PathBuf> {
     // If a preferred device is specified, only use that one
     if let Some(p) = preferred_device {
         let path = PathBuf::from(p);
@@ -920,6 +1360,7 @@ fn select_all_razer_keyboard_devices(preferred_device: &Option<String>) -> Vec<P
     }
 
     let mut razer_devices: Vec<PathBuf> = Vec::new();
+    let dpi_device_name = crate::hidpoll::virtual_device_name(nodes);
     
     info!("Scanning for ALL Razer interfaces to grab (keyboard + mouse + DPI)...");
     
@@ -928,11 +1369,23 @@ fn select_all_razer_keyboard_devices(preferred_device: &Option<String>) -> Vec<P
         let name_lower = name.to_ascii_lowercase();
 
         let is_razer = name_lower.contains("razer") || name_lower.contains("naga");
-        let is_dpi_device = name.contains("RazerLinux DPI");
+        // Never grab our own output (another remapper's, or this one's when
+        // its devices are grabbed again)
+        if name == VIRTUAL_DEVICE_NAME {
+            continue;
+        }
+        let is_dpi_device = if nodes.hidraw.is_empty() {
+            name.contains("RazerLinux DPI")
+        } else {
+            name == dpi_device_name
+        };
         
         if !is_razer && !is_dpi_device {
             continue;
         }
+        if !is_dpi_device && !nodes.input.is_empty() && !nodes.input.contains(&path) {
+            continue;
+        }
         
         let keys = dev.supported_keys();
         
@@ -971,11 +1424,13 @@ fn select_all_razer_keyboard_devices(preferred_device: &Option<String>) -> Vec<P
         }
     }
     
-    if razer_devices.is_empty() {
-        // Fall back to single device selection if no interfaces found
-        if let Some(p) = select_source_device(&None) {
-            return vec![p];
-        }
+    // Fall back to single device selection if no interfaces found, unless that
+    // could pick another device's interface
+    if razer_devices.is_empty()
+        && nodes.input.is_empty()
+        && let Some(p) = select_source_device(&None)
+    {
+        return vec![p];
     }
     
     razer_devices
@@ -1127,3 +1582,128 @@ fn set_nonblocking(dev: &Device) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_device_gone() {
+        assert!(is_device_gone(&std::io::Error::from_raw_os_error(libc::ENODEV)));
+        assert!(!is_device_gone(&std::io::Error::from_raw_os_error(libc::EAGAIN)));
+        assert!(!is_device_gone(&std::io::Error::from(std::io::ErrorKind::WouldBlock)));
+    }
+
+    #[test]
+    fn test_target_key_codes() {
+        let mut mappings = BTreeMap::new();
+        let target = |base, ctrl| MappingTarget {
+            base,
+            mods: Modifiers { ctrl, ..Default::default() },
+        };
+        mappings.insert(2, target(30, true)); // Ctrl+A
+        mappings.insert(3, target(280, false)); // scroll up
+        mappings.insert(4, target(1001, false)); // macro 1
+        mappings.insert(5, target(275, false)); // BTN_SIDE
+
+        let codes: Vec<u16> = target_key_codes(mappings.values()).collect();
+        assert_eq!(codes, vec![30, 29, 275]);
+    }
+
+    fn key(base: u16) -> MappingTarget {
+        MappingTarget { base, mods: Modifiers::default() }
+    }
+
+    fn layer(layer_key: u16, mode: LayerMode, source: u16, target: u16) -> Layer {
+        Layer {
+            key: layer_key,
+            mode,
+            mappings: BTreeMap::from([(source, key(target))]),
+        }
+    }
+
+    /// Emitted code for each (source, value) event; None for dropped events
+    fn resolve_all(
+        state: &mut LayerState,
+        mappings: &BTreeMap<u16, MappingTarget>,
+        layers: &[Layer],
+        events: &[(u16, i32)],
+    ) -> Vec<Option<u16>> {
+        events
+            .iter()
+            .map(|&(code, value)| {
+                state
+                    .resolve(mappings, layers, code, value)
+                    .map(|target| target.map_or(code, |target| target.base))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hold_layer() {
+        let mappings = BTreeMap::from([(2, key(30))]);
+        let layers = [layer(13, LayerMode::Hold, 2, 46)];
+        let mut state = LayerState::default();
+
+        let out = resolve_all(&mut state, &mappings, &layers, &[(2, 1), (2, 0), (13, 1), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![Some(30), Some(30), None, Some(46), Some(46)]);
+        // Buttons the layer doesn't map pass through as before
+        let out = resolve_all(&mut state, &mappings, &layers, &[(3, 1), (3, 0), (13, 0), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![Some(3), Some(3), None, Some(30), Some(30)]);
+    }
+
+    #[test]
+    fn test_release_keeps_press_target() {
+        let mappings = BTreeMap::from([(2, key(30))]);
+        let layers = [layer(13, LayerMode::Hold, 2, 46)];
+        let mut state = LayerState::default();
+
+        // The layer key goes up before the button, which still repeats and releases its layer target
+        let out = resolve_all(&mut state, &mappings, &layers, &[(13, 1), (2, 1), (13, 0), (2, 2), (2, 0)]);
+        assert_eq!(out, vec![None, Some(46), None, Some(46), Some(46)]);
+    }
+
+    #[test]
+    fn test_toggle_and_one_shot_layers() {
+        let mappings = BTreeMap::new();
+        let layers = [layer(13, LayerMode::Toggle, 2, 46), layer(12, LayerMode::OneShot, 2, 47)];
+        let mut state = LayerState::default();
+
+        let out = resolve_all(&mut state, &mappings, &layers, &[(13, 1), (13, 0), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![None, None, Some(46), Some(46)]);
+        let out = resolve_all(&mut state, &mappings, &layers, &[(13, 1), (13, 0), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![None, None, Some(2), Some(2)]);
+
+        // A one-shot layer applies to the next press only, over a toggled layer
+        let out = resolve_all(&mut state, &mappings, &layers, &[(13, 1), (12, 1), (12, 0), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![None, None, None, Some(47), Some(47)]);
+        let out = resolve_all(&mut state, &mappings, &layers, &[(2, 1), (2, 0)]);
+        assert_eq!(out, vec![Some(46), Some(46)]);
+    }
+
+    #[test]
+    fn test_update_releases_held_keys() {
+        let mut state = LayerState::default();
+        let out = resolve_all(&mut state, &BTreeMap::from([(2, key(30))]), &[], &[(2, 1), (3, 1)]);
+        assert_eq!(out, vec![Some(30), Some(3)]);
+
+        // The old target is released once; the key's repeats and release are dropped
+        let released = state.take_held_targets();
+        assert_eq!(released.iter().map(|(code, target)| (*code, target.base)).collect::<Vec<_>>(), vec![(2, 30)]);
+        let mappings = BTreeMap::from([(2, key(46))]);
+        let out = resolve_all(&mut state, &mappings, &[], &[(2, 2), (2, 0), (2, 1), (2, 0)]);
+        assert_eq!(out, vec![None, None, Some(46), Some(46)]);
+        // A passed-through key is still released as itself
+        let out = resolve_all(&mut state, &mappings, &[], &[(3, 0)]);
+        assert_eq!(out, vec![Some(3)]);
+    }
+
+    #[test]
+    fn test_retain_emittable() {
+        let keys = BTreeSet::from([30]);
+        let mut mappings = BTreeMap::from([(2, key(30)), (3, key(300)), (4, key(1001))]);
+
+        assert_eq!(retain_emittable(&mut mappings, &keys), vec![3]);
+        assert_eq!(mappings.keys().copied().collect::<Vec<_>>(), vec![2, 4]);
+    }
+}
@@ -2,8 +2,10 @@
 //!
 //! Handles autostart configuration and default profile settings.
 
+use crate::focus::FocusedWindow;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use tracing::{info, warn};
@@ -23,22 +25,77 @@ pub struct AppSettings {
     #[serde(default)]
     pub minimize_to_tray: bool,
     
-    /// Show DPI change notifications (future feature)
-    #[serde(default)]
+    /// Show a desktop notification when the DPI buttons change the DPI stage
+    /// (defaults to on)
+    #[serde(default = "default_true")]
     pub show_dpi_notifications: bool,
+
+    /// Profile last loaded on each device, keyed by serial number (or hidraw
+    /// path for devices without one). Devices not listed use `default_profile`.
+    #[serde(default)]
+    pub device_profiles: BTreeMap<String, String>,
+
+    /// Profiles to switch to while an application has the focus, first
+    /// matching rule wins (`[[app_profiles]]` tables)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_profiles: Vec<AppProfileRule>,
+}
+
+/// Rule mapping a focused application to a profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppProfileRule {
+    /// WM_CLASS instance or class name, case-insensitive (e.g. "code", "firefox")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Process name as in /proc/<pid>/comm, from the window's _NET_WM_PID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// Serial number (or hidraw path) of the device the rule is for; all devices if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Profile to load
+    pub profile: String,
+}
+
+impl AppProfileRule {
+    /// Whether the rule applies to the window on the device with the given
+    /// key. Both `class` and `process` must match when both are set; a rule
+    /// with neither never matches.
+    pub fn matches(&self, window: &FocusedWindow, device_key: &str) -> bool {
+        if self.device.as_deref().is_some_and(|device| device != device_key) {
+            return false;
+        }
+        if self.class.is_none() && self.process.is_none() {
+            return false;
+        }
+        let class_matches = self.class.as_deref().is_none_or(|class| {
+            class.eq_ignore_ascii_case(&window.instance) || class.eq_ignore_ascii_case(&window.class)
+        });
+        let process_matches = self
+            .process
+            .as_deref()
+            .is_none_or(|process| window.process.as_deref() == Some(process));
+        class_matches && process_matches
+    }
 }
 
 fn default_profile_name() -> String {
     "Default".to_string()
 }
 
+fn default_true() -> bool {
+    true
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
             autostart: false,
             default_profile: "Default".to_string(),
             minimize_to_tray: false,
-            show_dpi_notifications: false,
+            show_dpi_notifications: true,
+            device_profiles: BTreeMap::new(),
+            app_profiles: Vec::new(),
         }
     }
 }
@@ -100,12 +157,45 @@ impl AppSettings {
         self.save()
     }
     
+    /// Profile to load on the device with the given key
+    pub fn profile_for_device(&self, key: &str) -> &str {
+        self.device_profiles
+            .get(key)
+            .map(String::as_str)
+            .unwrap_or(&self.default_profile)
+    }
+
+    /// Profile for the device with the given key while `window` has the
+    /// focus: the first matching `app_profiles` rule, else the device's
+    /// startup profile
+    pub fn profile_for_window(&self, window: &FocusedWindow, key: &str) -> &str {
+        self.app_profiles
+            .iter()
+            .find(|rule| rule.matches(window, key))
+            .map(|rule| rule.profile.as_str())
+            .unwrap_or_else(|| self.profile_for_device(key))
+    }
+
+    /// Remember the profile loaded on a device
+    pub fn set_device_profile(&mut self, key: &str, profile: &str) -> Result<()> {
+        self.device_profiles.insert(key.to_string(), profile.to_string());
+        info!("Profile for device {} set to: '{}'", key, profile);
+        self.save()
+    }
+    
     /// Set minimize to tray on close
     pub fn set_minimize_to_tray(&mut self, enabled: bool) -> Result<()> {
         self.minimize_to_tray = enabled;
         info!("Minimize to tray on close: {}", enabled);
         self.save()
     }
+
+    /// Enable or disable DPI change notifications
+    pub fn set_show_dpi_notifications(&mut self, enabled: bool) -> Result<()> {
+        self.show_dpi_notifications = enabled;
+        info!("DPI change notifications: {}", enabled);
+        self.save()
+    }
 }
 
 /// Get the autostart desktop file path
@@ -330,6 +420,7 @@ mod tests {
         let settings = AppSettings::default();
         assert!(!settings.autostart);
         assert_eq!(settings.default_profile, "Default");
+        assert!(settings.show_dpi_notifications);
     }
     
     #[test]
@@ -352,5 +443,58 @@ default_profile = "work"
         let settings: AppSettings = toml::from_str(toml).unwrap();
         assert!(settings.autostart);
         assert_eq!(settings.default_profile, "work");
+        assert!(settings.show_dpi_notifications);
+    }
+
+    #[test]
+    fn test_device_profiles() {
+        let toml = r#"
+default_profile = "work"
+
+[device_profiles]
+PM1234 = "mmo"
+"#;
+        let settings: AppSettings = toml::from_str(toml).unwrap();
+        assert_eq!(settings.profile_for_device("PM1234"), "mmo");
+        assert_eq!(settings.profile_for_device("/dev/hidraw5"), "work");
+
+        let toml = toml::to_string_pretty(&settings).unwrap();
+        let parsed: AppSettings = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.device_profiles, settings.device_profiles);
+    }
+
+    #[test]
+    fn test_app_profiles() {
+        let toml = r#"
+default_profile = "work"
+
+[[app_profiles]]
+class = "Code"
+profile = "ide"
+
+[[app_profiles]]
+process = "cs2"
+device = "PM1234"
+profile = "fps"
+
+[[app_profiles]]
+profile = "never"
+"#;
+        let settings: AppSettings = toml::from_str(toml).unwrap();
+        let window = |instance: &str, class: &str, process: Option<&str>| FocusedWindow {
+            instance: instance.into(),
+            class: class.into(),
+            process: process.map(String::from),
+        };
+
+        assert_eq!(settings.profile_for_window(&window("code", "code", Some("code")), "PM1234"), "ide");
+        assert_eq!(settings.profile_for_window(&window("cs2", "cs2", Some("cs2")), "PM1234"), "fps");
+        // The fps rule is for one device only
+        assert_eq!(settings.profile_for_window(&window("cs2", "cs2", Some("cs2")), "PM9999"), "work");
+        assert_eq!(settings.profile_for_window(&window("navigator", "firefox", None), "PM1234"), "work");
+
+        let toml = toml::to_string_pretty(&settings).unwrap();
+        let parsed: AppSettings = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.app_profiles, settings.app_profiles);
     }
 }
@@ -26,6 +26,11 @@ pub enum IpcCommand {
     Quit,
     Ping,
     Pong,
+    /// Switch the software lighting effect ("off" or a preset name)
+    Lighting(String),
+    /// Battery status of a wireless mouse and its low-battery threshold in
+    /// percent, shown in the tray (main app -> helper)
+    Battery { level: u8, charging: bool, threshold: u8 },
 }
 
 impl IpcCommand {
@@ -35,10 +40,27 @@ impl IpcCommand {
             IpcCommand::Quit => "QUIT".to_string(),
             IpcCommand::Ping => "PING".to_string(),
             IpcCommand::Pong => "PONG".to_string(),
+            IpcCommand::Lighting(preset) => format!("LIGHTING {}", preset),
+            IpcCommand::Battery { level, charging, threshold } => {
+                format!("BATTERY {} {} {}", level, *charging as u8, threshold)
+            }
         }
     }
 
     pub fn from_str(s: &str) -> Option<Self> {
+        if let Some(preset) = s.trim().strip_prefix("LIGHTING ") {
+            return Some(IpcCommand::Lighting(preset.trim().to_string()));
+        }
+        if let Some(args) = s.trim().strip_prefix("BATTERY ") {
+            let mut parts = args.split_whitespace();
+            let level = parts.next()?.parse().ok()?;
+            let charging = parts.next()? == "1";
+            let threshold = parts
+                .next()
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(crate::battery::LOW_BATTERY_PERCENT);
+            return Some(IpcCommand::Battery { level, charging, threshold });
+        }
         match s.trim() {
             "SHOW" => Some(IpcCommand::ShowWindow),
             "QUIT" => Some(IpcCommand::Quit),
@@ -51,7 +73,7 @@ impl IpcCommand {
 
 /// Run the tray helper (called when --tray-helper flag is passed)
 pub fn run_tray_helper() -> anyhow::Result<()> {
-    use ksni::menu::{MenuItem, StandardItem};
+    use ksni::menu::{MenuItem, StandardItem, SubMenu};
 
     let socket_path = socket_path();
     
@@ -77,6 +99,8 @@ pub fn run_tray_helper() -> anyhow::Result<()> {
     // Create tray icon
     struct TrayHelper {
         cmd_tx: std::sync::mpsc::Sender<IpcCommand>,
+        /// Last battery status reported by the main app, with its low-battery threshold
+        battery: Option<(crate::battery::BatteryStatus, u8)>,
     }
     
     impl ksni::Tray for TrayHelper {
@@ -85,7 +109,27 @@ pub fn run_tray_helper() -> anyhow::Result<()> {
         }
         
         fn icon_name(&self) -> String {
-            "input-mouse".to_string()
+            match self.battery {
+                Some((status, _)) if status.charging => "battery-good-charging".to_string(),
+                Some((status, threshold)) if status.is_low(threshold) => "battery-caution".to_string(),
+                _ => "input-mouse".to_string(),
+            }
+        }
+        
+        fn tool_tip(&self) -> ksni::ToolTip {
+            let description = match self.battery {
+                Some((status, _)) if status.charging => format!("Battery: {}% (charging)", status.level),
+                Some((status, threshold)) if status.is_low(threshold) => {
+                    format!("Battery low: {}%", status.level)
+                }
+                Some((status, _)) => format!("Battery: {}%", status.level),
+                None => String::new(),
+            };
+            ksni::ToolTip {
+                title: "RazerLinux".to_string(),
+                description,
+                ..Default::default()
+            }
         }
         
         fn id(&self) -> String {
@@ -113,6 +157,27 @@ pub fn run_tray_helper() -> anyhow::Result<()> {
                     }),
                     ..Default::default()
                 }),
+                MenuItem::SubMenu(SubMenu {
+                    label: "Lighting".to_string(),
+                    submenu: [
+                        ("Off", "off"),
+                        ("Gradient", "gradient"),
+                        ("Breathing", "breathing"),
+                        ("DPI Colors", "dpi"),
+                    ]
+                    .into_iter()
+                    .map(|(label, preset)| {
+                        MenuItem::Standard(StandardItem {
+                            label: label.to_string(),
+                            activate: Box::new(move |this: &mut Self| {
+                                let _ = this.cmd_tx.send(IpcCommand::Lighting(preset.to_string()));
+                            }),
+                            ..Default::default()
+                        })
+                    })
+                    .collect(),
+                    ..Default::default()
+                }),
                 MenuItem::Separator,
                 MenuItem::Standard(StandardItem {
                     label: "Quit".to_string(),
@@ -125,8 +190,9 @@ pub fn run_tray_helper() -> anyhow::Result<()> {
         }
     }
     
-    let tray = TrayHelper { cmd_tx: cmd_tx.clone() };
+    let tray = TrayHelper { cmd_tx: cmd_tx.clone(), battery: None };
     let service = ksni::TrayService::new(tray);
+    let tray_handle = service.handle();
     service.spawn();
     
     println!("Tray icon created");
@@ -154,8 +220,13 @@ pub fn run_tray_helper() -> anyhow::Result<()> {
                     Ok(n) if n > 0 => {
                         if let Some(cmd) = IpcCommand::from_str(&line) {
                             println!("Received command from main app: {:?}", cmd);
-                            if cmd == IpcCommand::Quit {
-                                running_accept.store(false, Ordering::Relaxed);
+                            match cmd {
+                                IpcCommand::Quit => running_accept.store(false, Ordering::Relaxed),
+                                IpcCommand::Battery { level, charging, threshold } => {
+                                    let status = crate::battery::BatteryStatus { level, charging };
+                                    tray_handle.update(|tray| tray.battery = Some((status, threshold)));
+                                }
+                                _ => {}
                             }
                         }
                     }
@@ -268,6 +339,15 @@ impl TrayClient {
         None
     }
     
+    /// Send a command to the tray helper
+    pub fn send(&mut self, cmd: &IpcCommand) {
+        if let Some(ref mut stream) = self.stream {
+            let msg = format!("{}\n", cmd.to_string());
+            let _ = stream.write_all(msg.as_bytes());
+            let _ = stream.flush();
+        }
+    }
+
     /// Send quit command to tray helper
     pub fn quit(&mut self) {
         if let Some(ref mut stream) = self.stream {